@@ -0,0 +1,158 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::cell::Cell;
+
+use adw::subclass::prelude::*;
+use glib::clone;
+use gtk::{gdk, gio, glib, prelude::*, CompositeTemplate};
+
+use crate::{cover_picture::CoverPicture, i18n::i18n};
+
+mod imp {
+    use glib::subclass::Signal;
+    use once_cell::sync::Lazy;
+
+    use super::*;
+
+    #[derive(Debug, Default, CompositeTemplate)]
+    #[template(resource = "/com/axos-project/Axuralis/album-header.ui")]
+    pub struct AlbumHeader {
+        // Template widgets
+        #[template_child]
+        pub cover_stack: TemplateChild<gtk::Stack>,
+        #[template_child]
+        pub cover_image: TemplateChild<CoverPicture>,
+        #[template_child]
+        pub album_label: TemplateChild<gtk::Inscription>,
+        #[template_child]
+        pub details_label: TemplateChild<gtk::Inscription>,
+        #[template_child]
+        pub collapse_button: TemplateChild<gtk::Button>,
+
+        pub collapsed: Cell<bool>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for AlbumHeader {
+        const NAME: &'static str = "AxuralisAlbumHeader";
+        type Type = super::AlbumHeader;
+        type ParentType = gtk::Widget;
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+
+            klass.set_layout_manager_type::<gtk::BoxLayout>();
+            klass.set_css_name("albumheader");
+            klass.set_accessible_role(gtk::AccessibleRole::Group);
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for AlbumHeader {
+        fn dispose(&self) {
+            while let Some(child) = self.obj().first_child() {
+                child.unparent();
+            }
+        }
+
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            self.obj().init_widgets();
+        }
+
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+                vec![
+                    Signal::builder("play").build(),
+                    Signal::builder("toggle-collapsed").build(),
+                ]
+            });
+
+            SIGNALS.as_ref()
+        }
+    }
+
+    impl WidgetImpl for AlbumHeader {}
+}
+
+glib::wrapper! {
+    pub struct AlbumHeader(ObjectSubclass<imp::AlbumHeader>)
+        @extends gtk::Widget,
+        @implements gio::ActionGroup, gio::ActionMap;
+}
+
+impl Default for AlbumHeader {
+    fn default() -> Self {
+        glib::Object::new()
+    }
+}
+
+impl AlbumHeader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn init_widgets(&self) {
+        // Clicking anywhere on the header plays the album; the collapse
+        // button claims its own clicks
+        let gesture = gtk::GestureClick::new();
+        gesture.set_button(gdk::BUTTON_PRIMARY);
+        gesture.connect_released(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |gesture, n_press, _, _| {
+                if n_press == 1 {
+                    gesture.set_state(gtk::EventSequenceState::Claimed);
+                    this.emit_by_name::<()>("play", &[]);
+                }
+            }
+        ));
+        self.add_controller(gesture);
+
+        self.imp().collapse_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.emit_by_name::<()>("toggle-collapsed", &[]);
+            }
+        ));
+    }
+
+    pub fn set_album(&self, album: &str) {
+        self.imp().album_label.set_text(Some(album));
+    }
+
+    pub fn set_details(&self, details: &str) {
+        self.imp().details_label.set_text(Some(details));
+    }
+
+    pub fn set_cover(&self, cover: Option<gdk::Texture>) {
+        let imp = self.imp();
+        if let Some(texture) = cover {
+            imp.cover_image.set_cover(Some(&texture));
+            imp.cover_stack.set_visible_child_name("cover");
+        } else {
+            imp.cover_image.set_cover(None);
+            imp.cover_stack.set_visible_child_name("no-cover");
+        }
+    }
+
+    pub fn set_collapsed(&self, collapsed: bool) {
+        let imp = self.imp();
+        imp.collapsed.set(collapsed);
+        if collapsed {
+            imp.collapse_button.set_icon_name("pan-end-symbolic");
+            imp.collapse_button
+                .set_tooltip_text(Some(&i18n("Expand the Album")));
+        } else {
+            imp.collapse_button.set_icon_name("pan-down-symbolic");
+            imp.collapse_button
+                .set_tooltip_text(Some(&i18n("Collapse the Album")));
+        }
+    }
+}
@@ -0,0 +1,322 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::cell::RefCell;
+
+use glib::clone;
+use gtk::{gio, glib, prelude::*, subclass::prelude::*};
+
+use crate::audio::Song;
+
+mod imp {
+    use glib::{ParamSpec, ParamSpecObject, Value};
+    use once_cell::sync::Lazy;
+
+    use super::*;
+
+    // Splits the songs of a model into sections, one for each run of
+    // consecutive songs from the same album. Collapsed sections only keep
+    // their first song, so that their header remains in the list
+    #[derive(Debug, Default)]
+    pub struct AlbumSectionModel {
+        pub model: RefCell<Option<gio::ListModel>>,
+        pub items_changed_id: RefCell<Option<glib::SignalHandlerId>>,
+        // The collapsed sections, as sorted ranges of the wrapped model
+        pub collapsed: RefCell<Vec<(u32, u32)>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for AlbumSectionModel {
+        const NAME: &'static str = "AxuralisAlbumSectionModel";
+        type Type = super::AlbumSectionModel;
+        type Interfaces = (gio::ListModel, gtk::SectionModel);
+    }
+
+    impl ObjectImpl for AlbumSectionModel {
+        fn properties() -> &'static [ParamSpec] {
+            static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| {
+                vec![ParamSpecObject::builder::<gio::ListModel>("model")
+                    .explicit_notify()
+                    .build()]
+            });
+
+            PROPERTIES.as_ref()
+        }
+
+        fn property(&self, _id: usize, pspec: &ParamSpec) -> Value {
+            match pspec.name() {
+                "model" => self.model.borrow().to_value(),
+                _ => unimplemented!(),
+            }
+        }
+
+        fn set_property(&self, _id: usize, value: &Value, pspec: &ParamSpec) {
+            match pspec.name() {
+                "model" => self
+                    .obj()
+                    .set_model(value.get::<gio::ListModel>().ok().as_ref()),
+                _ => unimplemented!(),
+            };
+        }
+    }
+
+    impl ListModelImpl for AlbumSectionModel {
+        fn item_type(&self) -> glib::Type {
+            Song::static_type()
+        }
+
+        fn n_items(&self) -> u32 {
+            if let Some(ref model) = *self.model.borrow() {
+                let hidden: u32 = self.collapsed.borrow().iter().map(|(s, e)| e - s - 1).sum();
+                return model.n_items() - hidden;
+            }
+
+            0
+        }
+
+        fn item(&self, position: u32) -> Option<glib::Object> {
+            if let Some(ref model) = *self.model.borrow() {
+                return model.item(self.obj().model_position(position));
+            }
+
+            None
+        }
+    }
+
+    impl SectionModelImpl for AlbumSectionModel {
+        fn section(&self, position: u32) -> (u32, u32) {
+            let obj = self.obj();
+            let n_items = self.n_items();
+            if position >= n_items {
+                return (n_items, u32::MAX);
+            }
+
+            let (start, end) = obj.model_section(obj.model_position(position));
+            (obj.visible_position(start), obj.visible_position(end))
+        }
+    }
+}
+
+glib::wrapper! {
+    pub struct AlbumSectionModel(ObjectSubclass<imp::AlbumSectionModel>)
+        @implements gio::ListModel, gtk::SectionModel;
+}
+
+impl Default for AlbumSectionModel {
+    fn default() -> Self {
+        Self::new(gio::ListModel::NONE)
+    }
+}
+
+impl AlbumSectionModel {
+    pub fn new(model: Option<&impl IsA<gio::ListModel>>) -> Self {
+        glib::Object::builder::<Self>()
+            .property("model", model.map(|m| m.as_ref()))
+            .build()
+    }
+
+    pub fn set_model(&self, model: Option<&gio::ListModel>) {
+        let imp = self.imp();
+        let n_removed = self.n_items();
+        imp.collapsed.take();
+
+        // Stop listening to the model we are replacing
+        if let Some(old_model) = imp.model.take() {
+            if let Some(id) = imp.items_changed_id.take() {
+                old_model.disconnect(id);
+            }
+        }
+
+        if let Some(model) = model {
+            imp.model.replace(Some(model.clone()));
+            let id = model.connect_items_changed(clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_, position, removed, added| {
+                    this.model_items_changed(position, removed, added);
+                }
+            ));
+            imp.items_changed_id.replace(Some(id));
+        }
+
+        let n_added = self.n_items();
+        if n_removed > 0 || n_added > 0 {
+            self.items_changed(0, n_removed, n_added);
+        }
+
+        self.notify("model");
+    }
+
+    fn album_key_at(&self, position: u32) -> Option<String> {
+        self.imp()
+            .model
+            .borrow()
+            .as_ref()
+            .and_then(|m| m.item(position))
+            .and_downcast::<Song>()
+            .map(|s| s.album_key())
+    }
+
+    // The section around `position`, in positions of the wrapped model
+    fn model_section(&self, position: u32) -> (u32, u32) {
+        let n_items = self
+            .imp()
+            .model
+            .borrow()
+            .as_ref()
+            .map_or(0, |m| m.n_items());
+
+        let key = self.album_key_at(position);
+        let mut start = position;
+        while start > 0 && self.album_key_at(start - 1) == key {
+            start -= 1;
+        }
+        let mut end = position + 1;
+        while end < n_items && self.album_key_at(end) == key {
+            end += 1;
+        }
+
+        (start, end)
+    }
+
+    // Maps a position of this model to the wrapped model
+    fn model_position(&self, position: u32) -> u32 {
+        let mut res = position;
+        for (start, end) in self.imp().collapsed.borrow().iter() {
+            if *start >= res {
+                break;
+            }
+            res += end - start - 1;
+        }
+
+        res
+    }
+
+    // Maps a position of the wrapped model that is not hidden inside a
+    // collapsed section to this model
+    fn visible_position(&self, position: u32) -> u32 {
+        let mut hidden = 0;
+        for (start, end) in self.imp().collapsed.borrow().iter() {
+            if *start >= position {
+                break;
+            }
+            hidden += end.min(&position) - start - 1;
+        }
+
+        position - hidden
+    }
+
+    fn model_items_changed(&self, position: u32, removed: u32, added: u32) {
+        let imp = self.imp();
+        let changed_end = position + removed;
+
+        // The change grows to cover the collapsed sections it touches, as
+        // they may have gained or lost songs
+        let touched: Vec<(u32, u32)> = imp
+            .collapsed
+            .borrow()
+            .iter()
+            .filter(|(s, e)| *s <= changed_end && *e >= position)
+            .copied()
+            .collect();
+        let start = touched.iter().map(|r| r.0).fold(position, u32::min);
+        let old_end = touched.iter().map(|r| r.1).fold(changed_end, u32::max);
+        let visible_start = self.visible_position(start);
+        let old_visible_end = self.visible_position(old_end);
+
+        {
+            let mut collapsed = imp.collapsed.borrow_mut();
+            collapsed.retain(|r| !touched.contains(r));
+            for range in collapsed.iter_mut() {
+                if range.0 > changed_end {
+                    range.0 = range.0 - removed + added;
+                    range.1 = range.1 - removed + added;
+                }
+            }
+        }
+
+        // Touched sections stay collapsed only if none of their songs
+        // changed, and they did not merge with the new songs
+        for (s, e) in touched {
+            let range = if e <= position {
+                (s, e)
+            } else if s >= changed_end {
+                (s - removed + added, e - removed + added)
+            } else {
+                continue;
+            };
+
+            if self.model_section(range.0) == range {
+                let mut collapsed = imp.collapsed.borrow_mut();
+                if let Err(idx) = collapsed.binary_search(&range) {
+                    collapsed.insert(idx, range);
+                }
+            }
+        }
+
+        let new_visible_end = self.visible_position(old_end - removed + added);
+        self.items_changed(
+            visible_start,
+            old_visible_end - visible_start,
+            new_visible_end - visible_start,
+        );
+    }
+
+    pub fn expand_all(&self) {
+        let n_removed = self.n_items();
+        if !self.imp().collapsed.take().is_empty() {
+            self.items_changed(0, n_removed, self.n_items());
+        }
+    }
+
+    // Whether the section starting at `position` is collapsed
+    pub fn is_collapsed(&self, position: u32) -> bool {
+        let start = self.model_position(position);
+        self.imp()
+            .collapsed
+            .borrow()
+            .iter()
+            .any(|(s, _)| *s == start)
+    }
+
+    // All the songs of the section around `position`, even those of a
+    // collapsed section
+    pub fn section_songs(&self, position: u32) -> Vec<Song> {
+        let (start, end) = self.model_section(self.model_position(position));
+        let model = self.imp().model.borrow();
+        (start..end)
+            .filter_map(|i| model.as_ref()?.item(i).and_downcast::<Song>())
+            .collect()
+    }
+
+    // Collapsing a section takes all of its songs but the first one out
+    // of the model
+    pub fn toggle_collapsed(&self, position: u32) {
+        if position >= self.n_items() {
+            return;
+        }
+
+        let (start, end) = self.model_section(self.model_position(position));
+        let visible_start = self.visible_position(start);
+        let collapsed = {
+            let mut collapsed = self.imp().collapsed.borrow_mut();
+            match collapsed.binary_search(&(start, end)) {
+                Ok(idx) => {
+                    collapsed.remove(idx);
+                    false
+                }
+                Err(idx) => {
+                    collapsed.insert(idx, (start, end));
+                    true
+                }
+            }
+        };
+
+        let n_songs = end - start;
+        if collapsed {
+            self.items_changed(visible_start, n_songs, 1);
+        } else {
+            self.items_changed(visible_start, 1, n_songs);
+        }
+    }
+}
@@ -13,9 +13,12 @@ use gtk::{gio, glib, prelude::*};
 use log::{debug, warn};
 
 use crate::{
-    audio::AudioPlayer,
+    audio::{AudioPlayer, Library, PlayHistory, MAX_RATING},
     config::{APPLICATION_ID, VERSION},
     i18n::i18n,
+    library_dialog::LibraryDialog,
+    preferences_dialog::PreferencesDialog,
+    session::Session,
     utils,
     window::Window,
 };
@@ -30,6 +33,7 @@ mod imp {
     #[derive(Debug)]
     pub struct Application {
         pub player: Rc<AudioPlayer>,
+        pub library: Library,
         pub receiver: RefCell<Option<Receiver<ApplicationAction>>>,
         pub background_hold: RefCell<Option<gio::ApplicationHoldGuard>>,
         pub settings: gio::Settings,
@@ -47,6 +51,7 @@ mod imp {
 
             Self {
                 player: AudioPlayer::new(sender),
+                library: Library::new(),
                 receiver,
                 background_hold: RefCell::default(),
                 settings: utils::settings_manager(),
@@ -58,12 +63,16 @@ mod imp {
         fn constructed(&self) {
             self.parent_constructed();
 
+            self.player.set_library(&self.library);
+
             let obj = self.obj();
             obj.setup_channel();
             obj.setup_gactions();
             obj.setup_settings();
 
             obj.set_accels_for_action("app.quit", &["<primary>q"]);
+            obj.set_accels_for_action("app.preferences", &["<primary>comma"]);
+            obj.set_accels_for_action("app.library", &["<primary>m"]);
 
             obj.set_accels_for_action("queue.add-song", &["<primary>s"]);
             obj.set_accels_for_action("queue.add-folder", &["<primary>a"]);
@@ -78,6 +87,15 @@ mod imp {
             obj.set_accels_for_action("win.next", &["<primary>n"]);
             obj.set_accels_for_action("win.play", &["<primary>p"]);
             obj.set_accels_for_action("win.copy", &["<primary>c"]);
+            obj.set_accels_for_action("win.edit-tags", &["<primary>e"]);
+            obj.set_accels_for_action("win.song-properties", &["<primary>i"]);
+            obj.set_accels_for_action("win.show-lyrics", &["<primary>y"]);
+            for rating in 0..=MAX_RATING {
+                obj.set_accels_for_action(
+                    &format!("win.rate({})", rating),
+                    &[&format!("<primary>{}", rating)],
+                );
+            }
         }
     }
 
@@ -102,6 +120,20 @@ mod imp {
             self.parent_startup();
 
             gtk::Window::set_default_icon_name(APPLICATION_ID);
+
+            self.library.load();
+        }
+
+        fn shutdown(&self) {
+            // The window may be long gone if we were playing in the
+            // background, so store the session one last time
+            if !self.player.queue().is_empty() {
+                Session::capture(&self.player).save();
+            }
+            self.library.flush();
+            PlayHistory::global().lock().unwrap().flush();
+
+            self.parent_shutdown();
         }
     }
 
@@ -134,6 +166,10 @@ impl Application {
         self.imp().player.clone()
     }
 
+    pub fn library(&self) -> Library {
+        self.imp().library.clone()
+    }
+
     fn setup_settings(&self) {
         self.imp().settings.connect_changed(
             Some("background-play"),
@@ -154,6 +190,27 @@ impl Application {
         );
 
         let _dummy = self.imp().settings.boolean("background-play");
+
+        // The library follows the music folders, and what we import from them
+        for key in [
+            "music-folders",
+            "import-follow-symlinks",
+            "import-skip-hidden",
+            "import-exclude-patterns",
+        ] {
+            self.imp().settings.connect_changed(
+                Some(key),
+                clone!(
+                    #[weak(rename_to = this)]
+                    self,
+                    move |_, key| {
+                        debug!("GSettings:{key} changed, scanning the library");
+                        this.imp().library.scan();
+                    }
+                ),
+            );
+            let _dummy = self.imp().settings.value(key);
+        }
     }
 
     fn setup_channel(&self) {
@@ -203,6 +260,16 @@ impl Application {
                     app.quit();
                 })
                 .build(),
+            gio::ActionEntry::builder("preferences")
+                .activate(|app: &Application, _, _| {
+                    app.show_preferences();
+                })
+                .build(),
+            gio::ActionEntry::builder("library")
+                .activate(|app: &Application, _, _| {
+                    app.show_library();
+                })
+                .build(),
             gio::ActionEntry::builder("about")
                 .activate(|app: &Application, _, _| {
                     app.show_about();
@@ -227,6 +294,20 @@ impl Application {
             .build()]);
     }
 
+    fn show_preferences(&self) {
+        let window = self.active_window().unwrap();
+        let dialog = PreferencesDialog::new();
+
+        dialog.present(Some(&window));
+    }
+
+    fn show_library(&self) {
+        let window = self.active_window().unwrap();
+        let dialog = LibraryDialog::new(&self.imp().library);
+
+        dialog.present(Some(&window));
+    }
+
     fn show_about(&self) {
         let window = self.active_window().unwrap();
         let dialog = adw::AboutDialog::builder()
@@ -7,6 +7,8 @@ pub trait Controller {
     fn set_playback_state(&self, state: &PlaybackState);
 
     fn set_song(&self, song: &Song);
+    // The metadata of the current song changed, but it's still playing
+    fn refresh_song(&self, _song: &Song) {}
     fn set_position(&self, position: u64, notify: bool);
     fn set_repeat_mode(&self, repeat: RepeatMode);
 }
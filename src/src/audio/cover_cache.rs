@@ -1,11 +1,7 @@
 // SPDX-FileCopyrightText: 2022  Emmanuele Bassi
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::{
-    collections::HashMap,
-    path::{Path, PathBuf},
-    sync::Mutex,
-};
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
 
 use gtk::{gdk, gio, glib, prelude::*};
 use log::debug;
@@ -64,7 +60,11 @@ impl CoverCache {
         self.entries.get(uuid)
     }
 
-    fn load_cover_art(&self, tag: &lofty::tag::Tag, path: Option<&Path>) -> Option<glib::Bytes> {
+    fn load_cover_art(
+        &self,
+        tag: &lofty::tag::Tag,
+        folder: Option<&gio::File>,
+    ) -> Option<glib::Bytes> {
         if let Some(picture) = tag.get_picture_type(lofty::picture::PictureType::CoverFront) {
             debug!("Found CoverFront");
             return Some(glib::Bytes::from(picture.data()));
@@ -91,7 +91,7 @@ impl CoverCache {
         // to be in a hot cache; looking for a separate file will blow a bunch of
         // caches out of the water, which will slow down loading the song into the
         // playlist model
-        if let Some(p) = path {
+        if let Some(folder) = folder {
             let ext_cover_basename = vec!["Cover", "cover", "Folder", "folder"];
             let ext_cover_ext = vec!["jpg", "png"];
 
@@ -108,11 +108,10 @@ impl CoverCache {
                     v
                 });
             for name in ext_covers {
-                let mut cover_file = PathBuf::from(p);
-                cover_file.push(name);
-                debug!("Looking for external cover file: {:?}", &cover_file);
+                // The folder is not necessarily local, so we go through GIO
+                let f = folder.child(name);
+                debug!("Looking for external cover file: {}", f.uri());
 
-                let f = gio::File::for_path(&cover_file);
                 if let Ok((res, _)) = f.load_bytes(None::<&gio::Cancellable>) {
                     debug!("Loading cover from external cover file");
                     return Some(res);
@@ -125,7 +124,11 @@ impl CoverCache {
         None
     }
 
-    pub fn cover_art(&mut self, path: &Path, tag: &lofty::tag::Tag) -> Option<(CoverArt, String)> {
+    pub fn cover_art(
+        &mut self,
+        file: &gio::File,
+        tag: &lofty::tag::Tag,
+    ) -> Option<(CoverArt, String)> {
         let mut album_artist = None;
         let mut track_artist = None;
         let mut album = None;
@@ -159,11 +162,11 @@ impl CoverCache {
                 hasher.update(&artist);
             }
 
-            if let Some(parent) = path.parent() {
-                hasher.update(parent.to_str().unwrap());
+            if let Some(parent) = file.parent() {
+                hasher.update(location(&parent));
             }
         } else {
-            hasher.update(path.to_str().unwrap());
+            hasher.update(location(file));
         }
 
         let uuid = format!("{:x}", hasher.finalize());
@@ -176,7 +179,7 @@ impl CoverCache {
             None => {
                 debug!("Loading cover art for UUID: {}", &uuid);
 
-                let cover_art = self.load_cover_art(tag, path.parent());
+                let cover_art = self.load_cover_art(tag, file.parent().as_ref());
 
                 // The pixel buffer for the cover art
                 let cover_pixbuf = if let Some(ref cover_art) = cover_art {
@@ -221,7 +224,61 @@ impl CoverCache {
         }
     }
 
+    // Loads the cover art we stored in the cache directory the first time
+    // we saw it, so we do not need to look at the song metadata
+    pub fn cached_cover_art(&mut self, uuid: &str, palette: &[gdk::RGBA]) -> Option<CoverArt> {
+        if let Some(c) = self.lookup(&uuid.to_string()) {
+            return Some(c.clone());
+        }
+
+        if palette.is_empty() {
+            return None;
+        }
+
+        let path = utils::cover_cache_path(uuid);
+        match gdk::Texture::from_filename(&path) {
+            Ok(texture) => {
+                debug!("Loaded cached cover for UUID '{}'", uuid);
+                let res = CoverArt {
+                    texture,
+                    palette: palette.to_vec(),
+                    cache: Some(path),
+                };
+
+                Some(self.add_entry(uuid, res).clone())
+            }
+            Err(e) => {
+                debug!("Unable to load cached cover for UUID '{}': {}", uuid, e);
+                None
+            }
+        }
+    }
+
     pub fn clear(&mut self) {
         self.entries.clear();
     }
+
+    // Forgets a cover that changed inside the song files, and the copy we
+    // keep on disk unless other songs still point to it; we never
+    // overwrite that copy otherwise
+    pub fn invalidate(&mut self, uuid: &str, remove_file: bool) {
+        self.entries.remove(uuid);
+        if !remove_file {
+            return;
+        }
+
+        let file = gio::File::for_path(utils::cover_cache_path(uuid));
+        if let Err(e) = file.delete(gio::Cancellable::NONE) {
+            debug!("Unable to remove cached cover for UUID '{}': {}", uuid, e);
+        }
+    }
+}
+
+// Local files are identified by their path, so that the cover art we already
+// cached for them keeps its UUID; everything else only has a URI
+fn location(file: &gio::File) -> String {
+    match file.path() {
+        Some(path) => path.to_string_lossy().into_owned(),
+        None => file.uri().to_string(),
+    }
 }
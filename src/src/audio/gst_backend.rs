@@ -24,7 +24,7 @@ pub struct GstReplayGain {
 }
 
 fn send_update_position(sender: &Sender<PlaybackAction>, clock: gst::ClockTime, notify: bool) {
-    let pos = clock.seconds();
+    let pos = clock.mseconds();
     if let Err(e) = sender.send_blocking(PlaybackAction::UpdatePosition(pos, notify)) {
         error!("Failed to send UpdatePosition({pos}): {e}");
     }
@@ -175,6 +175,11 @@ impl GstBackend {
         self.gst_player.seek(gst::ClockTime::from_seconds(position));
     }
 
+    pub fn seek_position_ms(&self, position: u64) {
+        self.gst_player
+            .seek(gst::ClockTime::from_mseconds(position));
+    }
+
     pub fn seek_start(&self) {
         self.gst_player.seek(gst::ClockTime::from_seconds(0));
     }
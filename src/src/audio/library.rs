@@ -0,0 +1,552 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use glib::{clone, subclass::Signal};
+use gtk::{gio, glib, prelude::*, subclass::prelude::*};
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    audio::{
+        library_group::{group_songs, BrowseKind, LibraryGroup},
+        metadata_cache::{read_song_data, write_song_data, FileStamp, MetadataCache},
+        PlayHistory, RuleContext, SmartPlaylist, Song, SongData, SongFacts, SongLoader, SortKey,
+    },
+    folder_watcher::FolderChanges,
+    utils,
+};
+
+// Bump this whenever the layout of the library file changes; a version
+// mismatch means we scan the music folders from scratch
+const LIBRARY_VERSION: i64 = 5;
+const LIBRARY_GROUP: &str = "library";
+
+// How many songs we add to the library at once while scanning
+const SCAN_BATCH_SIZE: usize = 200;
+
+fn library_path() -> PathBuf {
+    let mut path = glib::user_data_dir();
+    path.push("axuralis");
+    path.push("library");
+    path.push("songs.library");
+    path
+}
+
+// A song we know about, and the state of its file when we read it
+#[derive(Debug)]
+struct LibraryEntry {
+    song: Song,
+    stamp: Option<FileStamp>,
+    // When we first found the song, in seconds since the epoch
+    added: i64,
+}
+
+mod imp {
+    use glib::{ParamSpec, ParamSpecBoolean, ParamSpecUInt, Value};
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    pub struct Library {
+        pub entries: RefCell<HashMap<String, LibraryEntry>>,
+        pub scanning: Cell<bool>,
+        pub rescan: Cell<bool>,
+        pub loaded: Cell<bool>,
+        pub save_id: RefCell<Option<glib::SourceId>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for Library {
+        const NAME: &'static str = "AxuralisLibrary";
+        type Type = super::Library;
+    }
+
+    impl ObjectImpl for Library {
+        fn properties() -> &'static [ParamSpec] {
+            static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| {
+                vec![
+                    ParamSpecUInt::builder("n-songs").read_only().build(),
+                    ParamSpecBoolean::builder("scanning").read_only().build(),
+                ]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn property(&self, _id: usize, pspec: &ParamSpec) -> Value {
+            match pspec.name() {
+                "n-songs" => (self.entries.borrow().len() as u32).to_value(),
+                "scanning" => self.scanning.get().to_value(),
+                _ => unimplemented!(),
+            }
+        }
+
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> =
+                Lazy::new(|| vec![Signal::builder("changed").build()]);
+
+            SIGNALS.as_ref()
+        }
+    }
+}
+
+glib::wrapper! {
+    pub struct Library(ObjectSubclass<imp::Library>);
+}
+
+impl Default for Library {
+    fn default() -> Self {
+        glib::Object::new()
+    }
+}
+
+// Every song inside the music folders; the songs are stored on disk, so
+// we only need to look at the files that changed since the last scan
+impl Library {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn n_songs(&self) -> u32 {
+        self.imp().entries.borrow().len() as u32
+    }
+
+    pub fn is_scanning(&self) -> bool {
+        self.imp().scanning.get()
+    }
+
+    pub fn songs(&self) -> Vec<Song> {
+        self.imp()
+            .entries
+            .borrow()
+            .values()
+            .map(|e| e.song.clone())
+            .collect()
+    }
+
+    pub fn groups(&self, kind: BrowseKind) -> Vec<LibraryGroup> {
+        group_songs(&self.songs(), kind)
+    }
+
+    // The songs that follow the rules of a smart playlist right now
+    pub fn smart_playlist_songs(&self, playlist: &SmartPlaylist) -> Vec<Song> {
+        let context = RuleContext::now();
+        let history = PlayHistory::global().lock().unwrap();
+        let mut songs: Vec<Song> = self
+            .imp()
+            .entries
+            .borrow()
+            .iter()
+            .filter(|(uri, entry)| {
+                let song = &entry.song;
+                let stats = history.stats(uri);
+                let facts = SongFacts {
+                    title: song.title(),
+                    artists: song.artists(),
+                    album: song.album(),
+                    genre: song.genre(),
+                    year: song.year(),
+                    duration: song.duration(),
+                    rating: song.rating(),
+                    play_count: stats.play_count,
+                    last_played: stats.last_played,
+                    added: Some(entry.added),
+                };
+                playlist.matches(&facts, &context)
+            })
+            .map(|(_, entry)| entry.song.clone())
+            .collect();
+        songs.sort_by(|a, b| SortKey::Artist.compare(a, b));
+
+        songs
+    }
+
+    fn set_scanning(&self, scanning: bool) {
+        if scanning != self.imp().scanning.replace(scanning) {
+            self.notify("scanning");
+        }
+    }
+
+    fn changed(&self) {
+        self.notify("n-songs");
+        self.emit_by_name::<()>("changed", &[]);
+    }
+
+    // Loads the songs we stored the last time, and then looks for the
+    // songs that changed in the meantime
+    pub fn load(&self) {
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = this)]
+            self,
+            async move {
+                this.set_scanning(true);
+
+                let stored = gio::spawn_blocking(load_entries).await.unwrap_or_default();
+                let songs: Vec<(Song, FileStamp, i64)> = stored
+                    .into_iter()
+                    .filter_map(|(stamp, data, added)| {
+                        Song::from_data(data).ok().map(|s| (s, stamp, added))
+                    })
+                    .collect();
+                debug!("Loaded {} songs from the library", songs.len());
+                {
+                    let mut entries = this.imp().entries.borrow_mut();
+                    for (song, stamp, added) in songs {
+                        let entry = LibraryEntry {
+                            song: song.clone(),
+                            stamp: Some(stamp),
+                            added,
+                        };
+                        entries.insert(song.uri(), entry);
+                    }
+                }
+                this.imp().loaded.set(true);
+                this.changed();
+
+                this.set_scanning(false);
+                this.scan();
+            }
+        ));
+    }
+
+    // Looks for new, changed and removed files inside the music folders
+    pub fn scan(&self) {
+        if !self.imp().loaded.get() {
+            return;
+        }
+
+        // The music folders may have changed while we were looking at them
+        if self.is_scanning() {
+            self.imp().rescan.set(true);
+            return;
+        }
+
+        let folders = utils::music_folders();
+        let options = utils::ImportOptions::from_settings(true);
+        let known: HashMap<String, Option<FileStamp>> = self
+            .imp()
+            .entries
+            .borrow()
+            .iter()
+            .map(|(uri, e)| (uri.clone(), e.stamp))
+            .collect();
+
+        self.set_scanning(true);
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = this)]
+            self,
+            async move {
+                let res = gio::spawn_blocking(move || {
+                    let mut seen = HashSet::new();
+                    let mut changed = Vec::new();
+                    for folder in &folders {
+                        for file in utils::load_files_from_folder(folder, &options).files {
+                            let uri = file.uri().to_string();
+                            let stamp = FileStamp::for_file(&file);
+                            match known.get(&uri) {
+                                Some(known_stamp) if stamp.is_some() && *known_stamp == stamp => (),
+                                _ => changed.push((file, stamp)),
+                            }
+                            seen.insert(uri);
+                        }
+                    }
+
+                    let removed: Vec<String> = known
+                        .into_keys()
+                        .filter(|uri| !seen.contains(uri))
+                        .collect();
+
+                    (changed, removed)
+                })
+                .await;
+
+                if let Ok((changed, removed)) = res {
+                    debug!(
+                        "Library scan: {} new or changed files, {} removed",
+                        changed.len(),
+                        removed.len()
+                    );
+                    this.remove_uris(&removed);
+                    this.add_files(changed).await;
+                }
+
+                this.set_scanning(false);
+                if this.imp().rescan.replace(false) {
+                    this.scan();
+                }
+            }
+        ));
+    }
+
+    // Keeps the library in sync with the watched music folders
+    pub fn apply_folder_changes(&self, changes: &FolderChanges) {
+        let mut modified = false;
+
+        {
+            let mut entries = self.imp().entries.borrow_mut();
+            for (from, to) in &changes.moved {
+                let moved: Vec<String> = entries
+                    .iter()
+                    .filter(|(_, e)| {
+                        let f = e.song.file();
+                        f.equal(from) || f.has_prefix(from)
+                    })
+                    .map(|(uri, _)| uri.clone())
+                    .collect();
+                for uri in moved {
+                    let entry = entries.remove(&uri).unwrap();
+                    let file = entry.song.file();
+                    let new_file = if file.equal(from) {
+                        to.clone()
+                    } else if let Some(path) = from.relative_path(&file) {
+                        to.resolve_relative_path(path)
+                    } else {
+                        continue;
+                    };
+                    entry.song.set_file(&new_file);
+                    entries.insert(new_file.uri().to_string(), entry);
+                    modified = true;
+                }
+            }
+        }
+
+        let removed: Vec<String> = self
+            .imp()
+            .entries
+            .borrow()
+            .iter()
+            .filter(|(_, e)| {
+                let f = e.song.file();
+                changes
+                    .removed
+                    .iter()
+                    .any(|r| f.equal(r) || f.has_prefix(r))
+            })
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        if !removed.is_empty() {
+            self.remove_uris(&removed);
+        } else if modified {
+            self.changed();
+            self.queue_save();
+        }
+
+        if !changes.added.is_empty() {
+            let files: Vec<(gio::File, Option<FileStamp>)> = changes
+                .added
+                .iter()
+                .map(|f| (f.clone(), FileStamp::for_file(f)))
+                .collect();
+            glib::spawn_future_local(clone!(
+                #[weak(rename_to = this)]
+                self,
+                async move {
+                    this.add_files(files).await;
+                }
+            ));
+        }
+    }
+
+    // Reads the songs again after we changed their files ourselves, by
+    // editing their tags or rating them; the files may not look any
+    // different to a scan, so we skip the metadata cache as well
+    pub fn reload_uris(&self, uris: &[String]) {
+        let uris: Vec<String> = {
+            let entries = self.imp().entries.borrow();
+            uris.iter()
+                .filter(|uri| entries.contains_key(*uri))
+                .cloned()
+                .collect()
+        };
+        if uris.is_empty() {
+            return;
+        }
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = this)]
+            self,
+            async move {
+                let files = gio::spawn_blocking(move || {
+                    {
+                        let mut metadata_cache = MetadataCache::global().lock().unwrap();
+                        for uri in &uris {
+                            metadata_cache.invalidate(uri);
+                        }
+                    }
+
+                    uris.iter()
+                        .map(|uri| {
+                            let file = gio::File::for_uri(uri);
+                            let stamp = FileStamp::for_file(&file);
+                            (file, stamp)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .await
+                .unwrap_or_default();
+
+                this.add_files(files).await;
+            }
+        ));
+    }
+
+    fn remove_uris(&self, uris: &[String]) {
+        if uris.is_empty() {
+            return;
+        }
+
+        {
+            let mut entries = self.imp().entries.borrow_mut();
+            for uri in uris {
+                entries.remove(uri);
+            }
+        }
+
+        self.changed();
+        self.queue_save();
+    }
+
+    // Reads the tags of new or changed files, and adds them in batches
+    async fn add_files(&self, files: Vec<(gio::File, Option<FileStamp>)>) {
+        if files.is_empty() {
+            return;
+        }
+
+        let (files, stamps): (Vec<gio::File>, Vec<Option<FileStamp>>) = files.into_iter().unzip();
+        let loader = SongLoader::new(files);
+        let now = glib::real_time() / 1_000_000;
+        loop {
+            let batch = loader.next_batch(SCAN_BATCH_SIZE).await;
+            if batch.is_empty() {
+                break;
+            }
+
+            {
+                let mut entries = self.imp().entries.borrow_mut();
+                for (pos, data) in batch {
+                    if let Ok(song) = Song::from_data(data) {
+                        // Changed files keep the date we first found them
+                        let added = entries.get(&song.uri()).map_or(now, |e| e.added);
+                        let entry = LibraryEntry {
+                            song: song.clone(),
+                            stamp: stamps[pos],
+                            added,
+                        };
+                        entries.insert(song.uri(), entry);
+                    }
+                }
+            }
+
+            self.changed();
+        }
+
+        self.queue_save();
+    }
+
+    // Scans and folder changes come in bursts, so we write the library
+    // once things calm down
+    fn queue_save(&self) {
+        if let Some(id) = self.imp().save_id.take() {
+            id.remove();
+        }
+
+        let id = glib::timeout_add_seconds_local_once(
+            5,
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                move || {
+                    this.imp().save_id.replace(None);
+                    this.save();
+                }
+            ),
+        );
+        self.imp().save_id.replace(Some(id));
+    }
+
+    // Writes a pending save right away, e.g. when quitting
+    pub fn flush(&self) {
+        if let Some(id) = self.imp().save_id.take() {
+            id.remove();
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let kf = glib::KeyFile::new();
+        kf.set_int64(LIBRARY_GROUP, "Version", LIBRARY_VERSION);
+        for (uri, entry) in self.imp().entries.borrow().iter() {
+            // Songs we could not stat are looked at again on the next scan
+            let stamp = match entry.stamp {
+                Some(stamp) => stamp,
+                None => continue,
+            };
+
+            let group = group_name(uri);
+            let song = &entry.song;
+            kf.set_string(&group, "Uri", uri);
+            kf.set_int64(&group, "Added", entry.added);
+            write_song_data(
+                &kf,
+                &group,
+                stamp,
+                &song.data(),
+                &song.cover_palette().unwrap_or_default(),
+            );
+        }
+
+        let path = library_path();
+        if let Some(parent) = path.parent() {
+            glib::mkdir_with_parents(parent, 0o755);
+        }
+
+        match kf.save_to_file(&path) {
+            Ok(_) => debug!("Library stored in: {:?}", &path),
+            Err(e) => warn!("Unable to store library: {e}"),
+        }
+    }
+}
+
+fn group_name(uri: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(uri.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+// Runs on a worker thread, since the library file can be quite large
+fn load_entries() -> Vec<(FileStamp, SongData, i64)> {
+    let path = library_path();
+    let kf = glib::KeyFile::new();
+    if let Err(e) = kf.load_from_file(&path, glib::KeyFileFlags::NONE) {
+        debug!("Unable to load library: {e}");
+        return vec![];
+    }
+
+    match kf.int64(LIBRARY_GROUP, "Version") {
+        Ok(LIBRARY_VERSION) => (),
+        _ => {
+            debug!("Discarding library with a different version");
+            return vec![];
+        }
+    }
+
+    let now = glib::real_time() / 1_000_000;
+    kf.groups()
+        .iter()
+        .filter(|group| group.as_str() != LIBRARY_GROUP)
+        .filter_map(|group| {
+            let uri = kf.string(group.as_str(), "Uri").ok()?;
+            let (stamp, mut data, palette) =
+                read_song_data(&kf, group.as_str(), gio::File::for_uri(&uri))?;
+            data.restore_cover(&palette);
+            let added = kf.int64(group.as_str(), "Added").unwrap_or(now);
+            Some((stamp, data, added))
+        })
+        .collect()
+}
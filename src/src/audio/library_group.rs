@@ -0,0 +1,219 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
+
+use gtk::{gdk, gio, glib, prelude::*, subclass::prelude::*};
+
+use crate::{
+    audio::{queue::cmp_text, Song, SortKey},
+    i18n::{i18n, ni18n_f},
+};
+
+// The ways we can browse the library
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrowseKind {
+    Albums,
+    Artists,
+    Genres,
+    Years,
+}
+
+mod imp {
+    use glib::{ParamSpec, ParamSpecObject, ParamSpecString, ParamSpecUInt, Value};
+    use once_cell::sync::Lazy;
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    pub struct LibraryGroup {
+        pub title: RefCell<String>,
+        pub subtitle: RefCell<String>,
+        pub cover: RefCell<Option<gdk::Texture>>,
+        pub songs: RefCell<Vec<Song>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for LibraryGroup {
+        const NAME: &'static str = "AxuralisLibraryGroup";
+        type Type = super::LibraryGroup;
+    }
+
+    impl ObjectImpl for LibraryGroup {
+        fn properties() -> &'static [ParamSpec] {
+            static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| {
+                vec![
+                    ParamSpecString::builder("title").read_only().build(),
+                    ParamSpecString::builder("subtitle").read_only().build(),
+                    ParamSpecObject::builder::<gdk::Texture>("cover")
+                        .read_only()
+                        .build(),
+                    ParamSpecUInt::builder("n-songs").read_only().build(),
+                ]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn property(&self, _id: usize, pspec: &ParamSpec) -> Value {
+            match pspec.name() {
+                "title" => self.title.borrow().to_value(),
+                "subtitle" => self.subtitle.borrow().to_value(),
+                "cover" => self.cover.borrow().to_value(),
+                "n-songs" => (self.songs.borrow().len() as u32).to_value(),
+                _ => unimplemented!(),
+            }
+        }
+    }
+}
+
+glib::wrapper! {
+    pub struct LibraryGroup(ObjectSubclass<imp::LibraryGroup>);
+}
+
+// A set of songs from the library sharing an album, an artist, a genre or
+// a year
+impl LibraryGroup {
+    fn new(title: String, subtitle: String, mut songs: Vec<Song>) -> Self {
+        songs.sort_by(|a, b| SortKey::Album.compare(a, b));
+
+        let res: Self = glib::Object::new();
+        let imp = res.imp();
+        imp.title.replace(title);
+        imp.subtitle.replace(subtitle);
+        imp.cover
+            .replace(songs.iter().find_map(|s| s.cover_texture()));
+        imp.songs.replace(songs);
+        res
+    }
+
+    pub fn title(&self) -> String {
+        self.imp().title.borrow().clone()
+    }
+
+    pub fn subtitle(&self) -> String {
+        self.imp().subtitle.borrow().clone()
+    }
+
+    pub fn cover(&self) -> Option<gdk::Texture> {
+        self.imp().cover.borrow().clone()
+    }
+
+    pub fn songs(&self) -> Vec<Song> {
+        self.imp().songs.borrow().clone()
+    }
+
+    pub fn files(&self) -> Vec<gio::File> {
+        self.imp().songs.borrow().iter().map(|s| s.file()).collect()
+    }
+}
+
+fn n_songs_label(n_songs: usize) -> String {
+    ni18n_f(
+        // Translators: the `{}` must be left unmodified; it will be
+        // expanded to the number of songs
+        "{} song",
+        "{} songs",
+        n_songs as u32,
+        &[&n_songs.to_string()],
+    )
+}
+
+// Splits the songs of the library into the groups of a browse view, in
+// the order in which they should be displayed
+pub fn group_songs(songs: &[Song], kind: BrowseKind) -> Vec<LibraryGroup> {
+    // Keys are folded, so that slightly different spellings of the same
+    // name end up together; the first spelling we see is the one we show
+    let mut groups: HashMap<String, (String, Option<String>, Vec<Song>)> = HashMap::new();
+    for song in songs {
+        // The sort tags only cover the album and the track artist; when
+        // a song has many artists, the sort tag cannot tell which one it
+        // is about
+        let sort_name = match kind {
+            BrowseKind::Albums => song.album_sort(),
+            BrowseKind::Artists if song.album_artist().is_none() && song.artists().len() == 1 => {
+                song.artist_sort()
+            }
+            _ => None,
+        };
+        // Songs without an album artist are listed under each of their
+        // artists
+        let entries: Vec<(String, String)> = match kind {
+            BrowseKind::Albums => vec![(song.album_key(), song.album())],
+            BrowseKind::Artists => match song.album_artist() {
+                Some(artist) => vec![(artist.to_lowercase(), artist)],
+                None => song
+                    .artists()
+                    .into_iter()
+                    .map(|artist| (artist.to_lowercase(), artist))
+                    .collect(),
+            },
+            BrowseKind::Genres => match song.genre() {
+                Some(genre) => vec![(genre.to_lowercase(), genre)],
+                None => vec![(String::new(), i18n("Unknown genre"))],
+            },
+            BrowseKind::Years => match song.year() {
+                // Padded, so that the keys sort like the years
+                Some(year) => vec![(format!("{year:08}"), year.to_string())],
+                None => vec![(String::new(), i18n("Unknown year"))],
+            },
+        };
+
+        for (key, title) in entries {
+            let group = groups
+                .entry(key)
+                .or_insert_with(|| (title, None, Vec::new()));
+            if group.1.is_none() {
+                group.1 = sort_name.clone();
+            }
+            group.2.push(song.clone());
+        }
+    }
+
+    let mut res: Vec<(String, String, LibraryGroup)> = groups
+        .into_iter()
+        .map(|(key, (title, sort_name, songs))| {
+            let subtitle = match kind {
+                BrowseKind::Albums => songs[0].album_artist().unwrap_or_else(|| songs[0].artist()),
+                BrowseKind::Artists => {
+                    let n_albums = songs
+                        .iter()
+                        .map(|s| s.album_key())
+                        .collect::<HashSet<String>>()
+                        .len();
+                    ni18n_f(
+                        // Translators: the `{}` must be left unmodified; it
+                        // will be expanded to the number of albums
+                        "{} album",
+                        "{} albums",
+                        n_albums as u32,
+                        &[&n_albums.to_string()],
+                    )
+                }
+                BrowseKind::Genres | BrowseKind::Years => n_songs_label(songs.len()),
+            };
+
+            let sort_name = sort_name.unwrap_or_else(|| title.clone());
+            (key, sort_name, LibraryGroup::new(title, subtitle, songs))
+        })
+        .collect();
+
+    res.sort_by(|(key_a, sort_a, _), (key_b, sort_b, _)| match kind {
+        // The most recent years come first, and songs without a year
+        // come last
+        BrowseKind::Years => match (key_a.is_empty(), key_b.is_empty()) {
+            (false, true) => Ordering::Less,
+            (true, false) => Ordering::Greater,
+            _ => key_b.cmp(key_a),
+        },
+        BrowseKind::Genres if key_a.is_empty() != key_b.is_empty() => {
+            key_a.is_empty().cmp(&key_b.is_empty())
+        }
+        _ => cmp_text(sort_a, sort_b),
+    });
+
+    res.into_iter().map(|(_, _, group)| group).collect()
+}
@@ -0,0 +1,357 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{fs, path::PathBuf, time::Duration};
+
+use gtk::{gio, glib, prelude::*};
+use lofty::{
+    id3::v2::{FrameFlags, SynchronizedTextFrame, TimestampFormat},
+    prelude::TaggedFileExt,
+    tag::{ItemKey, ItemValue},
+};
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    audio::{song::read_tagged_file, Song},
+    config::VERSION,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LyricLine {
+    // In milliseconds from the start of the song
+    pub time: u64,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Lyrics {
+    Plain(String),
+    // Sorted by time
+    Synced(Vec<LyricLine>),
+}
+
+impl Lyrics {
+    // Synced lyrics if the text has timestamps, plain ones otherwise
+    pub fn from_text(text: &str) -> Option<Self> {
+        if let Some(lines) = parse_lrc(text) {
+            return Some(Lyrics::Synced(lines));
+        }
+
+        let text = text.trim();
+        (!text.is_empty()).then(|| Lyrics::Plain(text.to_string()))
+    }
+
+    // The line being sung at `position`, in milliseconds
+    pub fn line_at(&self, position: u64) -> Option<usize> {
+        match self {
+            Lyrics::Synced(lines) => lines.partition_point(|l| l.time <= position).checked_sub(1),
+            Lyrics::Plain(_) => None,
+        }
+    }
+}
+
+// Parses a timestamp like "01:23.45"; some files use a colon before the
+// fraction of a second, or have no fraction at all. Timestamps too large
+// to count in milliseconds are not timestamps
+fn parse_timestamp(text: &str) -> Option<u64> {
+    let (minutes, seconds) = text.split_once(':')?;
+    let minutes = minutes.trim().parse::<u64>().ok()?;
+    let seconds = seconds
+        .trim()
+        .replacen(':', ".", 1)
+        .parse::<f64>()
+        .ok()
+        .filter(|s| (0.0..60.0).contains(s))?;
+
+    minutes
+        .checked_mul(60_000)?
+        .checked_add((seconds * 1000.0).round() as u64)
+}
+
+// The enhanced LRC format times every word, e.g. "<00:12.50> word"; we
+// only highlight lines, so the word times go away
+fn strip_word_times(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        match rest[start..].find('>') {
+            Some(len) if parse_timestamp(&rest[start + 1..start + len]).is_some() => {
+                res.push_str(&rest[..start]);
+                rest = &rest[start + len + 1..];
+            }
+            _ => {
+                res.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    res.push_str(rest);
+
+    res.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// Parses lyrics in the LRC format:
+//
+//   [ar:Some Artist]
+//   [offset:+250]
+//   [00:12.34]First line
+//   [00:15.00][01:15.00]A line of the chorus
+//
+// Returns None if no line has a timestamp
+pub fn parse_lrc(text: &str) -> Option<Vec<LyricLine>> {
+    let mut offset: i64 = 0;
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let mut rest = line.trim();
+        let mut times = Vec::new();
+        while let Some((tag, after)) = rest.strip_prefix('[').and_then(|r| r.split_once(']')) {
+            if let Some(time) = parse_timestamp(tag) {
+                times.push(time);
+            } else if let Some(value) = tag.strip_prefix("offset:") {
+                offset = value.trim().parse().unwrap_or(0);
+            }
+            rest = after;
+        }
+
+        let text = strip_word_times(rest);
+        for time in times {
+            lines.push(LyricLine {
+                time,
+                text: text.clone(),
+            });
+        }
+    }
+
+    if lines.is_empty() {
+        return None;
+    }
+
+    // A positive offset shows the lines sooner
+    for line in &mut lines {
+        line.time = if offset >= 0 {
+            line.time.saturating_sub(offset.unsigned_abs())
+        } else {
+            line.time.saturating_add(offset.unsigned_abs())
+        };
+    }
+    lines.sort_by_key(|l| l.time);
+
+    Some(lines)
+}
+
+// The SYLT frame of ID3v2 tags; lofty keeps it as binary data
+fn parse_sylt(data: &[u8]) -> Option<Vec<LyricLine>> {
+    let frame = SynchronizedTextFrame::parse(data, FrameFlags::default()).ok()?;
+    // Times in MPEG frames would need the frame rate of the file
+    if frame.information.timestamp_format != TimestampFormat::MS {
+        return None;
+    }
+
+    let mut lines: Vec<LyricLine> = frame
+        .content
+        .into_iter()
+        .map(|(time, text)| LyricLine {
+            time: time as u64,
+            text: text.trim().to_string(),
+        })
+        .collect();
+    lines.sort_by_key(|l| l.time);
+
+    (!lines.is_empty()).then_some(lines)
+}
+
+// A "song.lrc" file next to "song.flac"
+fn sidecar_lyrics(file: &gio::File) -> Option<Lyrics> {
+    let basename = file.basename()?;
+    let lrc = file.parent()?.child(basename.with_extension("lrc"));
+    let (bytes, _) = lrc.load_contents(gio::Cancellable::NONE).ok()?;
+
+    debug!("Found lyrics file {}", lrc.uri());
+    Lyrics::from_text(&String::from_utf8_lossy(&bytes))
+}
+
+fn embedded_lyrics(file: &gio::File) -> Option<Lyrics> {
+    let tagged_file = match read_tagged_file(file) {
+        Ok(f) => f,
+        Err(e) => {
+            warn!("Unable to open file {}: {}", file.uri(), e);
+            return None;
+        }
+    };
+
+    let sylt = ItemKey::Unknown("SYLT".to_string());
+    let mut unsynced = None;
+    for tag in tagged_file.tags() {
+        if let Some(ItemValue::Binary(data)) = tag.get(&sylt).map(|item| item.value()) {
+            if let Some(lines) = parse_sylt(data) {
+                return Some(Lyrics::Synced(lines));
+            }
+        }
+
+        // Plenty of taggers write LRC text in the unsynced lyrics
+        unsynced =
+            unsynced.or_else(|| tag.get_string(&ItemKey::Lyrics).and_then(Lyrics::from_text));
+    }
+
+    unsynced
+}
+
+fn lyrics_cache_path(uri: &str) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update(uri);
+
+    let mut cache = glib::user_cache_dir();
+    cache.push("axuralis");
+    cache.push("lyrics");
+    cache.push(format!("{:x}.lrc", hasher.finalize()));
+    cache
+}
+
+// Asks an LRCLIB-compatible server; answers are cached, including the
+// lack of lyrics, so that we only ask once per song. Songs without an
+// artist or a title would only get the lyrics of some other song
+fn fetch_lyrics(server: &str, request: &LyricsRequest) -> Option<Lyrics> {
+    let artist = request.artist.as_deref().filter(|s| !s.trim().is_empty())?;
+    let title = request.title.as_deref().filter(|s| !s.trim().is_empty())?;
+
+    let uri = request.file.uri();
+    let cache = lyrics_cache_path(&uri);
+    if let Ok(text) = fs::read_to_string(&cache) {
+        debug!("Using cached lyrics for {}", uri);
+        return Lyrics::from_text(&text);
+    }
+
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(format!("Axuralis/{}", VERSION))
+        .timeout(Duration::from_secs(10))
+        .build()
+        .ok()?;
+    let duration = request.duration.to_string();
+    let mut query = vec![
+        ("artist_name", artist),
+        ("track_name", title),
+        ("duration", duration.as_str()),
+    ];
+    if let Some(album) = request.album.as_deref() {
+        query.push(("album_name", album));
+    }
+    let response = client
+        .get(format!("{}/api/get", server.trim_end_matches('/')))
+        .query(&query)
+        .send();
+
+    let text = match response {
+        Ok(r) if r.status() == reqwest::StatusCode::NOT_FOUND => String::new(),
+        Ok(r) if r.status().is_success() => match r.json::<serde_json::Value>() {
+            Ok(json) => json["syncedLyrics"]
+                .as_str()
+                .filter(|s| !s.trim().is_empty())
+                .or_else(|| json["plainLyrics"].as_str())
+                .unwrap_or_default()
+                .to_string(),
+            Err(e) => {
+                warn!("Unable to read the lyrics of {}: {}", uri, e);
+                return None;
+            }
+        },
+        // Errors are not cached: the server may be back later
+        Ok(r) => {
+            warn!("Unable to fetch the lyrics of {}: {}", uri, r.status());
+            return None;
+        }
+        Err(e) => {
+            warn!("Unable to fetch the lyrics of {}: {}", uri, e);
+            return None;
+        }
+    };
+
+    if let Some(parent) = cache.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Err(e) = fs::write(&cache, &text) {
+        warn!("Unable to cache the lyrics of {}: {}", uri, e);
+    }
+
+    Lyrics::from_text(&text)
+}
+
+// What we need to know about a song to find its lyrics away from the
+// main thread
+#[derive(Clone, Debug)]
+pub struct LyricsRequest {
+    pub file: gio::File,
+    // The tags as they are, without the fallbacks we show for untagged
+    // songs; only the first artist, as the server knows songs by it
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub album: Option<String>,
+    pub duration: u64,
+    // The server to ask when the song has no lyrics of its own
+    pub server: Option<String>,
+}
+
+impl LyricsRequest {
+    pub fn new(song: &Song, server: Option<String>) -> Self {
+        Self {
+            file: song.file(),
+            artist: song.tagged_artists().into_iter().next(),
+            title: song.tagged_title(),
+            album: song.tagged_album(),
+            duration: song.duration(),
+            server,
+        }
+    }
+}
+
+// Looks for the lyrics of a song: a .lrc file next to it wins, as it is
+// usually there on purpose, then the tags, then the server. This blocks,
+// so it should run in a thread
+pub fn find_lyrics(request: &LyricsRequest) -> Option<Lyrics> {
+    sidecar_lyrics(&request.file)
+        .or_else(|| embedded_lyrics(&request.file))
+        .or_else(|| fetch_lyrics(request.server.as_deref()?, request))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lrc() {
+        let lines = parse_lrc(
+            "[ar:Someone]\n\
+             [offset:+250]\n\
+             [00:15.00][01:15.00]Chorus\n\
+             [00:12.345]<00:12.50>First <00:13.00>line\n\
+             Not timed",
+        )
+        .unwrap();
+        let times: Vec<u64> = lines.iter().map(|l| l.time).collect();
+        assert_eq!(times, [12095, 14750, 74750]);
+        assert_eq!(lines[0].text, "First line");
+        assert_eq!(lines[1].text, "Chorus");
+
+        assert_eq!(parse_lrc("Just some words\n[ar:Someone]"), None);
+
+        // Huge timestamps and offsets must not overflow
+        assert_eq!(parse_lrc("[99999999999999999:00.00]Too late"), None);
+        let lines = parse_lrc("[offset:9223372036854775807]\n[00:02.00]Early").unwrap();
+        assert_eq!(lines[0].time, 0);
+        let lines = parse_lrc("[offset:-9223372036854775808]\n[00:01.00]Late").unwrap();
+        assert_eq!(lines[0].time, 9_223_372_036_854_776_808);
+    }
+
+    #[test]
+    fn test_line_at() {
+        let lyrics = Lyrics::from_text("[00:01.00]One\n[00:02:50]Two\n[00:04]Three").unwrap();
+        assert_eq!(lyrics.line_at(500), None);
+        assert_eq!(lyrics.line_at(1000), Some(0));
+        assert_eq!(lyrics.line_at(3000), Some(1));
+        assert_eq!(lyrics.line_at(60_000), Some(2));
+
+        let lyrics = Lyrics::from_text("  Plain words\n").unwrap();
+        assert_eq!(lyrics, Lyrics::Plain("Plain words".to_string()));
+        assert_eq!(lyrics.line_at(1000), None);
+    }
+}
@@ -0,0 +1,357 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+use gtk::{gdk, gio, glib, prelude::*};
+use log::{debug, warn};
+use once_cell::sync::OnceCell;
+use sha2::{Digest, Sha256};
+
+use crate::audio::SongData;
+
+// Bump this whenever the layout of the cache file changes, or whenever we
+// start reading more metadata out of the songs; a version mismatch drops
+// the whole cache
+const CACHE_VERSION: i64 = 6;
+const CACHE_GROUP: &str = "cache";
+
+fn cache_path() -> PathBuf {
+    let mut path = glib::user_cache_dir();
+    path.push("axuralis");
+    path.push("metadata");
+    path.push("songs.cache");
+    path
+}
+
+// What we know about a file on disk; if either changes, the metadata we
+// have cached for the file is stale
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FileStamp {
+    modified: i64,
+    size: i64,
+}
+
+impl FileStamp {
+    pub fn for_file(file: &gio::File) -> Option<Self> {
+        let info = file
+            .query_info(
+                "time::modified,time::modified-usec,standard::size",
+                gio::FileQueryInfoFlags::NONE,
+                gio::Cancellable::NONE,
+            )
+            .ok()?;
+        let modified = info.modification_date_time()?;
+
+        Some(FileStamp {
+            modified: modified.to_unix() * 1_000_000 + modified.microsecond() as i64,
+            size: info.size(),
+        })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size.max(0) as u64
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    stamp: FileStamp,
+    // The metadata, without the cover art; covers are cached separately
+    // by the CoverCache, so we only keep their palette
+    data: SongData,
+    palette: Vec<gdk::RGBA>,
+}
+
+// Parsing tags and hashing covers for every song in the queue every time
+// we start is slow, so we keep the metadata of each song on disk, keyed
+// by its URI, and we only use it if the file did not change since
+#[derive(Debug)]
+pub struct MetadataCache {
+    entries: HashMap<String, CacheEntry>,
+    dirty: bool,
+}
+
+impl MetadataCache {
+    pub fn global() -> &'static Mutex<MetadataCache> {
+        static CACHE: OnceCell<Mutex<MetadataCache>> = OnceCell::new();
+
+        CACHE.get_or_init(|| {
+            let c = MetadataCache::load();
+            Mutex::new(c)
+        })
+    }
+
+    fn load() -> Self {
+        let mut res = MetadataCache {
+            entries: HashMap::new(),
+            dirty: false,
+        };
+
+        let path = cache_path();
+        let kf = glib::KeyFile::new();
+        if let Err(e) = kf.load_from_file(&path, glib::KeyFileFlags::NONE) {
+            debug!("Unable to load metadata cache: {e}");
+            return res;
+        }
+
+        match kf.int64(CACHE_GROUP, "Version") {
+            Ok(CACHE_VERSION) => (),
+            _ => {
+                debug!("Discarding metadata cache with a different version");
+                return res;
+            }
+        }
+
+        for group in kf.groups().iter() {
+            if group.as_str() == CACHE_GROUP {
+                continue;
+            }
+
+            if let Some((uri, entry)) = load_entry(&kf, group.as_str()) {
+                res.entries.insert(uri, entry);
+            }
+        }
+
+        debug!(
+            "Loaded {} entries from the metadata cache",
+            res.entries.len()
+        );
+
+        res
+    }
+
+    pub fn save(&mut self) {
+        if !self.dirty {
+            return;
+        }
+
+        // Forget the songs that were deleted since we read them, or the
+        // cache would only ever grow; remote files are left alone, as
+        // they may just be unreachable right now
+        let n_entries = self.entries.len();
+        self.entries
+            .retain(|uri, _| match gio::File::for_uri(uri).path() {
+                Some(path) => path.exists(),
+                None => true,
+            });
+        if self.entries.len() < n_entries {
+            debug!(
+                "Dropped {} missing files from the metadata cache",
+                n_entries - self.entries.len()
+            );
+        }
+
+        let kf = glib::KeyFile::new();
+        kf.set_int64(CACHE_GROUP, "Version", CACHE_VERSION);
+        for (uri, entry) in &self.entries {
+            store_entry(&kf, uri, entry);
+        }
+
+        let path = cache_path();
+        if let Some(parent) = path.parent() {
+            glib::mkdir_with_parents(parent, 0o755);
+        }
+
+        match kf.save_to_file(&path) {
+            Ok(_) => {
+                debug!("Metadata cache stored in: {:?}", &path);
+                self.dirty = false;
+            }
+            Err(e) => warn!("Unable to store metadata cache: {e}"),
+        }
+    }
+
+    // Returns the cached metadata for the file at `uri`, and the palette
+    // of its cover art, as long as the file did not change
+    pub fn lookup(&self, uri: &str, stamp: FileStamp) -> Option<(SongData, Vec<gdk::RGBA>)> {
+        self.entries
+            .get(uri)
+            .filter(|e| e.stamp == stamp)
+            .map(|e| (e.data.clone(), e.palette.clone()))
+    }
+
+    pub fn insert(&mut self, uri: &str, stamp: FileStamp, data: &SongData) {
+        let palette = data.cover_palette().cloned().unwrap_or_default();
+        let mut data = data.clone();
+        data.cover_art = None;
+
+        self.entries.insert(
+            uri.to_string(),
+            CacheEntry {
+                stamp,
+                data,
+                palette,
+            },
+        );
+        self.dirty = true;
+    }
+
+    // Drops the metadata of a file we just changed ourselves; the stamp
+    // of the file may not change if we are quick enough
+    pub fn invalidate(&mut self, uri: &str) {
+        if self.entries.remove(uri).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    // Whether a file other than `uri` points to the cached cover `uuid`;
+    // every track of an album shares the same cover
+    pub fn uses_cover(&self, uuid: &str, uri: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|(u, e)| u != uri && e.data.cover_uuid() == Some(uuid))
+    }
+}
+
+// URIs can contain characters that are not allowed in group names, so we
+// use a hash of the URI for the group, and store the URI inside it
+fn group_name(uri: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(uri.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+fn store_entry(kf: &glib::KeyFile, uri: &str, entry: &CacheEntry) {
+    let group = group_name(uri);
+    kf.set_string(&group, "Uri", uri);
+    write_song_data(kf, &group, entry.stamp, &entry.data, &entry.palette);
+}
+
+fn load_entry(kf: &glib::KeyFile, group: &str) -> Option<(String, CacheEntry)> {
+    let uri = kf.string(group, "Uri").ok()?.to_string();
+    let (stamp, data, palette) = read_song_data(kf, group, gio::File::for_uri(&uri))?;
+
+    Some((
+        uri,
+        CacheEntry {
+            stamp,
+            data,
+            palette,
+        },
+    ))
+}
+
+// Stores the metadata of a song, without its cover art, inside `group`;
+// the library uses the same layout for its own file
+pub fn write_song_data(
+    kf: &glib::KeyFile,
+    group: &str,
+    stamp: FileStamp,
+    data: &SongData,
+    palette: &[gdk::RGBA],
+) {
+    let set_string = |key: &str, value: Option<&str>| {
+        if let Some(value) = value {
+            kf.set_string(group, key, value);
+        }
+    };
+    let set_uint = |key: &str, value: Option<u32>| {
+        if let Some(value) = value {
+            kf.set_uint64(group, key, value as u64);
+        }
+    };
+
+    kf.set_int64(group, "Modified", stamp.modified);
+    kf.set_int64(group, "Size", stamp.size);
+    set_string("Title", data.title());
+    set_string("Artist", data.artist());
+    if !data.artists().is_empty() {
+        let artists: Vec<&str> = data.artists().iter().map(|s| s.as_str()).collect();
+        kf.set_string_list(group, "Artists", &artists);
+    }
+    set_string("Album", data.album());
+    set_string("AlbumArtist", data.album_artist());
+    set_string("Genre", data.genre());
+    set_string("ArtistSort", data.artist_sort());
+    set_string("AlbumSort", data.album_sort());
+    set_string("TitleSort", data.title_sort());
+    set_uint("Bpm", data.bpm());
+    set_uint("Rating", data.rating());
+    set_uint("Track", data.track_number());
+    set_uint("TrackTotal", data.track_total());
+    set_uint("Disc", data.disc_number());
+    set_uint("DiscTotal", data.disc_total());
+    set_uint("Year", data.year());
+    set_string("Date", data.date());
+    set_string("Composer", data.composer());
+    set_string("Comment", data.comment());
+    set_string("Codec", data.codec());
+    set_uint("Bitrate", data.bitrate());
+    set_uint("SampleRate", data.sample_rate());
+    set_uint("BitDepth", data.bit_depth());
+    set_uint("Channels", data.channels());
+    kf.set_uint64(group, "Duration", data.duration());
+    set_string("Uuid", data.uuid());
+    set_string("CoverUuid", data.cover_uuid());
+
+    if !palette.is_empty() {
+        let palette: Vec<String> = palette.iter().map(|c| c.to_string()).collect();
+        let palette: Vec<&str> = palette.iter().map(|s| s.as_str()).collect();
+        kf.set_string_list(group, "Palette", &palette);
+    }
+}
+
+// The other side of write_song_data()
+pub fn read_song_data(
+    kf: &glib::KeyFile,
+    group: &str,
+    file: gio::File,
+) -> Option<(FileStamp, SongData, Vec<gdk::RGBA>)> {
+    let stamp = FileStamp {
+        modified: kf.int64(group, "Modified").ok()?,
+        size: kf.int64(group, "Size").ok()?,
+    };
+
+    let string = |key: &str| kf.string(group, key).ok().map(|s| s.to_string());
+    let uint = |key: &str| kf.uint64(group, key).ok().map(|v| v as u32);
+
+    let data = SongData {
+        title: string("Title"),
+        artist: string("Artist"),
+        artists: kf
+            .string_list(group, "Artists")
+            .map(|l| l.iter().map(|s| s.to_string()).collect())
+            .unwrap_or_default(),
+        album: string("Album"),
+        album_artist: string("AlbumArtist"),
+        genre: string("Genre"),
+        artist_sort: string("ArtistSort"),
+        album_sort: string("AlbumSort"),
+        title_sort: string("TitleSort"),
+        bpm: uint("Bpm"),
+        rating: uint("Rating"),
+        track_number: uint("Track"),
+        track_total: uint("TrackTotal"),
+        disc_number: uint("Disc"),
+        disc_total: uint("DiscTotal"),
+        year: uint("Year"),
+        date: string("Date"),
+        composer: string("Composer"),
+        comment: string("Comment"),
+        codec: string("Codec"),
+        bitrate: uint("Bitrate"),
+        sample_rate: uint("SampleRate"),
+        bit_depth: uint("BitDepth"),
+        channels: uint("Channels"),
+        // The file did not change since, so neither did its size
+        file_size: stamp.size(),
+        duration: kf.uint64(group, "Duration").unwrap_or(0),
+        uuid: string("Uuid"),
+        cover_uuid: string("CoverUuid"),
+        cover_art: None,
+        file,
+    };
+
+    let palette = kf
+        .string_list(group, "Palette")
+        .map(|colors| {
+            colors
+                .iter()
+                .filter_map(|c| gdk::RGBA::parse(c.as_str()).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Some((stamp, data, palette))
+}
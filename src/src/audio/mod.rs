@@ -49,20 +49,46 @@ pub use inhibit_controller::InhibitController;
 pub use mpris_controller::MprisController;
 
 mod gst_backend;
+mod library;
+mod library_group;
+mod lyrics;
+mod metadata_cache;
+mod smart_playlist;
 pub use gst_backend::GstBackend;
 
+mod play_history;
 mod player;
 mod queue;
+mod radio;
+mod rating;
 mod shuffle;
+mod skip_list;
 mod song;
+mod song_loader;
+mod song_report;
 mod state;
+mod tag_writer;
 mod waveform_generator;
 
+pub use library::Library;
+pub use library_group::{BrowseKind, LibraryGroup};
+pub use lyrics::{find_lyrics, LyricLine, Lyrics, LyricsRequest};
+pub use play_history::{HistoryController, PlayHistory, PlayStats};
 pub use player::{
     AudioPlayer, PlaybackAction, PlaybackState, RepeatMode, ReplayGainMode, SeekDirection,
 };
-pub use queue::Queue;
-pub use shuffle::ShuffleListModel;
-pub use song::Song;
+pub use queue::{cmp_track_order, Queue, SortKey};
+pub use radio::Radio;
+pub use rating::{write_rating, RatingTag, MAX_RATING};
+pub use shuffle::{ShuffleListModel, ShuffleMode};
+pub use skip_list::{SkipKind, SkipList};
+pub use smart_playlist::{
+    load_smart_playlists, store_smart_playlists, FieldKind, Rule, RuleContext, RuleField,
+    RuleOperator, SmartPlaylist, SongFacts,
+};
+pub use song::{Song, SongData};
+pub use song_loader::SongLoader;
+pub use song_report::{cache_section, inspect_file, report_text, ReportRow, ReportSection};
 pub use state::PlayerState;
+pub use tag_writer::{write_tags, CoverChange, TagChanges, TagField};
 pub use waveform_generator::WaveformGenerator;
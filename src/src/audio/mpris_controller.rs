@@ -13,7 +13,7 @@ use log::error;
 use mpris_server::{LoopStatus, Metadata, PlaybackStatus, Player, Time};
 
 use crate::{
-    audio::{Controller, PlaybackAction, PlaybackState, RepeatMode, Song},
+    audio::{Controller, PlaybackAction, PlaybackState, RepeatMode, Song, MAX_RATING},
     config::APPLICATION_ID,
 };
 
@@ -66,13 +66,33 @@ impl MprisController {
         let mut metadata = Metadata::new();
 
         if let Some(song) = self.song.take() {
-            metadata.set_artist(Some(vec![song.artist()]));
+            metadata.set_artist(Some(song.artists()));
             metadata.set_title(Some(song.title()));
             metadata.set_album(Some(song.album()));
+            metadata.set_album_artist(song.album_artist().map(|a| vec![a]));
+            metadata.set_genre(song.genre().map(|g| vec![g]));
+            metadata.set_composer(song.composer().map(|c| vec![c]));
+            metadata.set_comment(song.comment().map(|c| vec![c]));
+            metadata.set_track_number(song.track_number().map(|n| n as i32));
+            metadata.set_disc_number(song.disc_number().map(|n| n as i32));
+            metadata.set_url(Some(song.uri()));
+
+            // The date is meant to be in ISO 8601 format, which is what
+            // taggers write anyway; a year is better than nothing
+            let created = song
+                .date()
+                .or_else(|| song.year().map(|y| format!("{y:04}")));
+            metadata.set_content_created(created);
 
             let length = Time::from_secs(song.duration() as i64);
             metadata.set_length(Some(length));
 
+            // A fraction between 0 and 1; songs we did not rate have none
+            let rating = song.rating();
+            if rating > 0 {
+                metadata.set_user_rating(Some(rating as f64 / MAX_RATING as f64));
+            }
+
             // MPRIS should really support passing a bytes buffer for
             // the cover art, instead of requiring this ridiculous
             // charade
@@ -136,6 +156,10 @@ impl Controller for MprisController {
         self.update_metadata();
     }
 
+    fn refresh_song(&self, song: &Song) {
+        self.set_song(song);
+    }
+
     fn set_position(&self, position: u64, notify: bool) {
         let pos = Time::from_secs(position as i64);
         if let Some(mpris) = self.mpris.get() {
@@ -160,7 +184,9 @@ impl Controller for MprisController {
         let status = match repeat {
             RepeatMode::Consecutive => LoopStatus::None,
             RepeatMode::RepeatOne => LoopStatus::Track,
-            RepeatMode::RepeatAll => LoopStatus::Playlist,
+            // MPRIS has no notion of albums, and looping over one is
+            // closer to looping over a playlist than over a track
+            RepeatMode::RepeatAll | RepeatMode::RepeatAlbum => LoopStatus::Playlist,
         };
 
         glib::spawn_future_local(clone!(
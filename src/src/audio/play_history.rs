@@ -0,0 +1,186 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{cell::RefCell, collections::HashMap, path::PathBuf, rc::Rc, sync::Mutex};
+
+use gtk::glib;
+use log::{debug, warn};
+use once_cell::sync::OnceCell;
+use sha2::{Digest, Sha256};
+
+use crate::audio::{Controller, PlaybackState, RepeatMode, Song};
+
+const HISTORY_VERSION: i64 = 1;
+const HISTORY_GROUP: &str = "history";
+
+// A song counts as played once we heard half of it, or four minutes of
+// it, whichever comes first
+const PLAYED_MAX_SECONDS: u64 = 240;
+
+fn history_path() -> PathBuf {
+    let mut path = glib::user_data_dir();
+    path.push("axuralis");
+    path.push("library");
+    path.push("plays.history");
+    path
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlayStats {
+    pub play_count: u32,
+    // In seconds since the epoch
+    pub last_played: Option<i64>,
+}
+
+// How many times we played each song, and when we last did, keyed by URI;
+// unlike the playback history of the queue, this survives restarts
+#[derive(Debug, Default)]
+pub struct PlayHistory {
+    entries: HashMap<String, PlayStats>,
+    // Whether some plays are not stored yet
+    dirty: bool,
+}
+
+impl PlayHistory {
+    pub fn global() -> &'static Mutex<PlayHistory> {
+        static HISTORY: OnceCell<Mutex<PlayHistory>> = OnceCell::new();
+
+        HISTORY.get_or_init(|| Mutex::new(PlayHistory::load()))
+    }
+
+    fn load() -> Self {
+        let mut res = PlayHistory::default();
+
+        let path = history_path();
+        let kf = glib::KeyFile::new();
+        if let Err(e) = kf.load_from_file(&path, glib::KeyFileFlags::NONE) {
+            debug!("Unable to load play history: {e}");
+            return res;
+        }
+
+        match kf.int64(HISTORY_GROUP, "Version") {
+            Ok(HISTORY_VERSION) => (),
+            _ => {
+                debug!("Discarding play history with a different version");
+                return res;
+            }
+        }
+
+        for group in kf.groups().iter() {
+            let group = group.as_str();
+            if group == HISTORY_GROUP {
+                continue;
+            }
+
+            if let Ok(uri) = kf.string(group, "Uri") {
+                let stats = PlayStats {
+                    play_count: kf.uint64(group, "Count").unwrap_or(0) as u32,
+                    last_played: kf.int64(group, "LastPlayed").ok(),
+                };
+                res.entries.insert(uri.to_string(), stats);
+            }
+        }
+
+        debug!("Loaded the play history of {} songs", res.entries.len());
+
+        res
+    }
+
+    fn save(&self) {
+        let kf = glib::KeyFile::new();
+        kf.set_int64(HISTORY_GROUP, "Version", HISTORY_VERSION);
+        for (uri, stats) in &self.entries {
+            let mut hasher = Sha256::new();
+            hasher.update(uri.as_bytes());
+            let group = format!("{:x}", hasher.finalize());
+
+            kf.set_string(&group, "Uri", uri);
+            kf.set_uint64(&group, "Count", stats.play_count as u64);
+            if let Some(last_played) = stats.last_played {
+                kf.set_int64(&group, "LastPlayed", last_played);
+            }
+        }
+
+        let path = history_path();
+        if let Some(parent) = path.parent() {
+            glib::mkdir_with_parents(parent, 0o755);
+        }
+
+        match kf.save_to_file(&path) {
+            Ok(_) => debug!("Play history stored in: {:?}", &path),
+            Err(e) => warn!("Unable to store play history: {e}"),
+        }
+    }
+
+    pub fn stats(&self, uri: &str) -> PlayStats {
+        self.entries.get(uri).copied().unwrap_or_default()
+    }
+
+    pub fn record_play(&mut self, uri: &str) {
+        let stats = self.entries.entry(uri.to_string()).or_default();
+        stats.play_count += 1;
+        stats.last_played = Some(glib::real_time() / 1_000_000);
+        self.dirty = true;
+    }
+
+    // Writes the plays we did not store yet, e.g. when quitting
+    pub fn flush(&mut self) {
+        if self.dirty {
+            self.dirty = false;
+            self.save();
+        }
+    }
+}
+
+// Records the songs we actually listened to in the PlayHistory
+#[derive(Debug, Default)]
+pub struct HistoryController {
+    // The current song, and whether we already counted it
+    song: RefCell<Option<(Song, bool)>>,
+    save_id: Rc<RefCell<Option<glib::SourceId>>>,
+}
+
+impl HistoryController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Coalesce plays into a single write of the history file
+    fn queue_save(&self) {
+        if let Some(id) = self.save_id.take() {
+            id.remove();
+        }
+
+        let save_id = Rc::clone(&self.save_id);
+        let id = glib::timeout_add_seconds_local_once(5, move || {
+            save_id.replace(None);
+            PlayHistory::global().lock().unwrap().flush();
+        });
+        self.save_id.replace(Some(id));
+    }
+}
+
+impl Controller for HistoryController {
+    fn set_playback_state(&self, _state: &PlaybackState) {}
+
+    fn set_song(&self, song: &Song) {
+        self.song.replace(Some((song.clone(), false)));
+    }
+
+    fn set_position(&self, position: u64, _notify: bool) {
+        let mut current = self.song.borrow_mut();
+        if let Some((song, recorded)) = current.as_mut() {
+            let threshold = (song.duration() / 2).min(PLAYED_MAX_SECONDS);
+            if !*recorded && song.duration() > 0 && position >= threshold {
+                *recorded = true;
+                PlayHistory::global()
+                    .lock()
+                    .unwrap()
+                    .record_play(&song.uri());
+                self.queue_save();
+            }
+        }
+    }
+
+    fn set_repeat_mode(&self, _mode: RepeatMode) {}
+}
@@ -3,8 +3,10 @@
 
 use std::{
     cell::RefCell,
+    collections::{HashMap, HashSet},
     fmt::{self, Display, Formatter},
     rc::Rc,
+    str::FromStr,
 };
 
 use async_channel::{Receiver, Sender};
@@ -15,9 +17,10 @@ use log::{debug, error};
 use crate::{
     application::ApplicationAction,
     audio::{
-        Controller, CoverCache, GstBackend, InhibitController, MprisController, PlayerState, Queue,
-        Song, WaveformGenerator,
+        Controller, CoverCache, GstBackend, HistoryController, InhibitController, Library,
+        MprisController, PlayerState, Queue, Radio, Song, WaveformGenerator,
     },
+    session::Session,
 };
 
 #[derive(Clone, Debug)]
@@ -27,7 +30,9 @@ pub enum PlaybackAction {
     Stop,
     SkipPrevious,
     SkipNext,
+    SkipTo(u32),
 
+    // In milliseconds
     UpdatePosition(u64, bool),
     VolumeChanged(f64),
     Repeat(RepeatMode),
@@ -52,6 +57,7 @@ pub enum RepeatMode {
     Consecutive,
     RepeatAll,
     RepeatOne,
+    RepeatAlbum,
 }
 
 impl Display for RepeatMode {
@@ -60,6 +66,21 @@ impl Display for RepeatMode {
             RepeatMode::Consecutive => write!(f, "consecutive"),
             RepeatMode::RepeatAll => write!(f, "repeat-all"),
             RepeatMode::RepeatOne => write!(f, "repeat-one"),
+            RepeatMode::RepeatAlbum => write!(f, "repeat-album"),
+        }
+    }
+}
+
+impl FromStr for RepeatMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "consecutive" => Ok(RepeatMode::Consecutive),
+            "repeat-all" => Ok(RepeatMode::RepeatAll),
+            "repeat-one" => Ok(RepeatMode::RepeatOne),
+            "repeat-album" => Ok(RepeatMode::RepeatAlbum),
+            _ => Err(format!("invalid repeat mode '{s}'")),
         }
     }
 }
@@ -116,6 +137,7 @@ pub struct AudioPlayer {
     queue: Queue,
     state: PlayerState,
     waveform_generator: WaveformGenerator,
+    radio: Radio,
 }
 
 impl fmt::Debug for AudioPlayer {
@@ -137,9 +159,13 @@ impl AudioPlayer {
         let inhibit_controller = InhibitController::new();
         controllers.push(Box::new(inhibit_controller));
 
+        let history_controller = HistoryController::new();
+        controllers.push(Box::new(history_controller));
+
         let waveform_generator = WaveformGenerator::new();
         controllers.push(Box::new(waveform_generator.clone()));
 
+        let radio = Radio::new(sender.clone());
         let backend = GstBackend::new(sender);
 
         let queue = Queue::default();
@@ -153,6 +179,7 @@ impl AudioPlayer {
             queue,
             state,
             waveform_generator,
+            radio,
         });
 
         res.clone().setup_channel();
@@ -184,6 +211,7 @@ impl AudioPlayer {
             PlaybackAction::Stop => self.set_playback_state(PlaybackState::Stopped),
             PlaybackAction::SkipPrevious => self.skip_previous(),
             PlaybackAction::SkipNext => self.skip_next(),
+            PlaybackAction::SkipTo(pos) => self.skip_to(pos),
             PlaybackAction::UpdatePosition(pos, notify) => self.update_position(pos, notify),
             PlaybackAction::VolumeChanged(vol) => self.update_volume(vol),
             PlaybackAction::PlayNext => self.play_next(),
@@ -294,7 +322,7 @@ impl AudioPlayer {
                 return;
             }
 
-            if self.queue.is_first_song() {
+            if !self.queue.has_history() {
                 return;
             }
 
@@ -359,6 +387,10 @@ impl AudioPlayer {
             if was_playing {
                 self.set_playback_state(PlaybackState::Playing);
             }
+        } else if self.radio.fill(&self.queue) {
+            // The radio will skip to the songs it adds, or go back to
+            // the beginning of the queue if it cannot find any
+            self.set_playback_state(PlaybackState::Stopped);
         } else {
             self.skip_to(0);
             self.set_playback_state(PlaybackState::Stopped);
@@ -448,9 +480,10 @@ impl AudioPlayer {
         self.backend.seek_position(pos as u64);
     }
 
+    // Seeks to `position`, in milliseconds
     pub fn seek_position_abs(&self, position: u64) {
-        let pos = u64::max(position, self.state.duration());
-        self.backend.seek_position(pos);
+        let pos = u64::min(position, self.state.duration() * 1000);
+        self.backend.seek_position_ms(pos);
     }
 
     pub fn queue(&self) -> &Queue {
@@ -465,15 +498,37 @@ impl AudioPlayer {
         &self.waveform_generator
     }
 
+    pub fn set_radio_enabled(&self, enabled: bool) {
+        self.radio.set_enabled(enabled);
+    }
+
+    // The radio picks the songs it adds from the library
+    pub fn set_library(&self, library: &Library) {
+        self.radio.set_library(library);
+    }
+
     pub fn set_current_song(&self, song: Option<Song>) {
         self.state.set_current_song(song);
     }
 
+    // Called after we read the tags of a song again
+    pub fn refresh_song(&self, song: &Song) {
+        if self.state.current_song().as_ref() != Some(song) {
+            return;
+        }
+
+        self.state.refresh_song();
+        for c in &self.controllers {
+            c.refresh_song(song);
+        }
+    }
+
+    // The backend reports milliseconds; the controllers use seconds
     fn update_position(&self, position: u64, notify: bool) {
-        self.state.set_position(position);
+        self.state.set_position_ms(position);
 
         for c in &self.controllers {
-            c.set_position(position, notify);
+            c.set_position(position / 1000, notify);
         }
     }
 
@@ -490,7 +545,8 @@ impl AudioPlayer {
         let cur_mode = self.queue.repeat_mode();
         let new_mode = match cur_mode {
             RepeatMode::Consecutive => RepeatMode::RepeatAll,
-            RepeatMode::RepeatAll => RepeatMode::RepeatOne,
+            RepeatMode::RepeatAll => RepeatMode::RepeatAlbum,
+            RepeatMode::RepeatAlbum => RepeatMode::RepeatOne,
             RepeatMode::RepeatOne => RepeatMode::Consecutive,
         };
         self.queue.set_repeat_mode(new_mode);
@@ -525,18 +581,105 @@ impl AudioPlayer {
         cover_cache.clear();
     }
 
-    pub fn remove_song(&self, song: &Song) {
-        if song.playing() {
-            self.skip_next();
-        }
+    pub fn remove_songs(&self, songs: &[Song]) {
+        // If the current song goes away, we move on to the first song after
+        // it that stays in the queue
+        let removing_current = songs.iter().any(|s| s.playing());
+        let next_song = if removing_current {
+            self.next_remaining_song(songs)
+        } else {
+            None
+        };
+
+        self.queue.remove_songs(songs);
+
+        if removing_current {
+            if let Some(current_song) = self.state.current_song() {
+                current_song.set_playing(false);
+            }
+            self.queue.set_current_song(None);
 
-        self.queue.remove_song(song);
+            match next_song.and_then(|s| self.queue.song_position(&s)) {
+                Some(pos) => self.skip_to(pos),
+                None => {
+                    self.stop();
+                    self.state.set_current_song(None);
+                }
+            }
+        }
 
         if self.queue.is_empty() {
             self.state.set_current_song(None);
         }
     }
 
+    fn next_remaining_song(&self, removed: &[Song]) -> Option<Song> {
+        let removed: HashSet<String> = removed.iter().map(|s| s.uri()).collect();
+        let n_songs = self.queue.n_songs();
+        let current = self.queue.current_song_index()?;
+
+        // With repeat, the queue wraps around to the songs before the
+        // current one
+        let wrap = self.queue.repeat_mode() == RepeatMode::RepeatAll;
+        let positions: Vec<u32> = if wrap {
+            (current + 1..n_songs).chain(0..current).collect()
+        } else {
+            (current + 1..n_songs).collect()
+        };
+
+        positions
+            .into_iter()
+            .filter_map(|pos| self.queue.song_at(pos))
+            .find(|s| !removed.contains(&s.uri()))
+    }
+
+    pub fn restore_session(&self, session: &Session) {
+        // The songs were added again while loading them; sorting by date
+        // added should follow the first time instead
+        let added: HashMap<String, i64> = session
+            .files
+            .iter()
+            .map(|f| f.uri().to_string())
+            .zip(session.added.iter().copied())
+            .collect();
+        for song in self.queue.songs() {
+            if let Some(added) = added.get(&song.uri()).filter(|a| **a > 0) {
+                song.set_added(*added);
+            }
+        }
+
+        if let Some(ref order) = session.shuffle {
+            if !self.queue.set_shuffle_order(order) {
+                debug!("Queue does not match the session, not restoring shuffle order");
+            }
+        }
+
+        self.update_repeat_mode(session.repeat_mode);
+        self.set_volume(session.volume);
+
+        let current_pos = session.current.as_ref().and_then(|current| {
+            (0..self.queue.n_songs())
+                .find(|pos| self.queue.song_at(*pos).unwrap().file().equal(current))
+        });
+
+        if let Some(pos) = current_pos {
+            debug!(
+                "Restoring session at song {} (position: {}s)",
+                pos, session.position
+            );
+            self.skip_to(pos);
+            self.set_playback_state(PlaybackState::Paused);
+
+            let position = u64::min(session.position, self.state.duration());
+            if position > 0 {
+                self.backend.seek_position(position);
+                self.state.set_position(position);
+            }
+        } else {
+            self.skip_to(0);
+        }
+    }
+
     pub fn set_replaygain(&self, replaygain: ReplayGainMode) {
         self.backend.set_replaygain(replaygain);
     }
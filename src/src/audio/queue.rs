@@ -1,11 +1,175 @@
 // SPDX-FileCopyrightText: 2025 Ardox
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::cell::Cell;
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
 
+use glib::clone;
 use gtk::{gio, glib, prelude::*, subclass::prelude::*};
 
-use crate::audio::{RepeatMode, ShuffleListModel, Song};
+use crate::audio::{RepeatMode, ShuffleListModel, ShuffleMode, SkipList, Song, SongData};
+
+// The maximum number of songs we keep in the playback history
+const HISTORY_SIZE: u32 = 50;
+
+#[derive(Clone, Copy, Debug, glib::Enum, PartialEq, Default)]
+#[enum_type(name = "AxuralisSortKey")]
+pub enum SortKey {
+    #[enum_value(name = "title")]
+    Title,
+    #[enum_value(name = "artist")]
+    Artist,
+    #[enum_value(name = "album")]
+    Album,
+    #[enum_value(name = "year")]
+    Year,
+    #[enum_value(name = "duration")]
+    Duration,
+    #[default]
+    #[enum_value(name = "added")]
+    Added,
+    #[enum_value(name = "rating")]
+    Rating,
+}
+
+impl From<i32> for SortKey {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => Self::Title,
+            1 => Self::Artist,
+            2 => Self::Album,
+            3 => Self::Year,
+            4 => Self::Duration,
+            5 => Self::Added,
+            6 => Self::Rating,
+            _ => panic!("invalid SortKey enum key"),
+        }
+    }
+}
+
+impl From<SortKey> for i32 {
+    fn from(value: SortKey) -> Self {
+        match value {
+            SortKey::Title => 0,
+            SortKey::Artist => 1,
+            SortKey::Album => 2,
+            SortKey::Year => 3,
+            SortKey::Duration => 4,
+            SortKey::Added => 5,
+            SortKey::Rating => 6,
+        }
+    }
+}
+
+impl SortKey {
+    pub fn compare(self, a: &Song, b: &Song) -> Ordering {
+        match self {
+            SortKey::Title => cmp_keys(&a.sort_keys().title, &b.sort_keys().title),
+            SortKey::Artist => cmp_keys(&a.sort_keys().artist, &b.sort_keys().artist)
+                .then_with(|| SortKey::Album.compare(a, b)),
+            SortKey::Album => cmp_keys(&a.sort_keys().album, &b.sort_keys().album)
+                .then_with(|| a.album_key().cmp(&b.album_key()))
+                .then_with(|| cmp_track_order(a, b)),
+            SortKey::Year => {
+                cmp_missing_last(a.year(), b.year()).then_with(|| SortKey::Album.compare(a, b))
+            }
+            SortKey::Duration => a.duration().cmp(&b.duration()),
+            SortKey::Added => a.added().cmp(&b.added()),
+            SortKey::Rating => a
+                .rating()
+                .cmp(&b.rating())
+                .then_with(|| SortKey::Artist.compare(a, b)),
+        }
+    }
+}
+
+pub(super) fn cmp_text(a: &str, b: &str) -> Ordering {
+    cmp_keys(&glib::CollationKey::from(a), &glib::CollationKey::from(b))
+}
+
+fn cmp_keys(a: &glib::CollationKey, b: &glib::CollationKey) -> Ordering {
+    a.partial_cmp(b).unwrap()
+}
+
+// Songs without a tag go after the songs that have one
+fn cmp_missing_last<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+// The order of the tracks inside an album, as defined by their tags;
+// songs without a disc number are assumed to be on the first disc
+pub fn cmp_track_order(a: &Song, b: &Song) -> Ordering {
+    let disc_a = a.disc_number().unwrap_or(1);
+    let disc_b = b.disc_number().unwrap_or(1);
+    disc_a
+        .cmp(&disc_b)
+        .then_with(|| cmp_missing_last(a.track_number(), b.track_number()))
+}
+
+// Lookup tables for the songs inside the queue, so that we do not need
+// to scan the whole store to find a song; the same song may be queued
+// more than once, so each key maps to all the matching songs
+#[derive(Debug, Default)]
+pub struct SongIndex {
+    uuids: HashMap<String, Vec<Song>>,
+    uris: HashMap<String, Vec<Song>>,
+    selection_handlers: HashMap<Song, glib::SignalHandlerId>,
+}
+
+impl SongIndex {
+    fn insert(&mut self, song: &Song) {
+        if let Some(uuid) = song.uuid() {
+            self.uuids.entry(uuid).or_default().push(song.clone());
+        }
+        self.uris.entry(song.uri()).or_default().push(song.clone());
+    }
+
+    fn remove(&mut self, song: &Song) {
+        fn remove_from(map: &mut HashMap<String, Vec<Song>>, key: String, song: &Song) {
+            if let Some(songs) = map.get_mut(&key) {
+                songs.retain(|s| s != song);
+                if songs.is_empty() {
+                    map.remove(&key);
+                }
+            }
+        }
+
+        if let Some(uuid) = song.uuid() {
+            remove_from(&mut self.uuids, uuid, song);
+        }
+        remove_from(&mut self.uris, song.uri(), song);
+    }
+
+    // Whether this very song, not just an equal one, is in the index
+    fn contains(&self, song: &Song) -> bool {
+        self.uris
+            .get(&song.uri())
+            .map_or(false, |songs| songs.contains(song))
+    }
+
+    // Follows the same rules as Song::equals(): the UUID wins if both
+    // songs have one, otherwise we compare the URIs
+    fn find(&self, song: &Song) -> Option<Song> {
+        if let Some(uuid) = song.uuid() {
+            if let Some(s) = self.uuids.get(&uuid).and_then(|v| v.first()) {
+                return Some(s.clone());
+            }
+        }
+
+        self.uris
+            .get(&song.uri())
+            .and_then(|v| v.iter().find(|s| s.equals(song)))
+            .cloned()
+    }
+}
 
 mod imp {
     use glib::{ParamSpec, ParamSpecEnum, ParamSpecObject, ParamSpecUInt, Value};
@@ -20,6 +184,13 @@ mod imp {
         pub repeat_mode: Cell<RepeatMode>,
         pub current_pos: Cell<Option<u32>>,
         pub shuffled: Cell<bool>,
+        // The songs we actually played, most recent first
+        pub history: gio::ListStore,
+        pub index: RefCell<SongIndex>,
+        // The position of each song inside the store; this is rebuilt
+        // lazily, the first time we need it after the store changed
+        pub positions: RefCell<Option<HashMap<Song, u32>>>,
+        pub n_selected: Cell<u32>,
     }
 
     #[glib::object_subclass]
@@ -37,11 +208,28 @@ mod imp {
                 repeat_mode: Cell::new(RepeatMode::default()),
                 current_pos: Cell::new(None),
                 shuffled: Cell::new(false),
+                history: gio::ListStore::new::<Song>(),
+                index: RefCell::default(),
+                positions: RefCell::default(),
+                n_selected: Cell::new(0),
             }
         }
     }
 
     impl ObjectImpl for Queue {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            let obj = self.obj();
+            self.store.connect_items_changed(clone!(
+                #[weak(rename_to = this)]
+                obj,
+                move |_, _, _, _| {
+                    this.imp().positions.replace(None);
+                }
+            ));
+        }
+
         fn properties() -> &'static [ParamSpec] {
             static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| {
                 vec![
@@ -108,15 +296,91 @@ impl Queue {
         None
     }
 
+    // The position of the song in the (possibly shuffled) model
+    pub fn song_position(&self, song: &Song) -> Option<u32> {
+        self.store_position(song)
+            .map(|pos| self.imp().model.shuffled_position(pos))
+    }
+
+    // The position of the song in the backing store
+    fn store_position(&self, song: &Song) -> Option<u32> {
+        let song = self.imp().index.borrow().find(song)?;
+
+        let store = &self.imp().store;
+        let mut positions = self.imp().positions.borrow_mut();
+        positions
+            .get_or_insert_with(|| {
+                (0..store.n_items())
+                    .filter_map(|pos| store.item(pos).and_downcast::<Song>().map(|s| (s, pos)))
+                    .collect()
+            })
+            .get(&song)
+            .copied()
+    }
+
+    fn index_song(&self, song: &Song) {
+        let imp = self.imp();
+        imp.index.borrow_mut().insert(song);
+
+        if song.selected() {
+            imp.n_selected.set(imp.n_selected.get() + 1);
+        }
+
+        let handler = song.connect_notify_local(
+            Some("selected"),
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |song, _| {
+                    let n_selected = this.imp().n_selected.get();
+                    if song.selected() {
+                        this.imp().n_selected.set(n_selected + 1);
+                    } else {
+                        this.imp().n_selected.set(n_selected.saturating_sub(1));
+                    }
+                }
+            ),
+        );
+        if let Some(old_handler) = imp
+            .index
+            .borrow_mut()
+            .selection_handlers
+            .insert(song.clone(), handler)
+        {
+            // The same song was queued twice, and we only want to
+            // count it once
+            song.disconnect(old_handler);
+        }
+    }
+
+    fn unindex_song(&self, song: &Song) {
+        let imp = self.imp();
+        let mut index = imp.index.borrow_mut();
+        index.remove(song);
+
+        if let Some(handler) = index.selection_handlers.remove(song) {
+            song.disconnect(handler);
+            if song.selected() {
+                imp.n_selected.set(imp.n_selected.get().saturating_sub(1));
+            }
+        }
+    }
+
+    fn clear_index(&self) {
+        let imp = self.imp();
+        let index = imp.index.take();
+        for (song, handler) in index.selection_handlers {
+            song.disconnect(handler);
+        }
+        imp.positions.replace(None);
+        imp.n_selected.set(0);
+    }
+
     pub fn set_current_song(&self, song: Option<Song>) {
         if let Some(song) = song {
-            for i in 0..self.n_songs() {
-                let s = self.song_at(i).unwrap();
-                if song.equals(&s) {
-                    self.imp().current_pos.replace(Some(i));
-                    self.notify("current");
-                    return;
-                }
+            if let Some(pos) = self.song_position(&song) {
+                self.imp().current_pos.replace(Some(pos));
+                self.notify("current");
             }
         } else {
             self.imp().current_pos.replace(None);
@@ -131,7 +395,10 @@ impl Queue {
     pub fn add_song(&self, song: &Song) -> bool {
         if !song.equals(&Song::default()) {
             // Add song to the backing store
+            song.set_added(glib::real_time());
+            song.set_skip_shuffle(SkipList::load().contains(song));
             self.imp().store.append(song);
+            self.index_song(song);
             self.notify("n-songs");
             true
         } else {
@@ -140,66 +407,182 @@ impl Queue {
     }
 
     pub fn add_songs(&self, songs: &[impl IsA<glib::Object>]) {
+        self.append_songs(songs, &SkipList::load());
+    }
+
+    fn append_songs(&self, songs: &[impl IsA<glib::Object>], skip_list: &SkipList) {
+        let now = glib::real_time();
+        for song in songs {
+            if let Some(song) = song.dynamic_cast_ref::<Song>() {
+                song.set_added(now);
+                song.set_skip_shuffle(skip_list.contains(song));
+                self.index_song(song);
+            }
+        }
+
         self.imp()
             .store
             .splice(self.imp().model.n_items(), 0, songs);
         self.notify("n-songs");
     }
 
-    pub fn remove_song(&self, song: &Song) {
-        let was_shuffled = self.imp().model.shuffled();
-        let n_songs = self.n_songs();
-        for pos in 0..n_songs {
-            let s = self
-                .imp()
-                .store
-                .item(pos)
-                .unwrap()
-                .downcast::<Song>()
-                .unwrap();
-            if s.equals(song) {
-                self.imp().store.remove(pos);
-                break;
-            }
+    // Re-reads the skip list after it changed, and reshuffles the songs
+    // that have not been played yet to honor it
+    pub fn refresh_skip_list(&self) {
+        let skip_list = SkipList::load();
+        for song in self.songs() {
+            song.set_skip_shuffle(skip_list.contains(&song));
+        }
+
+        if self.is_shuffled() {
+            // Like changing the shuffle mode: the anchor is the position
+            // of the current song in the unshuffled order
+            let current_song = self.current_song();
+            self.imp().model.unshuffle();
+            self.set_current_song(current_song);
+
+            let current_pos = self.imp().current_pos.get().unwrap_or(0);
+            self.imp().model.reshuffle(current_pos);
+        }
+    }
+
+    // Removing songs one at a time would shift the rest of the store for
+    // each of them, so we rebuild the store in a single pass instead
+    pub fn remove_songs(&self, songs: &[Song]) {
+        let remove: HashSet<Song> = {
+            let index = self.imp().index.borrow();
+            songs.iter().filter_map(|s| index.find(s)).collect()
+        };
+        if remove.is_empty() {
+            return;
+        }
+
+        let current_song = self.current_song();
+
+        // The shuffled order is made of positions inside the store; the
+        // songs that remain keep their place in it, like when sorting
+        let model = &self.imp().model;
+        let shuffled: Option<Vec<Song>> = model.shuffled().then(|| {
+            (0..model.n_items())
+                .filter_map(|i| model.item(i).and_downcast::<Song>())
+                .filter(|s| !remove.contains(s))
+                .collect()
+        });
+        if shuffled.is_some() {
+            model.unshuffle();
         }
 
-        if n_songs != self.n_songs() {
-            if was_shuffled {
-                self.imp().model.reshuffle(0);
+        let store = &self.imp().store;
+        if remove.len() == 1 {
+            if let Some(pos) = remove.iter().next().and_then(|s| self.store_position(s)) {
+                store.remove(pos);
             }
-            self.notify("n-songs");
+        } else {
+            let remaining: Vec<Song> = self
+                .songs()
+                .into_iter()
+                .filter(|s| !remove.contains(s))
+                .collect();
+            store.splice(0, store.n_items(), &remaining);
         }
 
+        for song in &remove {
+            self.unindex_song(song);
+            self.remove_from_history(song);
+        }
+
+        if let Some(shuffled) = shuffled {
+            let songs = self.songs();
+            let positions: HashMap<&Song, u32> = songs
+                .iter()
+                .enumerate()
+                .map(|(i, s)| (s, i as u32))
+                .collect();
+            let order: Vec<u32> = shuffled
+                .iter()
+                .filter_map(|s| positions.get(s).copied())
+                .collect();
+            model.set_permutation(order);
+        }
+        self.notify("n-songs");
+
+        // The current song may have moved, but it is still the same song
         if self.is_empty() {
             self.imp().current_pos.replace(None);
+        } else if let Some(song) = current_song.filter(|s| !remove.contains(s)) {
+            let pos = self.song_position(&song);
+            self.imp().current_pos.replace(pos);
         }
     }
 
     pub fn clear(&self) {
         self.imp().current_pos.replace(None);
         self.imp().store.remove_all();
+        self.imp().history.remove_all();
+        self.clear_index();
         self.notify("n-songs");
     }
 
     pub fn skip_song(&self, pos: u32) -> Option<Song> {
+        if Some(pos) != self.imp().current_pos.get() {
+            self.push_history();
+        }
         self.imp().current_pos.replace(Some(pos));
         self.notify("current");
         self.song_at(pos)
     }
 
+    // The previous song is the last song we played, not the one that comes
+    // before the current song in the queue, which we may have never heard
     pub fn previous_song(&self) -> Option<Song> {
-        if let Some(current_pos) = self.imp().current_pos.get() {
-            if current_pos > 0 {
-                let prev = current_pos - 1;
-                self.imp().current_pos.replace(Some(prev));
+        let history = &self.imp().history;
+        while let Some(song) = history.item(0).and_downcast::<Song>() {
+            history.remove(0);
+
+            if let Some(pos) = self.song_position(&song) {
+                self.imp().current_pos.replace(Some(pos));
                 self.notify("current");
-                return self.song_at(current_pos - 1);
+                return Some(song);
             }
         }
 
         None
     }
 
+    pub fn history(&self) -> &gio::ListStore {
+        &self.imp().history
+    }
+
+    pub fn has_history(&self) -> bool {
+        self.imp().history.n_items() > 0
+    }
+
+    // Moves the current song at the top of the playback history
+    fn push_history(&self) {
+        if let Some(song) = self.current_song() {
+            self.remove_from_history(&song);
+
+            let history = &self.imp().history;
+            history.insert(0, &song);
+            if history.n_items() > HISTORY_SIZE {
+                history.remove(HISTORY_SIZE);
+            }
+        }
+    }
+
+    fn remove_from_history(&self, song: &Song) {
+        let history = &self.imp().history;
+        let mut pos = 0;
+        while pos < history.n_items() {
+            let s = history.item(pos).and_downcast::<Song>().unwrap();
+            if s.equals(song) {
+                history.remove(pos);
+            } else {
+                pos += 1;
+            }
+        }
+    }
+
     pub fn next_song(&self) -> Option<Song> {
         let store = &self.imp().model;
 
@@ -210,14 +593,25 @@ impl Queue {
 
         let repeat_mode = self.imp().repeat_mode.get();
         if let Some(current) = self.current_song_index() {
-            let next: Option<u32> = match repeat_mode {
-                RepeatMode::Consecutive if current < n_songs - 1 => Some(current + 1),
-                RepeatMode::Consecutive if current == n_songs - 1 => None,
-                RepeatMode::RepeatOne => Some(current),
-                RepeatMode::RepeatAll if current < n_songs - 1 => Some(current + 1),
-                RepeatMode::RepeatAll if current == n_songs - 1 => Some(0),
-                _ => None,
-            };
+            let mut next = self.next_position(current, repeat_mode);
+
+            // Songs on the skip list are never played in shuffled order;
+            // stop looking once we have been through the whole queue
+            if self.is_shuffled() && repeat_mode != RepeatMode::RepeatOne {
+                let mut n_steps = 0;
+                while let Some(pos) = next {
+                    if n_steps >= n_songs || !self.song_at(pos).map_or(false, |s| s.skip_shuffle())
+                    {
+                        break;
+                    }
+                    next = self.next_position(pos, repeat_mode);
+                    n_steps += 1;
+                }
+            }
+
+            if next != Some(current) {
+                self.push_history();
+            }
 
             if let Some(next) = next {
                 self.imp().current_pos.replace(Some(next));
@@ -236,6 +630,44 @@ impl Queue {
         }
     }
 
+    fn next_position(&self, current: u32, repeat_mode: RepeatMode) -> Option<u32> {
+        let n_songs = self.imp().model.n_items();
+        match repeat_mode {
+            RepeatMode::Consecutive if current < n_songs - 1 => Some(current + 1),
+            RepeatMode::Consecutive if current == n_songs - 1 => None,
+            RepeatMode::RepeatOne => Some(current),
+            RepeatMode::RepeatAll if current < n_songs - 1 => Some(current + 1),
+            RepeatMode::RepeatAll if current == n_songs - 1 => Some(0),
+            RepeatMode::RepeatAlbum => {
+                let (start, end) = self.album_run(current);
+                if current + 1 < end {
+                    Some(current + 1)
+                } else {
+                    Some(start)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // The contiguous run of songs around the given position that share
+    // the same album, as a half-open range
+    fn album_run(&self, pos: u32) -> (u32, u32) {
+        let key = |i: u32| self.song_at(i).map(|s| s.album_key());
+        let album = key(pos);
+
+        let mut start = pos;
+        while start > 0 && key(start - 1) == album {
+            start -= 1;
+        }
+        let mut end = pos + 1;
+        while end < self.n_songs() && key(end) == album {
+            end += 1;
+        }
+
+        (start, end)
+    }
+
     pub fn repeat_mode(&self) -> RepeatMode {
         self.imp().repeat_mode.get()
     }
@@ -285,6 +717,113 @@ impl Queue {
         }
     }
 
+    pub fn shuffle_mode(&self) -> ShuffleMode {
+        self.imp().model.shuffle_mode()
+    }
+
+    pub fn set_shuffle_mode(&self, mode: ShuffleMode) {
+        if mode == self.imp().model.shuffle_mode() {
+            return;
+        }
+
+        self.imp().model.set_shuffle_mode(mode);
+
+        if self.imp().shuffled.get() {
+            // Reshuffle around the current song, which needs to be
+            // anchored at its position in the unshuffled order
+            let current_song = self.current_song();
+            self.imp().model.unshuffle();
+            self.set_current_song(current_song);
+
+            let current_pos = self.imp().current_pos.get().unwrap_or(0);
+            self.imp().model.reshuffle(current_pos);
+        }
+    }
+
+    pub fn shuffle_order(&self) -> Option<Vec<u32>> {
+        if self.imp().shuffled.get() {
+            self.imp().model.permutation()
+        } else {
+            None
+        }
+    }
+
+    pub fn set_shuffle_order(&self, order: &[u32]) -> bool {
+        if order.len() != self.imp().store.n_items() as usize {
+            return false;
+        }
+
+        let current_song = self.current_song();
+        self.imp().shuffled.set(true);
+        self.imp().model.set_permutation(order.to_vec());
+        if current_song.is_some() {
+            self.set_current_song(current_song);
+        }
+
+        true
+    }
+
+    // The songs in the order of the queue, regardless of the shuffle state
+    pub fn songs(&self) -> Vec<Song> {
+        let store = &self.imp().store;
+        (0..store.n_items())
+            .filter_map(|i| store.item(i).and_downcast::<Song>())
+            .collect()
+    }
+
+    // Sorts the backing store, which is the order of the queue when it
+    // is not shuffled; a shuffled queue keeps playing in the same order
+    pub fn sort(&self, key: SortKey, descending: bool) {
+        let mut songs = self.songs();
+        if songs.len() < 2 {
+            return;
+        }
+
+        // Sorting is stable, so songs that compare equal keep their
+        // relative order
+        songs.sort_by(|a, b| {
+            let order = key.compare(a, b);
+            if descending {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+
+        let current_song = self.current_song();
+
+        // The shuffled order is made of positions inside the store, so it
+        // has to follow the songs to their new positions
+        let model = &self.imp().model;
+        let shuffled: Option<Vec<Song>> = model.shuffled().then(|| {
+            (0..model.n_items())
+                .filter_map(|i| model.item(i).and_downcast::<Song>())
+                .collect()
+        });
+        if shuffled.is_some() {
+            model.unshuffle();
+        }
+
+        self.imp().store.splice(0, songs.len() as u32, &songs);
+
+        if let Some(shuffled) = shuffled {
+            let positions: HashMap<&Song, u32> = songs
+                .iter()
+                .enumerate()
+                .map(|(i, s)| (s, i as u32))
+                .collect();
+            let order: Vec<u32> = shuffled
+                .iter()
+                .filter_map(|s| positions.get(s).copied())
+                .collect();
+            model.set_permutation(order);
+        }
+
+        if current_song.is_some() {
+            self.set_current_song(current_song);
+        }
+    }
+
     pub fn select_song_at(&self, index: u32) {
         if let Some(song) = self.imp().model.item(index) {
             let song = song.downcast_ref::<Song>().unwrap();
@@ -301,25 +840,149 @@ impl Queue {
     }
 
     pub fn n_selected_songs(&self) -> u32 {
-        let mut count = 0;
-        for i in 0..self.imp().store.n_items() {
-            let song = self.imp().store.item(i).unwrap();
-            if song.downcast_ref::<Song>().unwrap().selected() {
-                count += 1;
-            }
-        }
-
-        count
+        self.imp().n_selected.get()
     }
 
     pub fn contains(&self, s: &Song) -> bool {
-        for i in 0..self.imp().store.n_items() {
-            let song = self.imp().store.item(i).unwrap();
-            if song.downcast_ref::<Song>().unwrap().equals(s) {
-                return true;
+        self.imp().index.borrow().find(s).is_some()
+    }
+
+    // The songs for the given file, or for the files inside the given
+    // folder
+    pub fn songs_in(&self, file: &gio::File) -> Vec<Song> {
+        self.songs()
+            .into_iter()
+            .filter(|s| {
+                let f = s.file();
+                f.equal(file) || f.has_prefix(file)
+            })
+            .collect()
+    }
+
+    // Points a song to the new location of its file, without touching its
+    // position in the queue or in the history
+    pub fn relocate_song(&self, song: &Song, file: &gio::File) {
+        let mut index = self.imp().index.borrow_mut();
+        index.remove(song);
+        song.set_file(file);
+        index.insert(song);
+    }
+
+    // Uses the tags we read again for a song; its UUID comes from the
+    // tags, so a song in the queue is indexed again
+    pub fn reload_song(&self, song: &Song, data: SongData) {
+        let queued = {
+            let mut index = self.imp().index.borrow_mut();
+            let queued = index.contains(song);
+            if queued {
+                index.remove(song);
             }
+            queued
+        };
+
+        song.set_reloaded_data(data);
+
+        if queued {
+            self.imp().index.borrow_mut().insert(song);
         }
+    }
+}
 
-        false
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn test_remove_shuffled_songs() {
+        let songs: Vec<Song> = (0..6)
+            .map(|i| {
+                Song::with_tags(
+                    &format!("file:///music/{i}.flac"),
+                    &format!("Song {i}"),
+                    "Artist",
+                    "Album",
+                )
+            })
+            .collect();
+        let queue = Queue::default();
+        queue.append_songs(&songs, &SkipList::default());
+        assert!(queue.set_shuffle_order(&[4, 1, 5, 0, 3, 2]));
+
+        // The songs that remain keep their shuffled order
+        queue.remove_songs(&[songs[5].clone(), songs[0].clone()]);
+        let order: Vec<Song> = (0..queue.n_songs())
+            .filter_map(|i| queue.song_at(i))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                songs[4].clone(),
+                songs[1].clone(),
+                songs[3].clone(),
+                songs[2].clone()
+            ]
+        );
+    }
+
+    // Not a correctness test: this loads a large generated queue the way
+    // the window does, to keep an eye on how long it takes. Run it with:
+    //
+    //   cargo test --release bench_large_queue -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_large_queue() {
+        const N_SONGS: u32 = 20_000;
+
+        let songs: Vec<Song> = (0..N_SONGS)
+            .map(|i| {
+                Song::with_tags(
+                    &format!(
+                        "file:///music/artist-{}/album-{}/{i:05}.flac",
+                        i % 97,
+                        i % 1013
+                    ),
+                    &format!("Song {i}"),
+                    &format!("Artist {}", i % 97),
+                    &format!("Album {}", i % 1013),
+                )
+            })
+            .collect();
+
+        let queue = Queue::default();
+        let skip_list = SkipList::default();
+
+        let now = Instant::now();
+        for chunk in songs.chunks(1000) {
+            let new_songs: Vec<Song> = chunk
+                .iter()
+                .filter(|s| !queue.contains(s))
+                .cloned()
+                .collect();
+            queue.append_songs(&new_songs, &skip_list);
+        }
+        println!("Loaded {} songs: {} ms", N_SONGS, now.elapsed().as_millis());
+        assert_eq!(queue.n_songs(), N_SONGS);
+
+        // Loading the same songs again only finds duplicates
+        let now = Instant::now();
+        assert!(songs.iter().all(|s| queue.contains(s)));
+        println!("Checked duplicates: {} ms", now.elapsed().as_millis());
+
+        let now = Instant::now();
+        for song in songs.iter().step_by(100) {
+            queue.set_current_song(Some(song.clone()));
+            song.set_selected(true);
+        }
+        assert_eq!(queue.n_selected_songs(), N_SONGS / 100);
+        println!("Looked up songs: {} ms", now.elapsed().as_millis());
+
+        let now = Instant::now();
+        let selected: Vec<Song> = songs.iter().filter(|s| s.selected()).cloned().collect();
+        queue.remove_songs(&selected);
+        assert_eq!(queue.n_songs(), N_SONGS - N_SONGS / 100);
+        assert_eq!(queue.n_selected_songs(), 0);
+        println!("Removed selected songs: {} ms", now.elapsed().as_millis());
     }
 }
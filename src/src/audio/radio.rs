@@ -0,0 +1,210 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashSet, VecDeque},
+    rc::Rc,
+};
+
+use async_channel::Sender;
+use gtk::{gio, glib, prelude::*};
+use log::{debug, error};
+use rand::prelude::*;
+
+use crate::audio::{Library, PlaybackAction, Queue, SkipList, Song, SongLoader};
+
+// How many songs we append to the queue every time it runs out
+const RADIO_BATCH: usize = 5;
+// How many recently played songs we compare the candidates to
+const RADIO_SEEDS: u32 = 10;
+// How many of the songs we added we remember, to avoid repeating them
+const RADIO_MEMORY: usize = 200;
+// How similar to the recent songs a candidate needs to be, all seeds
+// together; a shared genre alone is enough, a close year is not
+const RADIO_MIN_SCORE: u32 = 2;
+// How far apart the years of two songs can be for them to match
+const RADIO_YEAR_RANGE: u32 = 5;
+
+// What the recently played songs have in common with a candidate
+struct RadioSeeds {
+    artists: HashSet<String>,
+    genres: HashSet<String>,
+    years: Vec<u32>,
+}
+
+impl RadioSeeds {
+    fn new(seeds: &[Song]) -> Self {
+        Self {
+            artists: seeds
+                .iter()
+                .flat_map(|s| s.tagged_artists())
+                .map(|a| a.to_lowercase())
+                .collect(),
+            genres: seeds
+                .iter()
+                .filter_map(|s| s.genre())
+                .map(|g| g.to_lowercase())
+                .collect(),
+            years: seeds.iter().filter_map(|s| s.year()).collect(),
+        }
+    }
+
+    fn matches(&self, song: &Song) -> bool {
+        song.tagged_artists()
+            .iter()
+            .any(|a| self.artists.contains(&a.to_lowercase()))
+            || song
+                .genre()
+                .map_or(false, |g| self.genres.contains(&g.to_lowercase()))
+            || song.year().map_or(false, |year| {
+                self.years
+                    .iter()
+                    .any(|y| y.abs_diff(year) <= RADIO_YEAR_RANGE)
+            })
+    }
+}
+
+// The radio keeps the queue going once it reaches its end, by appending
+// songs from the library that are similar to the ones we played
+#[derive(Debug)]
+pub struct Radio {
+    sender: Sender<PlaybackAction>,
+    enabled: Cell<bool>,
+    busy: Rc<Cell<bool>>,
+    recent: Rc<RefCell<VecDeque<String>>>,
+    library: RefCell<Option<Library>>,
+}
+
+impl Radio {
+    pub fn new(sender: Sender<PlaybackAction>) -> Self {
+        Self {
+            sender,
+            enabled: Cell::new(false),
+            busy: Rc::new(Cell::new(false)),
+            recent: Rc::new(RefCell::new(VecDeque::new())),
+            library: RefCell::default(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.set(enabled);
+    }
+
+    pub fn set_library(&self, library: &Library) {
+        self.library.replace(Some(library.clone()));
+    }
+
+    // Starts looking for songs to append to the queue; returns false if
+    // the radio is not going to add anything
+    pub fn fill(&self, queue: &Queue) -> bool {
+        if !self.enabled.get() || self.busy.get() {
+            return false;
+        }
+
+        let library = match self.library.borrow().as_ref() {
+            Some(library) => library.clone(),
+            None => return false,
+        };
+
+        // The songs we just played are the best indication of what the
+        // user wants to listen to next
+        let history = queue.history();
+        let seeds: Vec<Song> = (0..history.n_items().min(RADIO_SEEDS))
+            .filter_map(|i| history.item(i).and_downcast::<Song>())
+            .collect();
+        if seeds.is_empty() {
+            return false;
+        }
+
+        let mut exclude: HashSet<String> = queue.songs().iter().map(|s| s.uri()).collect();
+        exclude.extend(self.recent.borrow().iter().cloned());
+
+        // The radio picks songs at random, so it honors the shuffle skip
+        // list as well
+        let skip_list = SkipList::load();
+        let radio_seeds = RadioSeeds::new(&seeds);
+        let mut candidates: Vec<Song> = library
+            .songs()
+            .into_iter()
+            .filter(|s| !exclude.contains(&s.uri()))
+            .filter(|s| radio_seeds.matches(s) && !skip_list.contains(s))
+            .collect();
+        candidates.shuffle(&mut thread_rng());
+
+        let mut scored: Vec<(u32, Song)> = candidates
+            .into_iter()
+            .map(|song| (seeds.iter().map(|s| s.similarity(&song)).sum(), song))
+            .filter(|(score, _)| *score >= RADIO_MIN_SCORE)
+            .collect();
+
+        // The candidates are in random order, and the sort is stable, so
+        // songs with the same score are picked at random
+        scored.sort_by(|a, b| b.0.cmp(&a.0));
+        let files: Vec<gio::File> = scored
+            .into_iter()
+            .take(RADIO_BATCH)
+            .map(|(_, song)| song.file())
+            .collect();
+
+        let queue = queue.clone();
+        let sender = self.sender.clone();
+        let busy = self.busy.clone();
+        let recent = self.recent.clone();
+
+        busy.set(true);
+        glib::MainContext::default().spawn_local(async move {
+            // The library keeps its own songs; the queue gets new ones,
+            // loaded like any other file we add
+            let loader = SongLoader::new(files);
+            let mut loaded = Vec::new();
+            loop {
+                let batch = loader.next_batch(RADIO_BATCH).await;
+                if batch.is_empty() {
+                    break;
+                }
+                loaded.extend(batch);
+            }
+
+            loaded.sort_by_key(|(pos, _)| *pos);
+            let songs: Vec<Song> = loaded
+                .into_iter()
+                .filter_map(|(_, data)| Song::from_data(data).ok())
+                .collect();
+
+            busy.set(false);
+
+            let actions = if songs.is_empty() {
+                debug!("Radio: no songs found");
+                vec![PlaybackAction::SkipTo(0), PlaybackAction::Stop]
+            } else {
+                debug!("Radio: adding {} songs", songs.len());
+
+                let mut recent = recent.borrow_mut();
+                for song in &songs {
+                    song.set_auto_added(true);
+                    recent.push_back(song.uri());
+                }
+                while recent.len() > RADIO_MEMORY {
+                    recent.pop_front();
+                }
+
+                let first = queue.n_songs();
+                queue.add_songs(&songs);
+                vec![PlaybackAction::SkipTo(first), PlaybackAction::Play]
+            };
+
+            for action in actions {
+                if let Err(e) = sender.send(action).await {
+                    error!("Unable to send action: {e}");
+                }
+            }
+        });
+
+        true
+    }
+}
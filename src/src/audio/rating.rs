@@ -0,0 +1,363 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use gtk::glib;
+use lofty::{
+    config::WriteOptions,
+    id3::v2::{FrameFlags, PopularimeterFrame},
+    prelude::{TagExt, TaggedFileExt},
+    tag::{ItemKey, ItemValue, Tag, TagItem, TagType},
+};
+use log::{debug, warn};
+use once_cell::sync::OnceCell;
+use sha2::{Digest, Sha256};
+
+pub const MAX_RATING: u32 = 5;
+
+const RATINGS_VERSION: i64 = 1;
+const RATINGS_GROUP: &str = "ratings";
+
+// Windows Media Player writes this in the POPM frames it owns, and most
+// players read the rating from that frame first
+const POPM_EMAIL: &str = "Windows Media Player 9 Series";
+
+fn ratings_path() -> PathBuf {
+    let mut path = glib::user_data_dir();
+    path.push("axuralis");
+    path.push("library");
+    path.push("ratings.ini");
+    path
+}
+
+// Where ratings are written in the tags of a song; they are read from any
+// of these. ID3v2 tags always get a POPM frame
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum RatingTag {
+    // FMPS_RATING, except in ID3v2 tags
+    #[default]
+    Auto,
+    Fmps,
+    // A percentage, as some Vorbis comment taggers do
+    Rating,
+    // Ratings are only kept by us
+    None,
+}
+
+impl From<i32> for RatingTag {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => Self::Auto,
+            1 => Self::Fmps,
+            2 => Self::Rating,
+            3 => Self::None,
+            _ => panic!("invalid RatingTag enum key"),
+        }
+    }
+}
+
+impl From<RatingTag> for i32 {
+    fn from(value: RatingTag) -> Self {
+        match value {
+            RatingTag::Auto => 0,
+            RatingTag::Fmps => 1,
+            RatingTag::Rating => 2,
+            RatingTag::None => 3,
+        }
+    }
+}
+
+// The byte of a POPM frame, where 0 means "not rated"; the ranges follow
+// what Windows Media Player and most taggers do
+fn stars_from_popm(value: u8) -> u32 {
+    match value {
+        0 => 0,
+        1..=31 => 1,
+        32..=95 => 2,
+        96..=159 => 3,
+        160..=223 => 4,
+        _ => 5,
+    }
+}
+
+fn popm_from_stars(rating: u32) -> u8 {
+    match rating {
+        0 => 0,
+        1 => 1,
+        2 => 64,
+        3 => 128,
+        4 => 196,
+        _ => 255,
+    }
+}
+
+// FMPS_RATING is a fraction between 0 and 1
+fn stars_from_fmps(value: &str) -> Option<u32> {
+    let value = value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|v| (0.0..=1.0).contains(v))?;
+    Some((value * MAX_RATING as f64).round() as u32)
+}
+
+// RATING is usually a percentage, but some taggers write a number of
+// stars; anything above 5 cannot be a number of stars
+fn stars_from_rating(value: &str) -> Option<u32> {
+    let value = value.trim().parse::<u32>().ok()?;
+    if value <= MAX_RATING {
+        Some(value)
+    } else if value <= 100 {
+        Some((value + 10) / 20)
+    } else {
+        None
+    }
+}
+
+// Taggers disagree on the case of these names, and MP4 keeps them in
+// freeform atoms
+fn is_rating_key(key: &ItemKey, name: &str) -> bool {
+    match key {
+        ItemKey::Unknown(key) => key
+            .rsplit(':')
+            .next()
+            .is_some_and(|k| k.eq_ignore_ascii_case(name)),
+        _ => false,
+    }
+}
+
+// The key of a rating written as text; MP4 files have no atom for it, so
+// it goes in a freeform one, as other taggers do
+fn rating_key(tag_type: TagType, name: &str) -> ItemKey {
+    match tag_type {
+        TagType::Mp4Ilst => ItemKey::Unknown(format!("----:com.apple.iTunes:{name}")),
+        _ => ItemKey::Unknown(name.to_string()),
+    }
+}
+
+fn rating_text<'a>(tag: &'a Tag, name: &str) -> Option<&'a str> {
+    tag.items()
+        .find(|item| is_rating_key(item.key(), name))
+        .and_then(|item| item.value().text())
+}
+
+// The rating byte and the play counter of the POPM frame of ID3v2 tags;
+// lofty keeps the frame as binary data
+fn popularimeter(tag: &Tag) -> Option<(u8, u64)> {
+    match tag.get(&ItemKey::Popularimeter).map(|item| item.value()) {
+        Some(ItemValue::Binary(data)) => {
+            PopularimeterFrame::parse(&mut &data[..], FrameFlags::default())
+                .ok()
+                .map(|frame| (frame.rating, frame.counter))
+        }
+        _ => None,
+    }
+}
+
+// The number of stars in a tag, if it has any
+pub(super) fn tag_rating(tag: &Tag) -> Option<u32> {
+    if let Some((rating, _)) = popularimeter(tag).filter(|(rating, _)| *rating > 0) {
+        return Some(stars_from_popm(rating));
+    }
+
+    rating_text(tag, "FMPS_RATING")
+        .and_then(stars_from_fmps)
+        .or_else(|| rating_text(tag, "RATING").and_then(stars_from_rating))
+        .map(|r| r.min(MAX_RATING))
+}
+
+// Writes the rating into the primary tag of a local file, replacing the
+// ratings written in the other ways; 0 removes the rating. The rating is
+// read back, so that we never lose one that the tag could not hold
+pub fn write_rating(path: &Path, rating: u32, format: RatingTag) -> Result<(), String> {
+    if format == RatingTag::None {
+        return Err("ratings are not written to tags".to_string());
+    }
+
+    let mut tagged_file = lofty::read_from_path(path).map_err(|e| e.to_string())?;
+
+    if tagged_file.primary_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+    let tag = tagged_file.primary_tag_mut().unwrap();
+
+    // The play counter of the POPM frame is not ours to lose
+    let counter = popularimeter(tag).map(|(_, counter)| counter).unwrap_or(0);
+    tag.remove_key(&ItemKey::Popularimeter);
+    tag.retain(|item| {
+        !is_rating_key(item.key(), "FMPS_RATING") && !is_rating_key(item.key(), "RATING")
+    });
+
+    let rating = rating.min(MAX_RATING);
+    let tag_type = tag.tag_type();
+    if rating > 0 && tag_type == TagType::Id3v2 {
+        // Text ratings would need TXXX frames, which the generic tag does
+        // not write, so ID3v2 always gets a POPM frame
+        let frame =
+            PopularimeterFrame::new(POPM_EMAIL.to_string(), popm_from_stars(rating), counter);
+        tag.insert(TagItem::new(
+            ItemKey::Popularimeter,
+            ItemValue::Binary(frame.as_bytes()),
+        ));
+    } else if rating > 0 && format == RatingTag::Rating {
+        tag.insert_text(rating_key(tag_type, "RATING"), (rating * 20).to_string());
+    } else if rating > 0 {
+        tag.insert_text(
+            rating_key(tag_type, "FMPS_RATING"),
+            format!("{:.1}", rating as f64 / MAX_RATING as f64),
+        );
+    }
+
+    tag.save_to_path(path, WriteOptions::default())
+        .map_err(|e| e.to_string())?;
+
+    let tagged_file = lofty::read_from_path(path).map_err(|e| e.to_string())?;
+    let written = tagged_file.primary_tag().and_then(tag_rating).unwrap_or(0);
+    if written != rating {
+        return Err(format!(
+            "the rating reads back as {written} instead of {rating}"
+        ));
+    }
+
+    Ok(())
+}
+
+// The ratings we could not write into the songs, because they are not
+// local files, or are read-only, or because we were asked not to; these
+// win over the ratings in the tags. Keyed by URI
+#[derive(Debug, Default)]
+pub struct RatingStore {
+    entries: HashMap<String, u32>,
+}
+
+impl RatingStore {
+    pub fn global() -> &'static Mutex<RatingStore> {
+        static RATINGS: OnceCell<Mutex<RatingStore>> = OnceCell::new();
+
+        RATINGS.get_or_init(|| Mutex::new(RatingStore::load()))
+    }
+
+    fn load() -> Self {
+        let mut res = RatingStore::default();
+
+        let path = ratings_path();
+        let kf = glib::KeyFile::new();
+        if let Err(e) = kf.load_from_file(&path, glib::KeyFileFlags::NONE) {
+            debug!("Unable to load ratings: {e}");
+            return res;
+        }
+
+        match kf.int64(RATINGS_GROUP, "Version") {
+            Ok(RATINGS_VERSION) => (),
+            _ => {
+                debug!("Discarding ratings with a different version");
+                return res;
+            }
+        }
+
+        for group in kf.groups().iter() {
+            let group = group.as_str();
+            if group == RATINGS_GROUP {
+                continue;
+            }
+
+            if let (Ok(uri), Ok(rating)) = (kf.string(group, "Uri"), kf.uint64(group, "Rating")) {
+                res.entries
+                    .insert(uri.to_string(), (rating as u32).min(MAX_RATING));
+            }
+        }
+
+        debug!("Loaded the ratings of {} songs", res.entries.len());
+
+        res
+    }
+
+    fn save(&self) {
+        let kf = glib::KeyFile::new();
+        kf.set_int64(RATINGS_GROUP, "Version", RATINGS_VERSION);
+        for (uri, rating) in &self.entries {
+            let mut hasher = Sha256::new();
+            hasher.update(uri.as_bytes());
+            let group = format!("{:x}", hasher.finalize());
+
+            kf.set_string(&group, "Uri", uri);
+            kf.set_uint64(&group, "Rating", *rating as u64);
+        }
+
+        let path = ratings_path();
+        if let Some(parent) = path.parent() {
+            glib::mkdir_with_parents(parent, 0o755);
+        }
+
+        match kf.save_to_file(&path) {
+            Ok(_) => debug!("Ratings stored in: {:?}", &path),
+            Err(e) => warn!("Unable to store ratings: {e}"),
+        }
+    }
+
+    // 0 is a rating too: it hides the one in the tags
+    pub fn rating(&self, uri: &str) -> Option<u32> {
+        self.entries.get(uri).copied()
+    }
+
+    pub fn set_rating(&mut self, uri: &str, rating: u32) {
+        self.entries.insert(uri.to_string(), rating.min(MAX_RATING));
+        self.save();
+    }
+
+    pub fn remove(&mut self, uri: &str) {
+        if self.entries.remove(uri).is_some() {
+            self.save();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rating_conversions() {
+        for rating in 0..=MAX_RATING {
+            assert_eq!(stars_from_popm(popm_from_stars(rating)), rating);
+        }
+        assert_eq!(stars_from_popm(200), 4);
+
+        assert_eq!(stars_from_fmps("0.8"), Some(4));
+        assert_eq!(stars_from_fmps(" 1.0 "), Some(5));
+        assert_eq!(stars_from_fmps("0.5"), Some(3));
+        assert_eq!(stars_from_fmps("2"), None);
+
+        assert_eq!(stars_from_rating("3"), Some(3));
+        assert_eq!(stars_from_rating("80"), Some(4));
+        assert_eq!(stars_from_rating("100"), Some(5));
+        assert_eq!(stars_from_rating("255"), None);
+    }
+
+    #[test]
+    fn test_tag_rating() {
+        let mut tag = Tag::new(TagType::VorbisComments);
+        assert_eq!(tag_rating(&tag), None);
+
+        tag.insert_text(ItemKey::Unknown("RATING".to_string()), "60".to_string());
+        assert_eq!(tag_rating(&tag), Some(3));
+
+        // FMPS_RATING wins, whatever its case
+        tag.insert_text(ItemKey::Unknown("fmps_rating".to_string()), "1".to_string());
+        assert_eq!(tag_rating(&tag), Some(5));
+
+        // MP4 files keep them in freeform atoms
+        let mut tag = Tag::new(TagType::Mp4Ilst);
+        tag.insert_text(
+            rating_key(TagType::Mp4Ilst, "FMPS_RATING"),
+            "0.4".to_string(),
+        );
+        assert_eq!(tag_rating(&tag), Some(2));
+    }
+}
@@ -1,12 +1,187 @@
 // SPDX-FileCopyrightText: 2022  Emmanuele Bassi
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::cell::RefCell;
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::BTreeMap,
+};
 
 use glib::clone;
 use gtk::{gio, glib, prelude::*, subclass::prelude::*};
 use rand::prelude::*;
 
+use crate::audio::{cmp_track_order, Song};
+
+#[derive(Clone, Copy, Debug, glib::Enum, PartialEq, Default)]
+#[enum_type(name = "AxuralisShuffleMode")]
+pub enum ShuffleMode {
+    #[default]
+    #[enum_value(name = "songs")]
+    Songs,
+    #[enum_value(name = "albums")]
+    Albums,
+    #[enum_value(name = "spread")]
+    Spread,
+    #[enum_value(name = "rated")]
+    Rated,
+}
+
+impl From<i32> for ShuffleMode {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => Self::Songs,
+            1 => Self::Albums,
+            2 => Self::Spread,
+            3 => Self::Rated,
+            _ => panic!("invalid ShuffleMode enum key"),
+        }
+    }
+}
+
+impl From<ShuffleMode> for i32 {
+    fn from(value: ShuffleMode) -> Self {
+        match value {
+            ShuffleMode::Songs => 0,
+            ShuffleMode::Albums => 1,
+            ShuffleMode::Spread => 2,
+            ShuffleMode::Rated => 3,
+        }
+    }
+}
+
+// Shuffles whole albums, and puts the tracks inside each album in the
+// order given by `cmp_tracks`; if `lead` is set, the tracks of that album
+// are moved in front, so that we finish the album we are currently
+// listening to
+fn shuffle_albums<R: Rng>(
+    positions: Vec<u32>,
+    keys: &[String],
+    lead: Option<&str>,
+    cmp_tracks: &dyn Fn(u32, u32) -> Ordering,
+    rng: &mut R,
+) -> Vec<u32> {
+    let mut leading = Vec::new();
+    let mut albums: BTreeMap<&str, Vec<u32>> = BTreeMap::new();
+    for pos in positions {
+        let key = keys[pos as usize].as_str();
+        if Some(key) == lead {
+            leading.push(pos);
+        } else {
+            albums.entry(key).or_default().push(pos);
+        }
+    }
+
+    // The queue may not have the tracks of an album in order
+    leading.sort_by(|a, b| cmp_tracks(*a, *b));
+    let mut blocks: Vec<Vec<u32>> = albums.into_values().collect();
+    for block in &mut blocks {
+        block.sort_by(|a, b| cmp_tracks(*a, *b));
+    }
+    blocks.shuffle(rng);
+
+    leading.extend(blocks.into_iter().flatten());
+    leading
+}
+
+// Spreads the tracks of each artist across the whole range: every artist
+// gets its tracks placed at evenly spaced intervals, starting from a
+// random offset and with a bit of jitter, so that the same artist is
+// played back to back only when there is no other choice
+fn shuffle_spread<R: Rng>(positions: Vec<u32>, keys: &[String], rng: &mut R) -> Vec<u32> {
+    let mut artists: BTreeMap<&str, Vec<u32>> = BTreeMap::new();
+    for pos in positions {
+        artists
+            .entry(keys[pos as usize].as_str())
+            .or_default()
+            .push(pos);
+    }
+
+    let mut placed: Vec<(f64, u32)> = Vec::new();
+    for (_, mut tracks) in artists {
+        tracks.shuffle(rng);
+
+        let n = tracks.len() as f64;
+        let offset = rng.gen::<f64>() / n;
+        for (i, pos) in tracks.into_iter().enumerate() {
+            let jitter = rng.gen_range(-0.1..0.1) / n;
+            placed.push((offset + i as f64 / n + jitter, pos));
+        }
+    }
+
+    placed.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut res: Vec<u32> = placed.into_iter().map(|(_, pos)| pos).collect();
+
+    // The jitter can still put two tracks by the same artist next to each
+    // other; pull the next track by a different artist in between them
+    let key = |pos: u32| keys[pos as usize].as_str();
+    for i in 1..res.len() {
+        if key(res[i]) == key(res[i - 1]) {
+            if let Some(j) = (i + 1..res.len()).find(|j| key(res[*j]) != key(res[i - 1])) {
+                let pos = res.remove(j);
+                res.insert(i, pos);
+            }
+        }
+    }
+
+    res
+}
+
+// How likely a song is to come early when favoring the rated songs; the
+// songs we did not rate count as average ones
+fn rating_weight(rating: u32) -> f64 {
+    match rating {
+        0 => 3.0,
+        r => r as f64,
+    }
+}
+
+// Orders the positions at random, with the heavier ones more likely to
+// come first: each position gets the key u^(1/weight), for a random u in
+// [0, 1), and the largest keys win (Efraimidis and Spirakis)
+fn shuffle_weighted<R: Rng>(positions: Vec<u32>, weights: &[f64], rng: &mut R) -> Vec<u32> {
+    let mut keyed: Vec<(f64, u32)> = positions
+        .into_iter()
+        .map(|pos| {
+            let weight = weights.get(pos as usize).copied().unwrap_or(1.0);
+            (rng.gen::<f64>().powf(1.0 / weight), pos)
+        })
+        .collect();
+
+    keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+    keyed.into_iter().map(|(_, pos)| pos).collect()
+}
+
+// Songs that should never be played in shuffled order go after all the
+// other ones, in the order in which they were shuffled
+fn move_skipped_last(positions: Vec<u32>, skipped: &[bool]) -> Vec<u32> {
+    let (mut res, rest): (Vec<u32>, Vec<u32>) = positions
+        .into_iter()
+        .partition(|pos| !skipped.get(*pos as usize).copied().unwrap_or(false));
+    res.extend(rest);
+    res
+}
+
+fn shuffle_positions<R: Rng>(
+    mode: ShuffleMode,
+    mut positions: Vec<u32>,
+    keys: &[String],
+    weights: &[f64],
+    lead: Option<&str>,
+    cmp_tracks: &dyn Fn(u32, u32) -> Ordering,
+    rng: &mut R,
+) -> Vec<u32> {
+    match mode {
+        ShuffleMode::Songs => {
+            positions.shuffle(rng);
+            positions
+        }
+        ShuffleMode::Albums => shuffle_albums(positions, keys, lead, cmp_tracks, rng),
+        ShuffleMode::Spread => shuffle_spread(positions, keys, rng),
+        ShuffleMode::Rated => shuffle_weighted(positions, weights, rng),
+    }
+}
+
 mod imp {
     use glib::{ParamSpec, ParamSpecObject, Value};
     use once_cell::sync::Lazy;
@@ -17,6 +192,9 @@ mod imp {
     pub struct ShuffleListModel {
         pub model: RefCell<Option<gio::ListModel>>,
         pub shuffle: RefCell<Option<Vec<u32>>>,
+        // The inverse of the shuffle, computed on demand
+        pub inverse: RefCell<Option<Vec<u32>>>,
+        pub mode: Cell<ShuffleMode>,
     }
 
     #[glib::object_subclass]
@@ -136,46 +314,261 @@ impl ShuffleListModel {
         self.imp().shuffle.borrow().is_some()
     }
 
+    pub fn shuffle_mode(&self) -> ShuffleMode {
+        self.imp().mode.get()
+    }
+
+    pub fn set_shuffle_mode(&self, mode: ShuffleMode) {
+        self.imp().mode.set(mode);
+    }
+
+    // The grouping key of each item in the underlying model, depending
+    // on the shuffle mode
+    fn shuffle_keys(&self, model: &gio::ListModel, mode: ShuffleMode) -> Vec<String> {
+        if matches!(mode, ShuffleMode::Songs | ShuffleMode::Rated) {
+            return Vec::new();
+        }
+
+        (0..model.n_items())
+            .map(|i| match model.item(i).and_downcast::<Song>() {
+                Some(song) if mode == ShuffleMode::Albums => song.album_key(),
+                Some(song) => song.artist().to_lowercase(),
+                None => i.to_string(),
+            })
+            .collect()
+    }
+
+    // The weight of each item in the underlying model, when favoring the
+    // rated songs
+    fn shuffle_weights(&self, model: &gio::ListModel, mode: ShuffleMode) -> Vec<f64> {
+        if mode != ShuffleMode::Rated {
+            return Vec::new();
+        }
+
+        (0..model.n_items())
+            .map(|i| {
+                let rating = model
+                    .item(i)
+                    .and_downcast::<Song>()
+                    .map_or(0, |s| s.rating());
+                rating_weight(rating)
+            })
+            .collect()
+    }
+
     pub fn reshuffle(&self, anchor: u32) {
         if let Some(ref model) = *self.imp().model.borrow() {
             let n_songs = model.n_items();
+            let mode = self.imp().mode.get();
+            let keys = self.shuffle_keys(model, mode);
+            let weights = self.shuffle_weights(model, mode);
+            let skipped: Vec<bool> = (0..n_songs)
+                .map(|i| {
+                    model
+                        .item(i)
+                        .and_downcast::<Song>()
+                        .map_or(false, |s| s.skip_shuffle())
+                })
+                .collect();
+            // Albums are played in the order of their tracks
+            let songs: Vec<Option<Song>> = if mode == ShuffleMode::Albums {
+                (0..n_songs)
+                    .map(|i| model.item(i).and_downcast::<Song>())
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let cmp_tracks = |a: u32, b: u32| match (songs.get(a as usize), songs.get(b as usize)) {
+                (Some(Some(a)), Some(Some(b))) => cmp_track_order(a, b),
+                _ => Ordering::Equal,
+            };
             let mut rng = thread_rng();
 
-            let positions: Vec<u32> = if anchor == 0 {
-                let mut before: Vec<u32> = vec![0];
-                let mut after: Vec<u32> = (1..n_songs).collect();
-                after.shuffle(&mut rng);
-
-                before.extend(after);
-                before
-            } else if anchor == n_songs - 1 {
-                let mut before: Vec<u32> = (0..n_songs - 1).collect();
-                let after: Vec<u32> = vec![n_songs - 1];
-                before.shuffle(&mut rng);
-
-                before.extend(after);
+            let positions: Vec<u32> = if n_songs == 0 {
+                Vec::new()
+            } else if anchor > 0 && anchor == n_songs - 1 {
+                let before = shuffle_positions(
+                    mode,
+                    (0..anchor).collect(),
+                    &keys,
+                    &weights,
+                    None,
+                    &cmp_tracks,
+                    &mut rng,
+                );
+                let mut before = move_skipped_last(before, &skipped);
+                before.push(anchor);
                 before
             } else {
-                let mut before: Vec<u32> = (0..anchor).collect();
-                let mut after: Vec<u32> = (anchor + 1..n_songs).collect();
-                after.shuffle(&mut rng);
+                let lead = keys.get(anchor as usize).map(|k| k.as_str());
+                let after = shuffle_positions(
+                    mode,
+                    (anchor + 1..n_songs).collect(),
+                    &keys,
+                    &weights,
+                    lead,
+                    &cmp_tracks,
+                    &mut rng,
+                );
+                let after = move_skipped_last(after, &skipped);
 
+                let mut before: Vec<u32> = (0..anchor).collect();
                 before.push(anchor);
                 before.extend(after);
                 before
             };
 
-            self.imp().shuffle.replace(Some(positions));
+            self.set_shuffle(Some(positions));
             self.items_changed(0, model.n_items(), model.n_items());
         } else {
-            self.imp().shuffle.replace(None);
+            self.set_shuffle(None);
+        }
+    }
+
+    fn set_shuffle(&self, shuffle: Option<Vec<u32>>) {
+        self.imp().shuffle.replace(shuffle);
+        self.imp().inverse.replace(None);
+    }
+
+    // Maps a position in the underlying model to the position of the
+    // same item in the shuffled model
+    pub fn shuffled_position(&self, position: u32) -> u32 {
+        let imp = self.imp();
+        if let Some(ref shuffle) = *imp.shuffle.borrow() {
+            let mut inverse = imp.inverse.borrow_mut();
+            let inverse = inverse.get_or_insert_with(|| {
+                let mut inverse = vec![u32::MAX; shuffle.len()];
+                for (shuffled_pos, pos) in shuffle.iter().enumerate() {
+                    if let Some(p) = inverse.get_mut(*pos as usize) {
+                        *p = shuffled_pos as u32;
+                    }
+                }
+                inverse
+            });
+
+            // Items added after shuffling are not part of the shuffle
+            match inverse.get(position as usize) {
+                Some(p) if *p != u32::MAX => *p,
+                _ => position,
+            }
+        } else {
+            position
+        }
+    }
+
+    pub fn permutation(&self) -> Option<Vec<u32>> {
+        self.imp().shuffle.borrow().as_ref().cloned()
+    }
+
+    pub fn set_permutation(&self, positions: Vec<u32>) {
+        if let Some(ref model) = *self.imp().model.borrow() {
+            self.set_shuffle(Some(positions));
+            self.items_changed(0, model.n_items(), model.n_items());
         }
     }
 
     pub fn unshuffle(&self) {
         if let Some(ref model) = *self.imp().model.borrow() {
-            self.imp().shuffle.replace(None);
+            self.set_shuffle(None);
             self.items_changed(0, model.n_items(), model.n_items());
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(k: &[&str]) -> Vec<String> {
+        k.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_shuffle_albums() {
+        let keys = keys(&["a", "a", "a", "b", "b", "c", "c", "c"]);
+        let mut rng = StdRng::seed_from_u64(42);
+        let res = shuffle_albums((0..8).collect(), &keys, None, &|a, b| a.cmp(&b), &mut rng);
+
+        let mut sorted = res.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..8).collect::<Vec<u32>>());
+
+        // Tracks of the same album are contiguous and in order
+        for w in res.windows(2) {
+            if keys[w[0] as usize] == keys[w[1] as usize] {
+                assert_eq!(w[0] + 1, w[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shuffle_albums_lead() {
+        let keys = keys(&["a", "a", "b", "b", "c", "c"]);
+        let mut rng = StdRng::seed_from_u64(7);
+        let res = shuffle_albums(
+            (1..6).collect(),
+            &keys,
+            Some("a"),
+            &|a, b| a.cmp(&b),
+            &mut rng,
+        );
+        assert_eq!(res[0], 1);
+        assert_eq!(res.len(), 5);
+    }
+
+    #[test]
+    fn test_shuffle_albums_track_order() {
+        // Both albums were queued with their tracks backwards
+        let keys = keys(&["a", "a", "a", "b", "b"]);
+        let mut rng = StdRng::seed_from_u64(5);
+        let res = shuffle_albums((0..5).collect(), &keys, None, &|a, b| b.cmp(&a), &mut rng);
+
+        let a: Vec<u32> = res.iter().copied().filter(|p| *p < 3).collect();
+        let b: Vec<u32> = res.iter().copied().filter(|p| *p >= 3).collect();
+        assert_eq!(a, vec![2, 1, 0]);
+        assert_eq!(b, vec![4, 3]);
+    }
+
+    #[test]
+    fn test_move_skipped_last() {
+        let skipped = [false, true, false, false, true];
+        let res = move_skipped_last(vec![4, 0, 1, 3, 2], &skipped);
+        assert_eq!(res, vec![0, 3, 2, 4, 1]);
+    }
+
+    #[test]
+    fn test_shuffle_weighted() {
+        let weights = [rating_weight(1), rating_weight(5), rating_weight(0)];
+        let mut rng = StdRng::seed_from_u64(3);
+
+        let mut n_first = [0; 3];
+        for _ in 0..1000 {
+            let res = shuffle_weighted((0..3).collect(), &weights, &mut rng);
+            let mut sorted = res.clone();
+            sorted.sort_unstable();
+            assert_eq!(sorted, vec![0, 1, 2]);
+
+            n_first[res[0] as usize] += 1;
+        }
+
+        // Five stars beat unrated songs, which beat a single star
+        assert!(n_first[1] > n_first[2]);
+        assert!(n_first[2] > n_first[0]);
+    }
+
+    #[test]
+    fn test_shuffle_spread() {
+        let keys = keys(&["a", "a", "a", "b", "b", "b", "c", "c", "c"]);
+        let mut rng = StdRng::seed_from_u64(1);
+        let res = shuffle_spread((0..9).collect(), &keys, &mut rng);
+
+        let mut sorted = res.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..9).collect::<Vec<u32>>());
+
+        // Three artists with three songs each can always be interleaved
+        for w in res.windows(2) {
+            assert_ne!(keys[w[0] as usize], keys[w[1] as usize]);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::HashSet;
+
+use gtk::prelude::*;
+
+use crate::{audio::Song, utils};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SkipKind {
+    Song,
+    Artist,
+    Album,
+}
+
+impl SkipKind {
+    pub fn from_nick(nick: &str) -> Option<Self> {
+        match nick {
+            "song" => Some(SkipKind::Song),
+            "artist" => Some(SkipKind::Artist),
+            "album" => Some(SkipKind::Album),
+            _ => None,
+        }
+    }
+
+    // The GSettings key holding the entries of this kind
+    pub fn settings_key(self) -> &'static str {
+        match self {
+            SkipKind::Song => "shuffle-skip-songs",
+            SkipKind::Artist => "shuffle-skip-artists",
+            SkipKind::Album => "shuffle-skip-albums",
+        }
+    }
+
+    // Songs are identified by their URI, while artists and albums are
+    // identified by their name; songs without the tag have no value, so
+    // that skipping one untagged album does not skip all of them
+    pub fn values_for(self, song: &Song) -> Vec<String> {
+        match self {
+            SkipKind::Song => vec![song.uri()],
+            SkipKind::Artist => song.tagged_artists(),
+            SkipKind::Album => song.tagged_album().into_iter().collect(),
+        }
+    }
+}
+
+// The songs we never want to hear in shuffled order, like intros, skits
+// or hidden tracks; artists and albums are matched case-insensitively
+#[derive(Debug, Default)]
+pub struct SkipList {
+    songs: HashSet<String>,
+    artists: HashSet<String>,
+    albums: HashSet<String>,
+}
+
+impl SkipList {
+    pub fn load() -> Self {
+        let settings = utils::settings_manager();
+        let entries = |kind: SkipKind, fold: bool| -> HashSet<String> {
+            settings
+                .strv(kind.settings_key())
+                .iter()
+                .map(|s| {
+                    if fold {
+                        s.to_lowercase()
+                    } else {
+                        s.to_string()
+                    }
+                })
+                .collect()
+        };
+
+        Self {
+            songs: entries(SkipKind::Song, false),
+            artists: entries(SkipKind::Artist, true),
+            albums: entries(SkipKind::Album, true),
+        }
+    }
+
+    pub fn contains(&self, song: &Song) -> bool {
+        self.songs.contains(&song.uri())
+            || (!self.artists.is_empty()
+                && song
+                    .tagged_artists()
+                    .iter()
+                    .any(|a| self.artists.contains(&a.to_lowercase())))
+            || (!self.albums.is_empty()
+                && song
+                    .tagged_album()
+                    .is_some_and(|a| self.albums.contains(&a.to_lowercase())))
+    }
+
+    pub fn entries(kind: SkipKind) -> Vec<String> {
+        utils::settings_manager()
+            .strv(kind.settings_key())
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    pub fn add(kind: SkipKind, values: &[String]) {
+        let mut entries = Self::entries(kind);
+        let n_entries = entries.len();
+        for value in values {
+            let folded = value.to_lowercase();
+            if !entries.iter().any(|e| e.to_lowercase() == folded) {
+                entries.push(value.to_string());
+            }
+        }
+
+        if entries.len() != n_entries {
+            Self::store(kind, &entries);
+        }
+    }
+
+    pub fn remove(kind: SkipKind, value: &str) {
+        let mut entries = Self::entries(kind);
+        entries.retain(|e| e != value);
+        Self::store(kind, &entries);
+    }
+
+    fn store(kind: SkipKind, entries: &[String]) {
+        utils::settings_manager()
+            .set_strv(kind.settings_key(), entries)
+            .expect("Unable to store setting");
+    }
+}
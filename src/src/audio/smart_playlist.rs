@@ -0,0 +1,592 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{cell::RefCell, fmt, path::PathBuf};
+
+use gtk::glib;
+use log::{debug, warn};
+
+use crate::{
+    i18n::{i18n, ni18n_f},
+    query::parse_duration,
+    utils::fold_text,
+};
+
+const PLAYLISTS_VERSION: i64 = 1;
+const PLAYLISTS_GROUP: &str = "playlists";
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+fn playlists_path() -> PathBuf {
+    let mut path = glib::user_data_dir();
+    path.push("axuralis");
+    path.push("library");
+    path.push("smart-playlists.ini");
+    path
+}
+
+// The kind of value a rule compares against
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldKind {
+    Text,
+    Number,
+    // Written like the durations of the search, e.g. "3:30" or "3m"
+    Duration,
+    // A number of days
+    Date,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RuleField {
+    Title,
+    Artist,
+    Album,
+    Genre,
+    Year,
+    Duration,
+    Rating,
+    PlayCount,
+    LastPlayed,
+    Added,
+}
+
+impl RuleField {
+    pub const ALL: [RuleField; 10] = [
+        RuleField::Title,
+        RuleField::Artist,
+        RuleField::Album,
+        RuleField::Genre,
+        RuleField::Year,
+        RuleField::Duration,
+        RuleField::Rating,
+        RuleField::PlayCount,
+        RuleField::LastPlayed,
+        RuleField::Added,
+    ];
+
+    fn nick(self) -> &'static str {
+        match self {
+            RuleField::Title => "title",
+            RuleField::Artist => "artist",
+            RuleField::Album => "album",
+            RuleField::Genre => "genre",
+            RuleField::Year => "year",
+            RuleField::Duration => "duration",
+            RuleField::Rating => "rating",
+            RuleField::PlayCount => "play-count",
+            RuleField::LastPlayed => "last-played",
+            RuleField::Added => "added",
+        }
+    }
+
+    fn from_nick(nick: &str) -> Option<Self> {
+        RuleField::ALL.into_iter().find(|f| f.nick() == nick)
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            RuleField::Title => i18n("Title"),
+            RuleField::Artist => i18n("Artist"),
+            RuleField::Album => i18n("Album"),
+            RuleField::Genre => i18n("Genre"),
+            RuleField::Year => i18n("Year"),
+            RuleField::Duration => i18n("Duration"),
+            RuleField::Rating => i18n("Rating"),
+            RuleField::PlayCount => i18n("Play Count"),
+            RuleField::LastPlayed => i18n("Last Played"),
+            RuleField::Added => i18n("Date Added"),
+        }
+    }
+
+    pub fn kind(self) -> FieldKind {
+        match self {
+            RuleField::Title | RuleField::Artist | RuleField::Album | RuleField::Genre => {
+                FieldKind::Text
+            }
+            RuleField::Year | RuleField::Rating | RuleField::PlayCount => FieldKind::Number,
+            RuleField::Duration => FieldKind::Duration,
+            RuleField::LastPlayed | RuleField::Added => FieldKind::Date,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RuleOperator {
+    Contains,
+    NotContains,
+    Is,
+    IsNot,
+    Less,
+    Greater,
+    InLast,
+    NotInLast,
+    ThisMonth,
+}
+
+impl RuleOperator {
+    const ALL: [RuleOperator; 9] = [
+        RuleOperator::Contains,
+        RuleOperator::NotContains,
+        RuleOperator::Is,
+        RuleOperator::IsNot,
+        RuleOperator::Less,
+        RuleOperator::Greater,
+        RuleOperator::InLast,
+        RuleOperator::NotInLast,
+        RuleOperator::ThisMonth,
+    ];
+
+    // The operators that make sense for a kind of field
+    pub fn for_kind(kind: FieldKind) -> &'static [RuleOperator] {
+        match kind {
+            FieldKind::Text => &[
+                RuleOperator::Contains,
+                RuleOperator::NotContains,
+                RuleOperator::Is,
+                RuleOperator::IsNot,
+            ],
+            FieldKind::Number | FieldKind::Duration => &[
+                RuleOperator::Is,
+                RuleOperator::IsNot,
+                RuleOperator::Less,
+                RuleOperator::Greater,
+            ],
+            FieldKind::Date => &[
+                RuleOperator::InLast,
+                RuleOperator::NotInLast,
+                RuleOperator::ThisMonth,
+            ],
+        }
+    }
+
+    fn nick(self) -> &'static str {
+        match self {
+            RuleOperator::Contains => "contains",
+            RuleOperator::NotContains => "not-contains",
+            RuleOperator::Is => "is",
+            RuleOperator::IsNot => "is-not",
+            RuleOperator::Less => "less",
+            RuleOperator::Greater => "greater",
+            RuleOperator::InLast => "in-last",
+            RuleOperator::NotInLast => "not-in-last",
+            RuleOperator::ThisMonth => "this-month",
+        }
+    }
+
+    fn from_nick(nick: &str) -> Option<Self> {
+        RuleOperator::ALL.into_iter().find(|o| o.nick() == nick)
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            RuleOperator::Contains => i18n("contains"),
+            RuleOperator::NotContains => i18n("does not contain"),
+            RuleOperator::Is => i18n("is"),
+            RuleOperator::IsNot => i18n("is not"),
+            RuleOperator::Less => i18n("is less than"),
+            RuleOperator::Greater => i18n("is greater than"),
+            // Translators: followed by a number of days
+            RuleOperator::InLast => i18n("in the last days"),
+            // Translators: followed by a number of days
+            RuleOperator::NotInLast => i18n("not in the last days"),
+            RuleOperator::ThisMonth => i18n("this month"),
+        }
+    }
+
+    pub fn needs_value(self) -> bool {
+        self != RuleOperator::ThisMonth
+    }
+}
+
+// What a rule looks at in a song
+#[derive(Debug, Default)]
+pub struct SongFacts {
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album: String,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    pub duration: u64,
+    // In stars, 0 for the songs we did not rate
+    pub rating: u32,
+    pub play_count: u32,
+    // In seconds since the epoch
+    pub last_played: Option<i64>,
+    pub added: Option<i64>,
+}
+
+// The current time, so that every song is compared against the same
+pub struct RuleContext {
+    pub now: i64,
+    pub month_start: i64,
+}
+
+impl RuleContext {
+    pub fn now() -> Self {
+        let now = glib::DateTime::now_local().expect("Unable to get the current time");
+        let month_start = glib::DateTime::from_local(now.year(), now.month(), 1, 0, 0, 0.0)
+            .map(|d| d.to_unix())
+            .unwrap_or_else(|_| now.to_unix());
+
+        Self {
+            now: now.to_unix(),
+            month_start,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub field: RuleField,
+    pub operator: RuleOperator,
+    pub value: String,
+}
+
+impl Rule {
+    pub fn new(field: RuleField, operator: RuleOperator, value: &str) -> Self {
+        Self {
+            field,
+            operator,
+            value: value.to_string(),
+        }
+    }
+
+    // Rules are stored as "field operator value"
+    fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.splitn(3, ' ');
+        let field = RuleField::from_nick(parts.next()?)?;
+        let operator = RuleOperator::from_nick(parts.next()?)?;
+        let value = parts.next().unwrap_or_default();
+
+        Some(Rule::new(field, operator, value))
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let kind = self.field.kind();
+        if !RuleOperator::for_kind(kind).contains(&self.operator) {
+            return false;
+        }
+        if !self.operator.needs_value() {
+            return true;
+        }
+
+        match kind {
+            FieldKind::Text => !self.value.trim().is_empty(),
+            FieldKind::Number | FieldKind::Date => self.number().is_some(),
+            FieldKind::Duration => self.number().is_some(),
+        }
+    }
+
+    fn number(&self) -> Option<u64> {
+        let value = self.value.trim();
+        match self.field.kind() {
+            FieldKind::Duration => parse_duration(value),
+            _ => value.parse::<u64>().ok(),
+        }
+    }
+
+    pub fn matches(&self, facts: &SongFacts, context: &RuleContext) -> bool {
+        match self.field.kind() {
+            FieldKind::Text => {
+                // A song with many artists matches if any of them does
+                let texts: Vec<String> = match self.field {
+                    RuleField::Title => vec![fold_text(&facts.title)],
+                    RuleField::Artist => facts.artists.iter().map(|a| fold_text(a)).collect(),
+                    RuleField::Album => vec![fold_text(&facts.album)],
+                    _ => vec![facts.genre.as_deref().map(fold_text).unwrap_or_default()],
+                };
+                let value = fold_text(self.value.trim());
+                match self.operator {
+                    RuleOperator::Contains => texts.iter().any(|t| t.contains(&value)),
+                    RuleOperator::NotContains => !texts.iter().any(|t| t.contains(&value)),
+                    RuleOperator::Is => texts.iter().any(|t| *t == value),
+                    RuleOperator::IsNot => !texts.iter().any(|t| *t == value),
+                    _ => false,
+                }
+            }
+            FieldKind::Number | FieldKind::Duration => {
+                let actual = match self.field {
+                    RuleField::Year => facts.year.map(u64::from),
+                    RuleField::Duration => Some(facts.duration),
+                    RuleField::Rating => Some(facts.rating as u64),
+                    _ => Some(facts.play_count as u64),
+                };
+                match (self.operator, actual, self.number()) {
+                    (RuleOperator::IsNot, actual, Some(value)) => actual != Some(value),
+                    (_, None, _) | (_, _, None) => false,
+                    (RuleOperator::Is, Some(actual), Some(value)) => actual == value,
+                    (RuleOperator::Less, Some(actual), Some(value)) => actual < value,
+                    (RuleOperator::Greater, Some(actual), Some(value)) => actual > value,
+                    _ => false,
+                }
+            }
+            FieldKind::Date => {
+                let time = match self.field {
+                    RuleField::LastPlayed => facts.last_played,
+                    _ => facts.added,
+                };
+                let since = match self.operator {
+                    RuleOperator::ThisMonth => context.month_start,
+                    _ => match self.number() {
+                        Some(days) => context.now - days as i64 * SECONDS_PER_DAY,
+                        None => return false,
+                    },
+                };
+                let recent = time.is_some_and(|t| t >= since);
+                match self.operator {
+                    RuleOperator::NotInLast => !recent,
+                    _ => recent,
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.field.nick(),
+            self.operator.nick(),
+            self.value
+        )
+    }
+}
+
+// A playlist made of the library songs that follow a set of rules; the
+// songs are picked again every time we play it
+#[derive(Clone, Debug, PartialEq)]
+pub struct SmartPlaylist {
+    pub name: String,
+    // Whether a song needs to follow all the rules, or just one
+    pub match_all: bool,
+    pub rules: Vec<Rule>,
+}
+
+impl SmartPlaylist {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            match_all: true,
+            rules: vec![],
+        }
+    }
+
+    pub fn matches(&self, facts: &SongFacts, context: &RuleContext) -> bool {
+        if self.rules.is_empty() {
+            return true;
+        }
+
+        let mut matches = self.rules.iter().map(|r| r.matches(facts, context));
+        if self.match_all {
+            matches.all(|m| m)
+        } else {
+            matches.any(|m| m)
+        }
+    }
+
+    pub fn description(&self) -> String {
+        let n_rules = self.rules.len();
+        ni18n_f(
+            // Translators: the `{}` must be left unmodified; it will be
+            // expanded to the number of rules of a smart playlist
+            "{} rule",
+            "{} rules",
+            n_rules as u32,
+            &[&n_rules.to_string()],
+        )
+    }
+
+    // The playlists we start with, as examples of what rules can do
+    fn defaults() -> Vec<Self> {
+        vec![
+            SmartPlaylist {
+                name: i18n("Added This Month"),
+                match_all: true,
+                rules: vec![Rule::new(RuleField::Added, RuleOperator::ThisMonth, "")],
+            },
+            SmartPlaylist {
+                name: i18n("Not Played Recently"),
+                match_all: true,
+                rules: vec![
+                    Rule::new(RuleField::PlayCount, RuleOperator::Greater, "0"),
+                    Rule::new(RuleField::LastPlayed, RuleOperator::NotInLast, "30"),
+                ],
+            },
+            SmartPlaylist {
+                name: i18n("Favorites"),
+                match_all: true,
+                rules: vec![Rule::new(RuleField::Rating, RuleOperator::Greater, "3")],
+            },
+            SmartPlaylist {
+                name: i18n("Never Played"),
+                match_all: true,
+                rules: vec![Rule::new(RuleField::PlayCount, RuleOperator::Is, "0")],
+            },
+        ]
+    }
+}
+
+thread_local! {
+    // The library dialog evaluates the playlists on every library update,
+    // so we only read the file once; storing the playlists updates it
+    static SMART_PLAYLISTS: RefCell<Option<Vec<SmartPlaylist>>> = const { RefCell::new(None) };
+}
+
+pub fn load_smart_playlists() -> Vec<SmartPlaylist> {
+    SMART_PLAYLISTS.with(|cache| {
+        cache
+            .borrow_mut()
+            .get_or_insert_with(read_smart_playlists)
+            .clone()
+    })
+}
+
+fn read_smart_playlists() -> Vec<SmartPlaylist> {
+    let path = playlists_path();
+    let kf = glib::KeyFile::new();
+    if let Err(e) = kf.load_from_file(&path, glib::KeyFileFlags::NONE) {
+        debug!("Unable to load smart playlists: {e}");
+        return SmartPlaylist::defaults();
+    }
+
+    match kf.int64(PLAYLISTS_GROUP, "Version") {
+        Ok(PLAYLISTS_VERSION) => (),
+        _ => {
+            debug!("Discarding smart playlists with a different version");
+            return SmartPlaylist::defaults();
+        }
+    }
+
+    let n_playlists = kf.uint64(PLAYLISTS_GROUP, "NumberOfPlaylists").unwrap_or(0);
+    (0..n_playlists)
+        .filter_map(|i| {
+            let group = format!("Playlist{i}");
+            let name = kf.string(&group, "Name").ok()?;
+            let rules = kf
+                .string_list(&group, "Rules")
+                .map(|rules| rules.iter().filter_map(|r| Rule::parse(r)).collect())
+                .unwrap_or_default();
+
+            Some(SmartPlaylist {
+                name: name.to_string(),
+                match_all: kf.boolean(&group, "MatchAll").unwrap_or(true),
+                rules,
+            })
+        })
+        .collect()
+}
+
+pub fn store_smart_playlists(playlists: &[SmartPlaylist]) {
+    SMART_PLAYLISTS.with(|cache| cache.replace(Some(playlists.to_vec())));
+
+    let kf = glib::KeyFile::new();
+    kf.set_int64(PLAYLISTS_GROUP, "Version", PLAYLISTS_VERSION);
+    kf.set_uint64(PLAYLISTS_GROUP, "NumberOfPlaylists", playlists.len() as u64);
+    for (i, playlist) in playlists.iter().enumerate() {
+        let group = format!("Playlist{i}");
+        kf.set_string(&group, "Name", &playlist.name);
+        kf.set_boolean(&group, "MatchAll", playlist.match_all);
+        let rules: Vec<String> = playlist.rules.iter().map(|r| r.to_string()).collect();
+        let rules: Vec<&str> = rules.iter().map(|s| s.as_str()).collect();
+        kf.set_string_list(&group, "Rules", &rules);
+    }
+
+    let path = playlists_path();
+    if let Some(parent) = path.parent() {
+        glib::mkdir_with_parents(parent, 0o755);
+    }
+
+    match kf.save_to_file(&path) {
+        Ok(_) => debug!("Smart playlists stored in: {:?}", &path),
+        Err(e) => warn!("Unable to store smart playlists: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = SECONDS_PER_DAY;
+
+    fn context() -> RuleContext {
+        RuleContext {
+            now: 100 * DAY,
+            month_start: 90 * DAY,
+        }
+    }
+
+    #[test]
+    fn test_rule_round_trip() {
+        let rule = Rule::new(RuleField::Genre, RuleOperator::Is, "Free Jazz");
+        assert_eq!(rule.to_string(), "genre is Free Jazz");
+        assert_eq!(Rule::parse(&rule.to_string()), Some(rule));
+
+        let rule = Rule::new(RuleField::Added, RuleOperator::ThisMonth, "");
+        assert_eq!(Rule::parse(&rule.to_string()), Some(rule));
+
+        assert_eq!(Rule::parse("mood is happy"), None);
+    }
+
+    #[test]
+    fn test_rule_validity() {
+        assert!(Rule::new(RuleField::Year, RuleOperator::Less, "1970").is_valid());
+        assert!(!Rule::new(RuleField::Year, RuleOperator::Less, "sixties").is_valid());
+        assert!(!Rule::new(RuleField::Year, RuleOperator::Contains, "19").is_valid());
+        assert!(Rule::new(RuleField::Duration, RuleOperator::Less, "3m").is_valid());
+        assert!(!Rule::new(RuleField::Title, RuleOperator::Is, " ").is_valid());
+        assert!(Rule::new(RuleField::Added, RuleOperator::ThisMonth, "").is_valid());
+    }
+
+    #[test]
+    fn test_smart_playlist_matches() {
+        let context = context();
+        let song = SongFacts {
+            title: "So What".to_string(),
+            genre: Some("Jazz".to_string()),
+            year: Some(1959),
+            duration: 562,
+            play_count: 3,
+            last_played: Some(60 * DAY),
+            added: Some(95 * DAY),
+            ..Default::default()
+        };
+
+        let mut playlist = SmartPlaylist::new("Old Jazz");
+        playlist.rules = vec![
+            Rule::new(RuleField::Genre, RuleOperator::Is, "jazz"),
+            Rule::new(RuleField::Year, RuleOperator::Less, "1970"),
+        ];
+        assert!(playlist.matches(&song, &context));
+
+        playlist.rules = vec![Rule::new(RuleField::Rating, RuleOperator::Greater, "3")];
+        assert!(!playlist.matches(&song, &context));
+        let rated = SongFacts {
+            rating: 4,
+            ..Default::default()
+        };
+        assert!(playlist.matches(&rated, &context));
+
+        playlist.rules = vec![
+            Rule::new(RuleField::PlayCount, RuleOperator::Greater, "0"),
+            Rule::new(RuleField::LastPlayed, RuleOperator::NotInLast, "30"),
+        ];
+        assert!(playlist.matches(&song, &context));
+
+        playlist.rules = vec![
+            Rule::new(RuleField::Added, RuleOperator::ThisMonth, ""),
+            Rule::new(RuleField::Duration, RuleOperator::Less, "5m"),
+        ];
+        assert!(!playlist.matches(&song, &context));
+        playlist.match_all = false;
+        assert!(playlist.matches(&song, &context));
+
+        // Songs we never played were not played recently, either
+        let never_played = SongFacts::default();
+        let rule = Rule::new(RuleField::LastPlayed, RuleOperator::NotInLast, "30");
+        assert!(rule.matches(&never_played, &context));
+        let rule = Rule::new(RuleField::Year, RuleOperator::IsNot, "1959");
+        assert!(rule.matches(&never_played, &context));
+    }
+}
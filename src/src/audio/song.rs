@@ -2,34 +2,75 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use std::{
-    cell::{Cell, RefCell},
+    cell::{Cell, Ref, RefCell},
     fmt::{self, Display, Formatter},
+    io::{Cursor, Read},
     path::PathBuf,
     time::Instant,
 };
 
-use glib::{ParamSpec, ParamSpecBoolean, ParamSpecObject, ParamSpecString, ParamSpecUInt, Value};
+use glib::{
+    ParamSpec, ParamSpecBoolean, ParamSpecObject, ParamSpecString, ParamSpecUInt, ParamSpecUInt64,
+    Value,
+};
 use gtk::{gdk, gio, glib, prelude::*, subclass::prelude::*};
-use lofty::prelude::{Accessor, TaggedFileExt};
+use lofty::{
+    file::{FileType, TaggedFile},
+    prelude::{Accessor, AudioFile, TaggedFileExt},
+    probe::Probe,
+    tag::{ItemKey, Tag},
+};
 use log::{debug, warn};
 use once_cell::sync::Lazy;
 use sha2::{Digest, Sha256};
 
 use crate::{
-    audio::cover_cache::{CoverArt, CoverCache},
+    audio::{
+        cover_cache::{CoverArt, CoverCache},
+        metadata_cache::{FileStamp, MetadataCache},
+        rating::{tag_rating, RatingStore, MAX_RATING},
+        waveform_generator::invalidate_cached_peaks,
+        TagField,
+    },
     i18n::i18n,
+    query::SearchFields,
+    utils,
 };
 
 #[derive(Debug, Clone)]
 pub struct SongData {
-    artist: Option<String>,
-    title: Option<String>,
-    album: Option<String>,
-    cover_art: Option<CoverArt>,
-    cover_uuid: Option<String>,
-    uuid: Option<String>,
-    duration: u64,
-    file: gio::File,
+    pub(super) artist: Option<String>,
+    // Every value of a multi-valued artist tag, as written
+    pub(super) artists: Vec<String>,
+    pub(super) title: Option<String>,
+    pub(super) album: Option<String>,
+    pub(super) album_artist: Option<String>,
+    pub(super) genre: Option<String>,
+    pub(super) artist_sort: Option<String>,
+    pub(super) album_sort: Option<String>,
+    pub(super) title_sort: Option<String>,
+    pub(super) bpm: Option<u32>,
+    // In stars, as written in the tags
+    pub(super) rating: Option<u32>,
+    pub(super) track_number: Option<u32>,
+    pub(super) track_total: Option<u32>,
+    pub(super) disc_number: Option<u32>,
+    pub(super) disc_total: Option<u32>,
+    pub(super) year: Option<u32>,
+    pub(super) date: Option<String>,
+    pub(super) composer: Option<String>,
+    pub(super) comment: Option<String>,
+    pub(super) codec: Option<String>,
+    pub(super) bitrate: Option<u32>,
+    pub(super) sample_rate: Option<u32>,
+    pub(super) bit_depth: Option<u32>,
+    pub(super) channels: Option<u32>,
+    pub(super) file_size: u64,
+    pub(super) cover_art: Option<CoverArt>,
+    pub(super) cover_uuid: Option<String>,
+    pub(super) uuid: Option<String>,
+    pub(super) duration: u64,
+    pub(super) file: gio::File,
 }
 
 impl SongData {
@@ -37,6 +78,10 @@ impl SongData {
         self.artist.as_deref()
     }
 
+    pub fn artists(&self) -> &[String] {
+        &self.artists
+    }
+
     pub fn title(&self) -> Option<&str> {
         self.title.as_deref()
     }
@@ -45,6 +90,96 @@ impl SongData {
         self.album.as_deref()
     }
 
+    pub fn album_artist(&self) -> Option<&str> {
+        self.album_artist.as_deref()
+    }
+
+    pub fn genre(&self) -> Option<&str> {
+        self.genre.as_deref()
+    }
+
+    // The names to sort by, when they differ from the displayed ones:
+    // "Beatles, The", or the reading of a name written in kanji
+    pub fn artist_sort(&self) -> Option<&str> {
+        self.artist_sort.as_deref()
+    }
+
+    pub fn album_sort(&self) -> Option<&str> {
+        self.album_sort.as_deref()
+    }
+
+    pub fn title_sort(&self) -> Option<&str> {
+        self.title_sort.as_deref()
+    }
+
+    pub fn bpm(&self) -> Option<u32> {
+        self.bpm
+    }
+
+    pub fn rating(&self) -> Option<u32> {
+        self.rating
+    }
+
+    pub fn track_number(&self) -> Option<u32> {
+        self.track_number
+    }
+
+    pub fn track_total(&self) -> Option<u32> {
+        self.track_total
+    }
+
+    pub fn disc_number(&self) -> Option<u32> {
+        self.disc_number
+    }
+
+    pub fn disc_total(&self) -> Option<u32> {
+        self.disc_total
+    }
+
+    pub fn year(&self) -> Option<u32> {
+        self.year
+    }
+
+    // The full recording date, when the tags have more than a year
+    pub fn date(&self) -> Option<&str> {
+        self.date.as_deref()
+    }
+
+    pub fn composer(&self) -> Option<&str> {
+        self.composer.as_deref()
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    pub fn codec(&self) -> Option<&str> {
+        self.codec.as_deref()
+    }
+
+    // In kbps
+    pub fn bitrate(&self) -> Option<u32> {
+        self.bitrate
+    }
+
+    // In Hz
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.sample_rate
+    }
+
+    pub fn bit_depth(&self) -> Option<u32> {
+        self.bit_depth
+    }
+
+    pub fn channels(&self) -> Option<u32> {
+        self.channels
+    }
+
+    // In bytes
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
     pub fn uuid(&self) -> Option<&str> {
         self.uuid.as_deref()
     }
@@ -81,33 +216,104 @@ impl SongData {
         None
     }
 
+    // Reads the tags of the file again, after we changed them; whatever
+    // we cached under the old tags is stale. The cached cover is shared
+    // by the whole album, so we only drop it if the cover itself changed.
+    // This is as slow as loading the song, so it can run in a worker thread
+    pub fn reload(uri: &str, cover_uuid: Option<&str>, cover_changed: bool) -> Self {
+        let shared_cover = {
+            let mut metadata_cache = MetadataCache::global().lock().unwrap();
+            metadata_cache.invalidate(uri);
+            cover_uuid.is_some_and(|uuid| metadata_cache.uses_cover(uuid, uri))
+        };
+        if let Some(cover_uuid) = cover_uuid.filter(|_| cover_changed) {
+            CoverCache::global()
+                .lock()
+                .unwrap()
+                .invalidate(cover_uuid, !shared_cover);
+        }
+
+        SongData::from_uri(uri)
+    }
+
     pub fn from_uri(uri: &str) -> Self {
         let now = Instant::now();
 
         let file = gio::File::for_uri(uri);
-        let path = file.path().expect("Unable to find file");
 
-        let tagged_file = match lofty::read_from_path(&path) {
+        // Reading the tags is the slow part, so we use the metadata we
+        // cached the last time we saw the file, unless it changed since
+        let stamp = FileStamp::for_file(&file);
+        if let Some(stamp) = stamp {
+            let cached = MetadataCache::global().lock().unwrap().lookup(uri, stamp);
+            if let Some((mut data, palette)) = cached {
+                data.file = file.clone();
+
+                // The cover art is cached on its own; if it went away, we
+                // need to load it from the file again
+                if data.restore_cover(&palette) {
+                    debug!(
+                        "Song {:?} ('{:?}') loaded from the cache: {} ms",
+                        &data.uuid,
+                        &data.title,
+                        now.elapsed().as_millis()
+                    );
+                    return data;
+                }
+            }
+        }
+
+        let tagged_file = match read_tagged_file(&file) {
             Ok(f) => f,
             Err(e) => {
-                warn!("Unable to open file {:?}: {}", path, e);
+                warn!("Unable to open file {}: {}", uri, e);
                 return SongData::default();
             }
         };
 
-        let mut cover_cache = CoverCache::global().lock().unwrap();
-
         let mut artist = None;
+        let mut artists = Vec::new();
         let mut title = None;
         let mut album = None;
+        let mut album_artist = None;
+        let mut genre = None;
+        let mut artist_sort = None;
+        let mut album_sort = None;
+        let mut title_sort = None;
+        let mut bpm = None;
+        let mut rating = None;
+        let mut track_number = None;
+        let mut track_total = None;
+        let mut disc_number = None;
+        let mut disc_total = None;
+        let mut year = None;
+        let mut date = None;
+        let mut composer = None;
+        let mut comment = None;
         let mut cover_art = None;
         let mut cover_uuid = None;
         if let Some(tag) = tagged_file.primary_tag() {
             debug!("Found primary tag");
             artist = tag.artist().map(|s| s.to_string());
+            artists = tag_artists(tag);
             title = tag.title().map(|s| s.to_string());
             album = tag.album().map(|s| s.to_string());
-            if let Some(res) = cover_cache.cover_art(&path, tag) {
+            album_artist = tag.get_string(&ItemKey::AlbumArtist).map(|s| s.to_string());
+            genre = tag.genre().map(|s| s.to_string());
+            artist_sort = tag_string(tag, ItemKey::TrackArtistSortOrder);
+            album_sort = tag_string(tag, ItemKey::AlbumTitleSortOrder);
+            title_sort = tag_string(tag, ItemKey::TrackTitleSortOrder);
+            bpm = tag_bpm(tag);
+            rating = tag_rating(tag);
+            track_number = tag.track();
+            track_total = tag.track_total();
+            disc_number = tag.disk();
+            disc_total = tag.disk_total();
+            year = tag.year();
+            date = tag_string(tag, ItemKey::RecordingDate);
+            composer = tag_string(tag, ItemKey::Composer);
+            comment = tag_comment(tag);
+            if let Some(res) = CoverCache::global().lock().unwrap().cover_art(&file, tag) {
                 cover_art = Some(res.0);
                 cover_uuid = Some(res.1);
             }
@@ -116,9 +322,27 @@ impl SongData {
             for tag in tagged_file.tags() {
                 debug!("Found tag: {:?}", tag.tag_type());
                 artist = tag.artist().map(|s| s.to_string());
+                artists = tag_artists(tag);
                 title = tag.title().map(|s| s.to_string());
                 album = tag.album().map(|s| s.to_string());
-                if let Some(res) = cover_cache.cover_art(&path, tag) {
+                album_artist = album_artist
+                    .or_else(|| tag.get_string(&ItemKey::AlbumArtist).map(|s| s.to_string()));
+                genre = genre.or_else(|| tag.genre().map(|s| s.to_string()));
+                artist_sort =
+                    artist_sort.or_else(|| tag_string(tag, ItemKey::TrackArtistSortOrder));
+                album_sort = album_sort.or_else(|| tag_string(tag, ItemKey::AlbumTitleSortOrder));
+                title_sort = title_sort.or_else(|| tag_string(tag, ItemKey::TrackTitleSortOrder));
+                bpm = bpm.or_else(|| tag_bpm(tag));
+                rating = rating.or_else(|| tag_rating(tag));
+                track_number = track_number.or_else(|| tag.track());
+                track_total = track_total.or_else(|| tag.track_total());
+                disc_number = disc_number.or_else(|| tag.disk());
+                disc_total = disc_total.or_else(|| tag.disk_total());
+                year = year.or_else(|| tag.year());
+                date = date.or_else(|| tag_string(tag, ItemKey::RecordingDate));
+                composer = composer.or_else(|| tag_string(tag, ItemKey::Composer));
+                comment = comment.or_else(|| tag_comment(tag));
+                if let Some(res) = CoverCache::global().lock().unwrap().cover_art(&file, tag) {
                     cover_art = Some(res.0);
                     cover_uuid = Some(res.1);
                 }
@@ -154,8 +378,20 @@ impl SongData {
             _ => None,
         };
 
-        let properties = lofty::prelude::AudioFile::properties(&tagged_file);
+        // Some taggers only write the full date
+        let year = year.or_else(|| date.as_deref().and_then(year_from_date));
+
+        let properties = tagged_file.properties();
         let duration = properties.duration().as_secs();
+        let codec = codec_name(tagged_file.file_type());
+        let bitrate = properties
+            .audio_bitrate()
+            .or_else(|| properties.overall_bitrate())
+            .filter(|b| *b > 0);
+        let sample_rate = properties.sample_rate().filter(|r| *r > 0);
+        let bit_depth = properties.bit_depth().map(u32::from).filter(|d| *d > 0);
+        let channels = properties.channels().map(u32::from).filter(|c| *c > 0);
+        let file_size = stamp.map(|s| s.size()).unwrap_or(0);
 
         debug!(
             "Song {:?} ('{:?}') loading time: {} ms",
@@ -164,16 +400,62 @@ impl SongData {
             now.elapsed().as_millis()
         );
 
-        SongData {
+        let res = SongData {
             artist,
+            artists,
             title,
             album,
+            album_artist,
+            genre,
+            artist_sort,
+            album_sort,
+            title_sort,
+            bpm,
+            rating,
+            track_number,
+            track_total,
+            disc_number,
+            disc_total,
+            year,
+            date,
+            composer,
+            comment,
+            codec,
+            bitrate,
+            sample_rate,
+            bit_depth,
+            channels,
+            file_size,
             cover_art,
             cover_uuid,
             uuid,
             duration,
             file,
+        };
+
+        if let Some(stamp) = stamp {
+            MetadataCache::global()
+                .lock()
+                .unwrap()
+                .insert(uri, stamp, &res);
         }
+
+        res
+    }
+
+    // Loads the cover art of metadata that comes from one of our caches;
+    // returns false if the song has a cover that is not cached any more
+    pub(super) fn restore_cover(&mut self, palette: &[gdk::RGBA]) -> bool {
+        let uuid = match self.cover_uuid {
+            Some(ref uuid) => uuid,
+            None => return true,
+        };
+
+        self.cover_art = CoverCache::global()
+            .lock()
+            .unwrap()
+            .cached_cover_art(uuid, palette);
+        self.cover_art.is_some()
     }
 
     pub fn uri(&self) -> String {
@@ -185,12 +467,132 @@ impl SongData {
     }
 }
 
+// Local files, and GVFS locations with a FUSE mount, can be read directly;
+// other locations, like an SMB share or an MTP device, are only available
+// through GIO. Lofty needs to seek around the file, so in that case we read
+// the whole stream in memory first
+pub(super) fn read_tagged_file(file: &gio::File) -> Result<TaggedFile, String> {
+    if let Some(path) = file.path() {
+        return lofty::read_from_path(&path).map_err(|e| e.to_string());
+    }
+
+    let stream = file
+        .read(gio::Cancellable::NONE)
+        .map_err(|e| e.to_string())?;
+    let mut bytes = Vec::new();
+    stream
+        .into_read()
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+
+    Probe::new(Cursor::new(bytes))
+        .guess_file_type()
+        .map_err(|e| e.to_string())?
+        .read()
+        .map_err(|e| e.to_string())
+}
+
+fn tag_string(tag: &Tag, key: ItemKey) -> Option<String> {
+    tag.get_string(&key)
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+}
+
+// Comments are often left over by rippers and encoders, like "Track 1" or
+// a URL; we keep them anyway, but not the empty ones
+fn tag_comment(tag: &Tag) -> Option<String> {
+    tag.comment()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+// Dates are written as "YYYY", "YYYY-MM-DD", or "YYYY-MM-DDTHH:MM:SS"
+fn year_from_date(date: &str) -> Option<u32> {
+    date.get(..4)
+        .and_then(|y| y.parse::<u32>().ok())
+        .filter(|y| *y > 0)
+}
+
+fn codec_name(file_type: FileType) -> Option<String> {
+    let name = match file_type {
+        FileType::Aac => "AAC",
+        FileType::Aiff => "AIFF",
+        FileType::Ape => "Monkey's Audio",
+        FileType::Flac => "FLAC",
+        FileType::Mpeg => "MP3",
+        FileType::Mp4 => "MP4",
+        FileType::Mpc => "Musepack",
+        FileType::Opus => "Opus",
+        FileType::Vorbis => "Vorbis",
+        FileType::Speex => "Speex",
+        FileType::Wav => "WAV",
+        FileType::WavPack => "WavPack",
+        _ => return None,
+    };
+
+    Some(name.to_string())
+}
+
+// Some taggers store fractional tempos, so we round them
+fn tag_bpm(tag: &Tag) -> Option<u32> {
+    tag.get_string(&ItemKey::Bpm)
+        .or_else(|| tag.get_string(&ItemKey::IntegerBpm))
+        .and_then(|s| s.trim().parse::<f32>().ok())
+        .filter(|bpm| *bpm > 0.0)
+        .map(|bpm| bpm.round() as u32)
+}
+
+// Some formats store one value per artist, or have a separate field
+// listing all of them
+fn tag_artists(tag: &Tag) -> Vec<String> {
+    let values =
+        |key: &ItemKey| -> Vec<String> { tag.get_strings(key).map(|s| s.to_string()).collect() };
+
+    let artists = values(&ItemKey::TrackArtists);
+    if artists.is_empty() {
+        values(&ItemKey::TrackArtist)
+    } else {
+        artists
+    }
+}
+
+// Tags are compared case-insensitively, and only if both songs have them
+fn same_tag(a: Option<&str>, b: Option<&str>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.to_lowercase() == b.to_lowercase(),
+        _ => false,
+    }
+}
+
 impl Default for SongData {
     fn default() -> Self {
         SongData {
             artist: Some("Invalid Artist".to_string()),
+            artists: Vec::new(),
             title: Some("Invalid Title".to_string()),
             album: Some("Invalid Album".to_string()),
+            album_artist: None,
+            genre: None,
+            artist_sort: None,
+            album_sort: None,
+            title_sort: None,
+            bpm: None,
+            rating: None,
+            track_number: None,
+            track_total: None,
+            disc_number: None,
+            disc_total: None,
+            year: None,
+            date: None,
+            composer: None,
+            comment: None,
+            codec: None,
+            bitrate: None,
+            sample_rate: None,
+            bit_depth: None,
+            channels: None,
+            file_size: 0,
             cover_art: None,
             cover_uuid: None,
             uuid: None,
@@ -200,14 +602,62 @@ impl Default for SongData {
     }
 }
 
+// Collation keys for sorting songs, built once; they follow the locale,
+// and prefer the sort tags over the displayed names
+#[derive(Debug)]
+pub struct SortKeys {
+    pub title: glib::CollationKey,
+    pub artist: glib::CollationKey,
+    pub album: glib::CollationKey,
+}
+
+// The tags and technical details of a song, as read-only properties; the
+// PlayerState forwards the same properties for the current song. Numbers
+// we do not know are 0
+pub(super) fn tag_param_specs() -> Vec<ParamSpec> {
+    vec![
+        ParamSpecString::builder("album-artist").read_only().build(),
+        ParamSpecString::builder("genre").read_only().build(),
+        ParamSpecUInt::builder("track-number").read_only().build(),
+        ParamSpecUInt::builder("track-total").read_only().build(),
+        ParamSpecUInt::builder("disc-number").read_only().build(),
+        ParamSpecUInt::builder("disc-total").read_only().build(),
+        ParamSpecUInt::builder("year").read_only().build(),
+        ParamSpecString::builder("date").read_only().build(),
+        ParamSpecString::builder("composer").read_only().build(),
+        ParamSpecString::builder("comment").read_only().build(),
+        ParamSpecString::builder("codec").read_only().build(),
+        ParamSpecUInt::builder("bitrate").read_only().build(),
+        ParamSpecUInt::builder("sample-rate").read_only().build(),
+        ParamSpecUInt::builder("bit-depth").read_only().build(),
+        ParamSpecUInt::builder("channels").read_only().build(),
+        ParamSpecUInt64::builder("file-size").read_only().build(),
+        ParamSpecUInt::builder("rating")
+            .maximum(MAX_RATING)
+            .read_only()
+            .build(),
+    ]
+}
+
 mod imp {
     use super::*;
 
     #[derive(Debug, Default)]
     pub struct Song {
         pub data: RefCell<SongData>,
+        pub sort_keys: RefCell<Option<SortKeys>>,
+        // Split once, as it happens all the time while searching
+        pub artists: RefCell<Option<Vec<String>>>,
+        // The generation of the artist separators the caches come from
+        pub separators_generation: Cell<u32>,
+        // Folded once, as searching goes through every song on each key
+        // press, and sorting by a search does so many times
+        pub search_fields: RefCell<Option<SearchFields>>,
         pub playing: Cell<bool>,
         pub selected: Cell<bool>,
+        pub added: Cell<i64>,
+        pub auto_added: Cell<bool>,
+        pub skip_shuffle: Cell<bool>,
     }
 
     #[glib::object_subclass]
@@ -219,7 +669,7 @@ mod imp {
     impl ObjectImpl for Song {
         fn properties() -> &'static [ParamSpec] {
             static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| {
-                vec![
+                let mut props = vec![
                     ParamSpecString::builder("uri").construct_only().build(),
                     ParamSpecString::builder("artist").read_only().build(),
                     ParamSpecString::builder("title").read_only().build(),
@@ -230,7 +680,11 @@ mod imp {
                         .build(),
                     ParamSpecBoolean::builder("playing").build(),
                     ParamSpecBoolean::builder("selected").build(),
-                ]
+                    ParamSpecBoolean::builder("auto-added").build(),
+                    ParamSpecBoolean::builder("skip-shuffle").build(),
+                ];
+                props.extend(tag_param_specs());
+                props
             });
             PROPERTIES.as_ref()
         }
@@ -241,11 +695,10 @@ mod imp {
                     let obj = self.obj();
                     if let Ok(p) = value.get::<&str>() {
                         self.data.replace(SongData::from_uri(p));
-                        obj.notify("artist");
-                        obj.notify("title");
-                        obj.notify("album");
-                        obj.notify("duration");
-                        obj.notify("cover");
+                        self.sort_keys.replace(None);
+                        self.artists.replace(None);
+                        self.search_fields.replace(None);
+                        obj.notify_data();
                     }
                 }
                 "playing" => {
@@ -256,6 +709,14 @@ mod imp {
                     let p = value.get::<bool>().expect("Value must be a boolean");
                     self.selected.set(p);
                 }
+                "auto-added" => {
+                    let p = value.get::<bool>().expect("Value must be a boolean");
+                    self.auto_added.set(p);
+                }
+                "skip-shuffle" => {
+                    let p = value.get::<bool>().expect("Value must be a boolean");
+                    self.skip_shuffle.set(p);
+                }
                 _ => unimplemented!(),
             }
         }
@@ -271,6 +732,25 @@ mod imp {
                 "cover" => obj.cover_texture().to_value(),
                 "playing" => self.playing.get().to_value(),
                 "selected" => self.selected.get().to_value(),
+                "auto-added" => self.auto_added.get().to_value(),
+                "skip-shuffle" => self.skip_shuffle.get().to_value(),
+                "album-artist" => obj.album_artist().to_value(),
+                "genre" => obj.genre().to_value(),
+                "track-number" => obj.track_number().unwrap_or(0).to_value(),
+                "track-total" => obj.track_total().unwrap_or(0).to_value(),
+                "disc-number" => obj.disc_number().unwrap_or(0).to_value(),
+                "disc-total" => obj.disc_total().unwrap_or(0).to_value(),
+                "year" => obj.year().unwrap_or(0).to_value(),
+                "date" => obj.date().to_value(),
+                "composer" => obj.composer().to_value(),
+                "comment" => obj.comment().to_value(),
+                "codec" => obj.codec().to_value(),
+                "bitrate" => obj.bitrate().unwrap_or(0).to_value(),
+                "sample-rate" => obj.sample_rate().unwrap_or(0).to_value(),
+                "bit-depth" => obj.bit_depth().unwrap_or(0).to_value(),
+                "channels" => obj.channels().unwrap_or(0).to_value(),
+                "file-size" => obj.file_size().to_value(),
+                "rating" => obj.rating().to_value(),
                 _ => unimplemented!(),
             }
         }
@@ -295,10 +775,36 @@ impl Song {
         }
     }
 
+    // Builds a song out of the metadata loaded by a SongLoader
+    pub fn from_data(data: SongData) -> Result<Song, &'static str> {
+        let res = Song::empty();
+        res.imp().data.replace(data);
+        if res.equals(&Song::default()) {
+            Err("Invalid song")
+        } else {
+            Ok(res)
+        }
+    }
+
     pub fn empty() -> Self {
         glib::Object::new()
     }
 
+    // Builds a song without reading any file
+    #[cfg(test)]
+    pub fn with_tags(uri: &str, title: &str, artist: &str, album: &str) -> Self {
+        let res = Song::empty();
+        res.imp().data.replace(SongData {
+            title: Some(title.to_string()),
+            artist: Some(artist.to_string()),
+            album: Some(album.to_string()),
+            uuid: Some(format!("{uri}#{title}")),
+            file: gio::File::for_uri(uri),
+            ..SongData::default()
+        });
+        res
+    }
+
     pub fn equals(&self, other: &Self) -> bool {
         if self.uuid().is_some() && other.uuid().is_some() {
             self.uuid() == other.uuid()
@@ -311,13 +817,65 @@ impl Song {
         self.imp().data.borrow().uri()
     }
 
+    // All the artists, as displayed
     pub fn artist(&self) -> String {
-        match self.imp().data.borrow().artist() {
-            Some(artist) => artist.to_string(),
-            None => i18n("Unknown artist"),
+        self.artists().join(", ")
+    }
+
+    // The names of the artists, with the tag values split at the
+    // separators from the settings
+    pub fn artists(&self) -> Vec<String> {
+        let artists = self.tagged_artists();
+        if artists.is_empty() {
+            vec![i18n("Unknown artist")]
+        } else {
+            artists
         }
     }
 
+    // Like artists(), without the fallback for songs without artist tags
+    pub fn tagged_artists(&self) -> Vec<String> {
+        self.check_artist_separators();
+        let imp = self.imp();
+        if let Some(artists) = imp.artists.borrow().as_ref() {
+            return artists.clone();
+        }
+
+        let artists = {
+            let data = imp.data.borrow();
+            utils::with_artist_separators(|separators| {
+                if data.artists().is_empty() {
+                    utils::split_artists(
+                        &data.artist().into_iter().collect::<Vec<&str>>(),
+                        separators,
+                    )
+                } else {
+                    utils::split_artists(data.artists(), separators)
+                }
+            })
+        };
+        imp.artists.replace(Some(artists.clone()));
+        artists
+    }
+
+    // The split artists, and the sort keys and search fields made from
+    // them, go stale when the separators change
+    fn check_artist_separators(&self) {
+        let imp = self.imp();
+        let generation = utils::artist_separators_generation();
+        if imp.separators_generation.replace(generation) != generation {
+            imp.artists.replace(None);
+            imp.sort_keys.replace(None);
+            imp.search_fields.replace(None);
+        }
+    }
+
+    // The artist separators changed: tells the views showing the song
+    pub fn refresh_artists(&self) {
+        self.check_artist_separators();
+        self.notify("artist");
+    }
+
     pub fn title(&self) -> String {
         match self.imp().data.borrow().title() {
             Some(title) => title.to_string(),
@@ -332,6 +890,75 @@ impl Song {
         }
     }
 
+    // The title tag, without the fallback
+    pub fn tagged_title(&self) -> Option<String> {
+        self.imp().data.borrow().title().map(|t| t.to_string())
+    }
+
+    // The album tag, without the fallback
+    pub fn tagged_album(&self) -> Option<String> {
+        self.imp().data.borrow().album().map(|a| a.to_string())
+    }
+
+    pub fn sort_keys(&self) -> Ref<'_, SortKeys> {
+        self.check_artist_separators();
+        let imp = self.imp();
+        if imp.sort_keys.borrow().is_none() {
+            let keys = {
+                let data = imp.data.borrow();
+                SortKeys {
+                    title: glib::CollationKey::from(data.title_sort().unwrap_or(&self.title())),
+                    artist: glib::CollationKey::from(data.artist_sort().unwrap_or(&self.artist())),
+                    album: glib::CollationKey::from(data.album_sort().unwrap_or(&self.album())),
+                }
+            };
+            imp.sort_keys.replace(Some(keys));
+        }
+
+        Ref::map(imp.sort_keys.borrow(), |keys| keys.as_ref().unwrap())
+    }
+
+    pub fn search_fields(&self) -> Ref<'_, SearchFields> {
+        self.check_artist_separators();
+        let imp = self.imp();
+        if imp.search_fields.borrow().is_none() {
+            let fields = SearchFields::from_song(self);
+            imp.search_fields.replace(Some(fields));
+        }
+
+        Ref::map(imp.search_fields.borrow(), |fields| {
+            fields.as_ref().unwrap()
+        })
+    }
+
+    pub fn artist_sort(&self) -> Option<String> {
+        self.imp()
+            .data
+            .borrow()
+            .artist_sort()
+            .map(|s| s.to_string())
+    }
+
+    pub fn album_sort(&self) -> Option<String> {
+        self.imp().data.borrow().album_sort().map(|s| s.to_string())
+    }
+
+    pub fn title_sort(&self) -> Option<String> {
+        self.imp().data.borrow().title_sort().map(|s| s.to_string())
+    }
+
+    pub fn album_artist(&self) -> Option<String> {
+        self.imp()
+            .data
+            .borrow()
+            .album_artist()
+            .map(|s| s.to_string())
+    }
+
+    pub fn genre(&self) -> Option<String> {
+        self.imp().data.borrow().genre().map(|s| s.to_string())
+    }
+
     pub fn cover_texture(&self) -> Option<gdk::Texture> {
         self.imp().data.borrow().cover_texture().cloned()
     }
@@ -356,6 +983,97 @@ impl Song {
         self.imp().data.borrow().duration()
     }
 
+    pub fn track_number(&self) -> Option<u32> {
+        self.imp().data.borrow().track_number()
+    }
+
+    pub fn track_total(&self) -> Option<u32> {
+        self.imp().data.borrow().track_total()
+    }
+
+    pub fn disc_number(&self) -> Option<u32> {
+        self.imp().data.borrow().disc_number()
+    }
+
+    pub fn disc_total(&self) -> Option<u32> {
+        self.imp().data.borrow().disc_total()
+    }
+
+    pub fn year(&self) -> Option<u32> {
+        self.imp().data.borrow().year()
+    }
+
+    pub fn date(&self) -> Option<String> {
+        self.imp().data.borrow().date().map(|s| s.to_string())
+    }
+
+    pub fn composer(&self) -> Option<String> {
+        self.imp().data.borrow().composer().map(|s| s.to_string())
+    }
+
+    pub fn comment(&self) -> Option<String> {
+        self.imp().data.borrow().comment().map(|s| s.to_string())
+    }
+
+    pub fn codec(&self) -> Option<String> {
+        self.imp().data.borrow().codec().map(|s| s.to_string())
+    }
+
+    pub fn bitrate(&self) -> Option<u32> {
+        self.imp().data.borrow().bitrate()
+    }
+
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.imp().data.borrow().sample_rate()
+    }
+
+    pub fn bit_depth(&self) -> Option<u32> {
+        self.imp().data.borrow().bit_depth()
+    }
+
+    pub fn channels(&self) -> Option<u32> {
+        self.imp().data.borrow().channels()
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.imp().data.borrow().file_size()
+    }
+
+    // In stars, from 0 for songs we did not rate to MAX_RATING; what we
+    // could not write into the file wins over the tags
+    pub fn rating(&self) -> u32 {
+        let stored = RatingStore::global().lock().unwrap().rating(&self.uri());
+        stored
+            .or_else(|| self.imp().data.borrow().rating())
+            .unwrap_or(0)
+    }
+
+    // Once the rating is written into the tags, we do not need to keep
+    // it on the side any more
+    pub fn set_rating(&self, rating: u32, in_tags: bool) {
+        let uri = self.uri();
+        if in_tags {
+            self.imp().data.borrow_mut().rating = Some(rating).filter(|r| *r > 0);
+            RatingStore::global().lock().unwrap().remove(&uri);
+        } else {
+            RatingStore::global()
+                .lock()
+                .unwrap()
+                .set_rating(&uri, rating);
+        }
+        self.imp().search_fields.replace(None);
+        self.notify("rating");
+    }
+
+    // The time at which the song was added to the queue, in microseconds
+    pub fn added(&self) -> i64 {
+        self.imp().added.get()
+    }
+
+    pub fn set_added(&self, added: i64) {
+        self.imp().added.set(added);
+    }
+
     pub fn playing(&self) -> bool {
         self.imp().playing.get()
     }
@@ -378,17 +1096,147 @@ impl Song {
         }
     }
 
+    // Whether the song was added to the queue by the radio
+    pub fn auto_added(&self) -> bool {
+        self.imp().auto_added.get()
+    }
+
+    pub fn set_auto_added(&self, auto_added: bool) {
+        let was_auto_added = self.imp().auto_added.replace(auto_added);
+        if was_auto_added != auto_added {
+            self.notify("auto-added");
+        }
+    }
+
+    // Whether the song, its artist or its album are never played in
+    // shuffled order
+    pub fn skip_shuffle(&self) -> bool {
+        self.imp().skip_shuffle.get()
+    }
+
+    pub fn set_skip_shuffle(&self, skip_shuffle: bool) {
+        let was_skip_shuffle = self.imp().skip_shuffle.replace(skip_shuffle);
+        if was_skip_shuffle != skip_shuffle {
+            self.notify("skip-shuffle");
+        }
+    }
+
     pub fn uuid(&self) -> Option<String> {
         self.imp().data.borrow().uuid().map(|s| s.to_string())
     }
 
-    pub fn search_key(&self) -> String {
-        format!("{} {} {}", self.artist(), self.album(), self.title())
+    // How close this song is to another one; the radio uses this to find
+    // songs that go well after the ones we played
+    pub fn similarity(&self, other: &Song) -> u32 {
+        let other_artists: Vec<String> = other.artists().iter().map(|a| a.to_lowercase()).collect();
+        let shared_artist = self
+            .artists()
+            .iter()
+            .any(|a| other_artists.contains(&a.to_lowercase()));
+
+        let a = self.imp().data.borrow();
+        let b = other.imp().data.borrow();
+
+        let mut score = 0;
+        if shared_artist && a.artist().is_some() && b.artist().is_some() {
+            score += 4;
+        }
+        if same_tag(a.album_artist(), b.album_artist()) {
+            score += 3;
+        }
+        if same_tag(a.genre(), b.genre()) {
+            score += 2;
+        }
+        if let (Some(year_a), Some(year_b)) = (a.year(), b.year()) {
+            if year_a.abs_diff(year_b) <= 5 {
+                score += 1;
+            }
+        }
+        if let (Some(bpm_a), Some(bpm_b)) = (a.bpm(), b.bpm()) {
+            // Within 8% of each other
+            if bpm_a.abs_diff(bpm_b) * 100 <= bpm_a.max(bpm_b) * 8 {
+                score += 1;
+            }
+        }
+
+        score
+    }
+
+    // Songs from the same album share the same key; we include the
+    // folder, so that different albums with the same title do not
+    // get merged together
+    pub fn album_key(&self) -> String {
+        let data = self.imp().data.borrow();
+        match data.album() {
+            Some(album) => {
+                let folder = data.file().parent().map(|p| p.uri().to_string());
+                format!("{}\u{0}{}", album, folder.unwrap_or_default())
+            }
+            None => data.uri(),
+        }
     }
 
     pub fn file(&self) -> gio::File {
         self.imp().data.borrow().file()
     }
+
+    pub(super) fn data(&self) -> SongData {
+        self.imp().data.borrow().clone()
+    }
+
+    // The value of a tag as written in the file, without any fallback
+    pub fn tag_text(&self, field: TagField) -> String {
+        let data = self.imp().data.borrow();
+        let text = match field {
+            TagField::Title => data.title().map(|s| s.to_string()),
+            // Written back as a single value, which we split again
+            TagField::Artist if data.artists().len() > 1 => Some(data.artists().join("; ")),
+            TagField::Artist => data.artist().map(|s| s.to_string()),
+            TagField::Album => data.album().map(|s| s.to_string()),
+            TagField::AlbumArtist => data.album_artist().map(|s| s.to_string()),
+            TagField::Genre => data.genre().map(|s| s.to_string()),
+            TagField::Composer => data.composer().map(|s| s.to_string()),
+            TagField::Comment => data.comment().map(|s| s.to_string()),
+            TagField::Year => data.year().map(|n| n.to_string()),
+            TagField::TrackNumber => data.track_number().map(|n| n.to_string()),
+            TagField::TrackTotal => data.track_total().map(|n| n.to_string()),
+            TagField::DiscNumber => data.disc_number().map(|n| n.to_string()),
+            TagField::DiscTotal => data.disc_total().map(|n| n.to_string()),
+        };
+
+        text.unwrap_or_default()
+    }
+
+    fn notify_data(&self) {
+        self.notify("artist");
+        self.notify("title");
+        self.notify("album");
+        self.notify("duration");
+        self.notify("cover");
+        for pspec in tag_param_specs() {
+            self.notify(pspec.name());
+        }
+    }
+
+    // Uses the tags we read again with SongData::reload()
+    pub fn set_reloaded_data(&self, data: SongData) {
+        if let Some(old_uuid) = self.uuid() {
+            if data.uuid() != Some(old_uuid.as_str()) {
+                invalidate_cached_peaks(&old_uuid);
+            }
+        }
+
+        self.imp().data.replace(data);
+        self.imp().sort_keys.replace(None);
+        self.imp().artists.replace(None);
+        self.imp().search_fields.replace(None);
+        self.notify_data();
+    }
+
+    // The file was moved or renamed, but it's still the same song
+    pub fn set_file(&self, file: &gio::File) {
+        self.imp().data.borrow_mut().file = file.clone();
+    }
 }
 
 impl Default for Song {
@@ -0,0 +1,124 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use async_channel::Receiver;
+use gtk::{gio, prelude::*};
+use log::debug;
+
+use crate::audio::{metadata_cache::MetadataCache, SongData};
+
+// The most worker threads we use for a single load; reading tags is
+// mostly I/O bound, so more threads would just fight over the disk
+const MAX_WORKERS: usize = 4;
+
+// How many loaded songs can pile up before the workers wait for the
+// main context to catch up
+const BACKLOG_SIZE: usize = 256;
+
+// Reading tags and decoding covers is the slow part of adding songs to
+// the queue, so a SongLoader spreads the files across a few worker
+// threads, and sends the metadata back to the main context as soon as
+// it's ready. Each result comes with the position of its file in the
+// list we were given, since the workers do not finish in order.
+#[derive(Debug)]
+pub struct SongLoader {
+    receiver: Receiver<(usize, SongData)>,
+    cancellable: gio::Cancellable,
+    n_files: u32,
+}
+
+impl SongLoader {
+    pub fn new(files: Vec<gio::File>) -> Self {
+        let n_files = files.len();
+        let n_workers = thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .clamp(1, MAX_WORKERS)
+            .min(n_files.max(1));
+
+        debug!("Loading {} files with {} workers", n_files, n_workers);
+
+        let files = Arc::new(Mutex::new(
+            files.into_iter().enumerate().collect::<VecDeque<_>>(),
+        ));
+        let (sender, receiver) = async_channel::bounded(BACKLOG_SIZE);
+        let cancellable = gio::Cancellable::new();
+        let running = Arc::new(AtomicUsize::new(n_workers));
+
+        for _ in 0..n_workers {
+            let files = files.clone();
+            let sender = sender.clone();
+            let cancellable = cancellable.clone();
+            let running = running.clone();
+            let _ = gio::spawn_blocking(move || {
+                while !cancellable.is_cancelled() {
+                    let next = files.lock().unwrap().pop_front();
+                    if let Some((pos, file)) = next {
+                        let data = SongData::from_uri(file.uri().as_str());
+                        if sender.send_blocking((pos, data)).is_err() {
+                            break;
+                        }
+                    } else {
+                        break;
+                    }
+                }
+
+                drop(sender);
+
+                // The last worker to finish writes down the metadata we
+                // read, so that the next load can skip the tags
+                if running.fetch_sub(1, Ordering::AcqRel) == 1 {
+                    MetadataCache::global().lock().unwrap().save();
+                }
+            });
+        }
+
+        Self {
+            receiver,
+            cancellable,
+            n_files: n_files as u32,
+        }
+    }
+
+    pub fn n_files(&self) -> u32 {
+        self.n_files
+    }
+
+    pub fn cancellable(&self) -> &gio::Cancellable {
+        &self.cancellable
+    }
+
+    // Waits for the next song, and returns it along with all the other
+    // songs that are ready, up to `max_batch`; an empty batch means that
+    // the load is complete, or that it was cancelled
+    pub async fn next_batch(&self, max_batch: usize) -> Vec<(usize, SongData)> {
+        let mut batch = Vec::new();
+        if let Ok(res) = self.receiver.recv().await {
+            batch.push(res);
+            while batch.len() < max_batch {
+                match self.receiver.try_recv() {
+                    Ok(res) => batch.push(res),
+                    Err(_) => break,
+                }
+            }
+        }
+
+        batch
+    }
+}
+
+impl Drop for SongLoader {
+    fn drop(&mut self) {
+        // Let the workers go if nobody is waiting for the results
+        self.cancellable.cancel();
+        self.receiver.close();
+    }
+}
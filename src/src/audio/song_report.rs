@@ -0,0 +1,345 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::fmt::Write;
+
+use gst_pbutils::prelude::*;
+use gtk::{gio, glib, prelude::*};
+use lofty::{
+    picture::{Picture, PictureInformation, PictureType},
+    prelude::TaggedFileExt,
+    tag::{ItemValue, Tag},
+};
+use log::warn;
+
+use crate::{
+    audio::{
+        metadata_cache::{FileStamp, MetadataCache},
+        song::read_tagged_file,
+        waveform_generator::waveform_cache_path,
+        Song,
+    },
+    i18n::{i18n, i18n_f, ni18n_f},
+    utils,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct ReportRow {
+    pub label: String,
+    pub value: String,
+    // An embedded picture, as stored in the file
+    pub image: Option<glib::Bytes>,
+}
+
+impl ReportRow {
+    fn new(label: &str, value: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            value: value.to_string(),
+            image: None,
+        }
+    }
+}
+
+// A group of properties of a song, e.g. its tags
+#[derive(Clone, Debug, Default)]
+pub struct ReportSection {
+    pub title: String,
+    pub rows: Vec<ReportRow>,
+}
+
+// The report as plain text, for bug reports and the like
+pub fn report_text(sections: &[ReportSection]) -> String {
+    let mut res = String::new();
+    for section in sections.iter().filter(|s| !s.rows.is_empty()) {
+        if !res.is_empty() {
+            res.push('\n');
+        }
+        let _ = writeln!(res, "{}", section.title);
+        for row in &section.rows {
+            let _ = writeln!(res, "{}: {}", row.label, row.value);
+        }
+    }
+    res
+}
+
+fn tag_section(tag: &Tag) -> ReportSection {
+    let rows = tag
+        .items()
+        .map(|item| {
+            // The key as the tag format spells it, e.g. TIT2 or TITLE
+            let label = item
+                .key()
+                .map_key(tag.tag_type(), true)
+                .map(|k| k.to_string())
+                .unwrap_or_else(|| format!("{:?}", item.key()));
+            let value = match item.value() {
+                ItemValue::Text(text) | ItemValue::Locator(text) => text.clone(),
+                ItemValue::Binary(data) => ni18n_f(
+                    // Translators: the `{}` must be left unmodified;
+                    // it will be expanded to a number of bytes
+                    "{} byte of binary data",
+                    "{} bytes of binary data",
+                    data.len() as u32,
+                    &[&data.len().to_string()],
+                ),
+            };
+            ReportRow::new(&label, &value)
+        })
+        .collect();
+
+    ReportSection {
+        // Translators: the `{}` must be left unmodified;
+        // it will be expanded to the tag format, e.g. ID3v2
+        title: i18n_f("{} Tag", &[&format!("{:?}", tag.tag_type())]),
+        rows,
+    }
+}
+
+fn picture_row(picture: &Picture) -> ReportRow {
+    let label = match picture.pic_type() {
+        PictureType::CoverFront => i18n("Front Cover"),
+        PictureType::CoverBack => i18n("Back Cover"),
+        PictureType::Artist | PictureType::LeadArtist => i18n("Artist"),
+        PictureType::Media => i18n("Media"),
+        other => format!("{:?}", other),
+    };
+
+    let mut details = vec![];
+    if let Some(mime_type) = picture.mime_type() {
+        details.push(mime_type.as_str().to_string());
+    }
+    if let Ok(info) = PictureInformation::from_picture(picture) {
+        details.push(format!("{} × {}", info.width, info.height));
+    }
+    details.push(glib::format_size(picture.data().len() as u64).to_string());
+
+    ReportRow {
+        label,
+        value: details.join(", "),
+        image: Some(glib::Bytes::from(picture.data())),
+    }
+}
+
+fn discover(file: &gio::File) -> Result<gst_pbutils::DiscovererInfo, glib::Error> {
+    let discoverer = gst_pbutils::Discoverer::new(gst::ClockTime::from_seconds(10))?;
+    discoverer.discover_uri(&file.uri())
+}
+
+fn codec_description(caps: Option<gst::Caps>) -> Option<String> {
+    caps.map(|caps| gst_pbutils::pb_utils_get_codec_description(&caps).to_string())
+}
+
+// What GStreamer finds in the file, which is what we actually play
+fn stream_section(info: &gst_pbutils::DiscovererInfo) -> ReportSection {
+    let mut rows = vec![];
+    if let Some(container) = info
+        .container_streams()
+        .first()
+        .and_then(|s| codec_description(s.caps()))
+    {
+        rows.push(ReportRow::new(&i18n("Container"), &container));
+    }
+    if let Some(duration) = info.duration() {
+        rows.push(ReportRow::new(
+            &i18n("Duration"),
+            &utils::format_time(duration.seconds() as i64),
+        ));
+    }
+
+    let streams = info.audio_streams();
+    for (i, stream) in streams.iter().enumerate() {
+        // Files with a single stream are the norm; only number them
+        // when there are more
+        let prefix = if streams.len() > 1 {
+            format!("#{} ", i + 1)
+        } else {
+            String::new()
+        };
+        let mut push = |label: String, value: String| {
+            rows.push(ReportRow::new(&format!("{prefix}{label}"), &value));
+        };
+
+        if let Some(codec) = codec_description(stream.caps()) {
+            push(i18n("Codec"), codec);
+        }
+        push(i18n("Channels"), stream.channels().to_string());
+        push(
+            i18n("Sample Rate"),
+            // Translators: the `{}` must be left unmodified;
+            // it will be expanded to a number of samples per second
+            i18n_f("{} Hz", &[&stream.sample_rate().to_string()]),
+        );
+        if stream.depth() > 0 {
+            push(
+                i18n("Bit Depth"),
+                // Translators: the `{}` must be left unmodified;
+                // it will be expanded to a number of bits
+                i18n_f("{} bits", &[&stream.depth().to_string()]),
+            );
+        }
+        if stream.bitrate() > 0 {
+            push(
+                i18n("Bitrate"),
+                // Translators: the `{}` must be left unmodified;
+                // it will be expanded to a number of kilobits per second
+                i18n_f("{} kbps", &[&(stream.bitrate() / 1000).to_string()]),
+            );
+        }
+        if stream.max_bitrate() > 0 {
+            push(
+                i18n("Maximum Bitrate"),
+                i18n_f("{} kbps", &[&(stream.max_bitrate() / 1000).to_string()]),
+            );
+        }
+    }
+
+    rows.push(ReportRow::new(
+        &i18n("Seekable"),
+        &if info.is_seekable() {
+            i18n("Yes")
+        } else {
+            i18n("No")
+        },
+    ));
+
+    ReportSection {
+        title: i18n("Stream"),
+        rows,
+    }
+}
+
+// The values the player uses when ReplayGain is enabled
+fn replaygain_section(info: &gst_pbutils::DiscovererInfo) -> ReportSection {
+    let mut rows = vec![];
+    if let Some(tags) = info.tags() {
+        let gain = |value: f64| format!("{:+.2} dB", value);
+        let peak = |value: f64| format!("{:.6}", value);
+
+        if let Some(value) = tags.get::<gst::tags::TrackGain>() {
+            rows.push(ReportRow::new(&i18n("Track Gain"), &gain(value.get())));
+        }
+        if let Some(value) = tags.get::<gst::tags::TrackPeak>() {
+            rows.push(ReportRow::new(&i18n("Track Peak"), &peak(value.get())));
+        }
+        if let Some(value) = tags.get::<gst::tags::AlbumGain>() {
+            rows.push(ReportRow::new(&i18n("Album Gain"), &gain(value.get())));
+        }
+        if let Some(value) = tags.get::<gst::tags::AlbumPeak>() {
+            rows.push(ReportRow::new(&i18n("Album Peak"), &peak(value.get())));
+        }
+        if let Some(value) = tags.get::<gst::tags::ReferenceLevel>() {
+            rows.push(ReportRow::new(
+                &i18n("Reference Level"),
+                &format!("{:.1} dB", value.get()),
+            ));
+        }
+    }
+
+    ReportSection {
+        title: i18n("ReplayGain"),
+        rows,
+    }
+}
+
+// Reads the tags and pictures of a file, and runs a GStreamer discoverer
+// on it; this blocks, so it should run in a thread
+pub fn inspect_file(file: &gio::File) -> Vec<ReportSection> {
+    let mut sections = vec![];
+
+    match read_tagged_file(file) {
+        Ok(tagged_file) => {
+            sections.extend(tagged_file.tags().iter().map(tag_section));
+            sections.push(ReportSection {
+                title: i18n("Pictures"),
+                rows: tagged_file
+                    .tags()
+                    .iter()
+                    .flat_map(|tag| tag.pictures())
+                    .map(picture_row)
+                    .collect(),
+            });
+        }
+        Err(e) => warn!("Unable to read the tags of {}: {}", file.uri(), e),
+    }
+
+    match discover(file) {
+        Ok(info) => {
+            sections.push(stream_section(&info));
+            sections.push(replaygain_section(&info));
+        }
+        Err(e) => warn!("Unable to discover {}: {}", file.uri(), e),
+    }
+
+    sections
+}
+
+// What we keep about the song on disk, to load it faster
+pub fn cache_section(song: &Song) -> ReportSection {
+    let cached = |is_cached: bool| {
+        if is_cached {
+            i18n("Cached")
+        } else {
+            i18n("Not cached")
+        }
+    };
+
+    let file = song.file();
+    let metadata_cached = FileStamp::for_file(&file)
+        .and_then(|stamp| {
+            MetadataCache::global()
+                .lock()
+                .unwrap()
+                .lookup(&file.uri(), stamp)
+        })
+        .is_some();
+
+    let cover = song
+        .cover_uuid()
+        .map(|uuid| utils::cover_cache_path(&uuid))
+        .filter(|path| path.exists())
+        .map(|path| path.display().to_string());
+    let waveform_cached = song
+        .uuid()
+        .is_some_and(|uuid| waveform_cache_path(&uuid).exists());
+
+    ReportSection {
+        title: i18n("Cache"),
+        rows: vec![
+            ReportRow::new(&i18n("Tags"), &cached(metadata_cached)),
+            ReportRow::new(&i18n("Cover"), &cover.unwrap_or_else(|| cached(false))),
+            ReportRow::new(&i18n("Waveform"), &cached(waveform_cached)),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_text() {
+        let sections = [
+            ReportSection {
+                title: "File".to_string(),
+                rows: vec![ReportRow::new("Location", "/music/song.flac")],
+            },
+            ReportSection {
+                title: "Pictures".to_string(),
+                rows: vec![],
+            },
+            ReportSection {
+                title: "Stream".to_string(),
+                rows: vec![
+                    ReportRow::new("Codec", "FLAC"),
+                    ReportRow::new("Channels", "2"),
+                ],
+            },
+        ];
+
+        assert_eq!(
+            report_text(&sections),
+            "File\nLocation: /music/song.flac\n\nStream\nCodec: FLAC\nChannels: 2\n"
+        );
+    }
+}
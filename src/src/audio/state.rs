@@ -5,7 +5,7 @@ use std::cell::{Cell, RefCell};
 
 use gtk::{gdk, glib, prelude::*, subclass::prelude::*};
 
-use crate::audio::{PlaybackState, Song};
+use crate::audio::{song::tag_param_specs, PlaybackState, Song};
 
 mod imp {
     use glib::{
@@ -19,6 +19,7 @@ mod imp {
     #[derive(Debug)]
     pub struct PlayerState {
         pub playback_state: Cell<PlaybackState>,
+        // In milliseconds, for the lyrics; everything else uses seconds
         pub position: Cell<u64>,
         pub current_song: RefCell<Option<Song>>,
         pub volume: Cell<f64>,
@@ -42,7 +43,7 @@ mod imp {
     impl ObjectImpl for PlayerState {
         fn properties() -> &'static [ParamSpec] {
             static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| {
-                vec![
+                let mut props = vec![
                     ParamSpecBoolean::builder("playing").read_only().build(),
                     ParamSpecUInt64::builder("position").read_only().build(),
                     ParamSpecObject::builder::<Song>("song").read_only().build(),
@@ -59,7 +60,9 @@ mod imp {
                         .default_value(1.0)
                         .read_only()
                         .build(),
-                ]
+                ];
+                props.extend(tag_param_specs());
+                props
             });
             PROPERTIES.as_ref()
         }
@@ -78,7 +81,11 @@ mod imp {
                 "album" => obj.album().to_value(),
                 "duration" => obj.duration().to_value(),
                 "cover" => obj.cover().to_value(),
-                _ => unimplemented!(),
+                // ... and so are the tags
+                name => match &*self.current_song.borrow() {
+                    Some(song) => song.property_value(name),
+                    None => pspec.default_value().clone(),
+                },
             }
         }
     }
@@ -161,14 +168,36 @@ impl PlayerState {
         self.notify("album");
         self.notify("duration");
         self.notify("cover");
+        for pspec in tag_param_specs() {
+            self.notify(pspec.name());
+        }
         self.notify("position");
     }
 
+    // The tags of the current song changed
+    pub fn refresh_song(&self) {
+        self.notify("title");
+        self.notify("artist");
+        self.notify("album");
+        self.notify("cover");
+        for pspec in tag_param_specs() {
+            self.notify(pspec.name());
+        }
+    }
+
     pub fn position(&self) -> u64 {
+        self.imp().position.get() / 1000
+    }
+
+    pub fn position_ms(&self) -> u64 {
         self.imp().position.get()
     }
 
     pub fn set_position(&self, position: u64) {
+        self.set_position_ms(position * 1000);
+    }
+
+    pub fn set_position_ms(&self, position: u64) {
         self.imp().position.replace(position);
         self.notify("position");
     }
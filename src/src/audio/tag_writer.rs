@@ -0,0 +1,230 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{io::Cursor, path::Path};
+
+use lofty::{
+    config::WriteOptions,
+    picture::{Picture, PictureType},
+    prelude::{Accessor, ItemKey, TagExt, TaggedFileExt},
+    tag::{ItemValue, Tag, TagItem},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TagField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Composer,
+    Year,
+    TrackNumber,
+    TrackTotal,
+    DiscNumber,
+    DiscTotal,
+    Comment,
+}
+
+impl TagField {
+    pub const ALL: [TagField; 12] = [
+        TagField::Title,
+        TagField::Artist,
+        TagField::Album,
+        TagField::AlbumArtist,
+        TagField::Genre,
+        TagField::Composer,
+        TagField::Year,
+        TagField::TrackNumber,
+        TagField::TrackTotal,
+        TagField::DiscNumber,
+        TagField::DiscTotal,
+        TagField::Comment,
+    ];
+
+    // Numbers are stored as such by most tag formats, so we only accept
+    // numbers for them
+    pub fn is_number(self) -> bool {
+        matches!(
+            self,
+            TagField::Year
+                | TagField::TrackNumber
+                | TagField::TrackTotal
+                | TagField::DiscNumber
+                | TagField::DiscTotal
+        )
+    }
+
+    // Fields that are different for every song, which we do not offer to
+    // change for many songs at once
+    pub fn is_per_song(self) -> bool {
+        matches!(self, TagField::Title | TagField::TrackNumber)
+    }
+
+    fn item_key(self) -> Option<ItemKey> {
+        match self {
+            TagField::Title => Some(ItemKey::TrackTitle),
+            TagField::Artist => Some(ItemKey::TrackArtist),
+            TagField::Album => Some(ItemKey::AlbumTitle),
+            TagField::AlbumArtist => Some(ItemKey::AlbumArtist),
+            TagField::Genre => Some(ItemKey::Genre),
+            TagField::Composer => Some(ItemKey::Composer),
+            TagField::Comment => Some(ItemKey::Comment),
+            _ => None,
+        }
+    }
+
+    // How the text typed for the field maps to its values: artists are
+    // separated by semicolons, any other field has a single value
+    pub fn values_from_text(self, text: &str) -> Vec<String> {
+        let values: Vec<&str> = if self == TagField::Artist {
+            text.split(';').collect()
+        } else {
+            vec![text]
+        };
+
+        values
+            .into_iter()
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_string())
+            .collect()
+    }
+
+    // Every value of the field; only the artist can have more than one
+    fn values(self, tag: &Tag) -> Vec<String> {
+        let number = match self {
+            TagField::Year => tag.year(),
+            TagField::TrackNumber => tag.track(),
+            TagField::TrackTotal => tag.track_total(),
+            TagField::DiscNumber => tag.disk(),
+            TagField::DiscTotal => tag.disk_total(),
+            _ => {
+                return self
+                    .item_key()
+                    .map(|key| tag_strings(tag, &key))
+                    .unwrap_or_default()
+            }
+        };
+
+        number.map(|n| n.to_string()).into_iter().collect()
+    }
+
+    // No values remove the field from the tag
+    fn set_values(self, tag: &mut Tag, values: &[String]) {
+        if self.is_number() {
+            let number = values
+                .first()
+                .and_then(|v| v.trim().parse::<u32>().ok())
+                .filter(|n| *n > 0);
+            match (self, number) {
+                (TagField::Year, Some(n)) => tag.set_year(n),
+                (TagField::Year, None) => tag.remove_year(),
+                (TagField::TrackNumber, Some(n)) => tag.set_track(n),
+                (TagField::TrackNumber, None) => tag.remove_track(),
+                (TagField::TrackTotal, Some(n)) => tag.set_track_total(n),
+                (TagField::TrackTotal, None) => tag.remove_track_total(),
+                (TagField::DiscNumber, Some(n)) => tag.set_disk(n),
+                (TagField::DiscNumber, None) => tag.remove_disk(),
+                (TagField::DiscTotal, Some(n)) => tag.set_disk_total(n),
+                (TagField::DiscTotal, None) => tag.remove_disk_total(),
+                _ => (),
+            }
+        } else if let Some(key) = self.item_key() {
+            // The list of artists would disagree with the new value
+            if self == TagField::Artist {
+                tag.remove_key(&ItemKey::TrackArtists);
+            }
+            set_tag_strings(tag, key, values);
+        }
+    }
+}
+
+fn tag_strings(tag: &Tag, key: &ItemKey) -> Vec<String> {
+    tag.get_strings(key).map(|s| s.to_string()).collect()
+}
+
+// Each value goes in its own item, so that formats with multi-valued
+// fields keep them apart
+fn set_tag_strings(tag: &mut Tag, key: ItemKey, values: &[String]) {
+    tag.remove_key(&key);
+    for value in values.iter().map(|v| v.trim()).filter(|v| !v.is_empty()) {
+        tag.push(TagItem::new(
+            key.clone(),
+            ItemValue::Text(value.to_string()),
+        ));
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CoverChange {
+    // The encoded image, as read from a file
+    Replace(Vec<u8>),
+    Remove,
+}
+
+// The fields to write into a song; anything else is left as it is
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TagChanges {
+    pub fields: Vec<(TagField, Vec<String>)>,
+    pub cover: Option<CoverChange>,
+    // The separate list of artists some formats keep next to the artist;
+    // changing the artist drops it, so it only comes back on undo
+    pub artists: Option<Vec<String>>,
+}
+
+impl TagChanges {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.cover.is_none() && self.artists.is_none()
+    }
+}
+
+// Writes the changes into the primary tag of a local file, creating the tag
+// if needed; returns the changes that bring the file back to how it was
+pub fn write_tags(path: &Path, changes: &TagChanges) -> Result<TagChanges, String> {
+    let mut tagged_file = lofty::read_from_path(path).map_err(|e| e.to_string())?;
+
+    if tagged_file.primary_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+    let tag = tagged_file.primary_tag_mut().unwrap();
+
+    let mut undo = TagChanges::default();
+    for (field, values) in &changes.fields {
+        undo.fields.push((*field, field.values(tag)));
+        if *field == TagField::Artist {
+            undo.artists = Some(tag_strings(tag, &ItemKey::TrackArtists));
+        }
+        field.set_values(tag, values);
+    }
+
+    if let Some(artists) = &changes.artists {
+        set_tag_strings(tag, ItemKey::TrackArtists, artists);
+    }
+
+    if let Some(cover) = &changes.cover {
+        let old_cover = tag
+            .pictures()
+            .iter()
+            .find(|p| p.pic_type() == PictureType::CoverFront)
+            .map(|p| p.data().to_vec());
+        undo.cover = Some(match old_cover {
+            Some(data) => CoverChange::Replace(data),
+            None => CoverChange::Remove,
+        });
+
+        tag.remove_picture_type(PictureType::CoverFront);
+        if let CoverChange::Replace(data) = cover {
+            let mut picture =
+                Picture::from_reader(&mut Cursor::new(data)).map_err(|e| e.to_string())?;
+            picture.set_pic_type(PictureType::CoverFront);
+            tag.push_picture(picture);
+        }
+    }
+
+    tag.save_to_path(path, WriteOptions::default())
+        .map_err(|e| e.to_string())?;
+
+    Ok(undo)
+}
@@ -1,7 +1,7 @@
 // SPDX-FileCopyrightText: 2025 Ardox
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::cell::RefCell;
+use std::{cell::RefCell, path::PathBuf};
 
 use glib::clone;
 use gst::prelude::*;
@@ -56,6 +56,26 @@ mod imp {
     }
 }
 
+pub(super) fn waveform_cache_path(uuid: &str) -> PathBuf {
+    let mut cache = glib::user_cache_dir();
+    cache.push("axuralis");
+    cache.push("waveforms");
+    cache.push(format!("{}.json", uuid));
+    cache
+}
+
+// The peaks of a song are keyed by its UUID, which changes along with its
+// tags; once that happens, nothing will ever look at them again
+pub fn invalidate_cached_peaks(uuid: &str) {
+    let file = gio::File::for_path(waveform_cache_path(uuid));
+    if let Err(e) = file.delete(gio::Cancellable::NONE) {
+        debug!(
+            "Unable to remove cached waveform for UUID '{}': {}",
+            uuid, e
+        );
+    }
+}
+
 glib::wrapper! {
     pub struct WaveformGenerator(ObjectSubclass<imp::WaveformGenerator>);
 }
@@ -74,6 +94,11 @@ impl Controller for WaveformGenerator {
         self.load_peaks();
     }
 
+    // The peaks did not change, but the UUID we store them under did
+    fn refresh_song(&self, _song: &Song) {
+        self.save_peaks();
+    }
+
     fn set_position(&self, _position: u64, _notify: bool) {}
     fn set_repeat_mode(&self, _mode: RepeatMode) {}
 }
@@ -98,12 +123,10 @@ impl WaveformGenerator {
             };
 
             if let Some(uuid) = song.uuid() {
-                let mut cache = glib::user_cache_dir();
-                cache.push("axuralis");
-                cache.push("waveforms");
-                glib::mkdir_with_parents(&cache, 0o755);
-
-                cache.push(format!("{}.json", uuid));
+                let cache = waveform_cache_path(&uuid);
+                if let Some(parent) = cache.parent() {
+                    glib::mkdir_with_parents(parent, 0o755);
+                }
 
                 let j = serde_json::to_string(&peaks).unwrap();
                 let file = gio::File::for_path(&cache);
@@ -130,11 +153,7 @@ impl WaveformGenerator {
         };
 
         if let Some(uuid) = song.uuid() {
-            let mut cache = glib::user_cache_dir();
-            cache.push("axuralis");
-            cache.push("waveforms");
-            cache.push(format!("{}.json", uuid));
-
+            let cache = waveform_cache_path(&uuid);
             let file = gio::File::for_path(&cache);
             file.load_contents_async(
                 gio::Cancellable::NONE,
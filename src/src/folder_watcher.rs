@@ -0,0 +1,368 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    time::Duration,
+};
+
+use async_channel::Sender;
+use glib::clone;
+use gtk::{gio, glib, prelude::*};
+use log::{debug, warn};
+
+use crate::utils::{self, ImportOptions};
+
+// How long the music folders need to stay quiet before we look at what
+// changed; copying an album results in a burst of events, and each file
+// keeps being written for a while after it has been created
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(2);
+
+// What changed inside the music folders since the last batch
+#[derive(Debug, Default)]
+pub struct FolderChanges {
+    // The new audio files
+    pub added: Vec<gio::File>,
+    // The files and folders that went away
+    pub removed: Vec<gio::File>,
+    // The files and folders that were moved or renamed, and where to
+    pub moved: Vec<(gio::File, gio::File)>,
+}
+
+impl FolderChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.moved.is_empty()
+    }
+}
+
+// The raw events we collected while waiting for things to settle down
+#[derive(Debug, Default)]
+struct PendingChanges {
+    created: HashMap<String, gio::File>,
+    deleted: HashMap<String, gio::File>,
+    moved: Vec<(gio::File, gio::File)>,
+}
+
+impl PendingChanges {
+    fn create(&mut self, file: &gio::File) {
+        let uri = file.uri().to_string();
+        self.deleted.remove(&uri);
+        self.created.insert(uri, file.clone());
+    }
+
+    fn delete(&mut self, file: &gio::File) {
+        let uri = file.uri().to_string();
+        // Something that came and went before we looked at it never
+        // existed, as far as we are concerned
+        if self.created.remove(&uri).is_none() {
+            self.deleted.insert(uri, file.clone());
+        }
+    }
+
+    fn rename(&mut self, from: &gio::File, to: &gio::File) {
+        // Downloads and copies are often written to a temporary file,
+        // and renamed once they are complete
+        if self.created.remove(from.uri().as_str()).is_some() {
+            self.create(to);
+            return;
+        }
+
+        // Moving a file across two watched folders is reported by both
+        if !self.moved.iter().any(|(f, t)| f.equal(from) && t.equal(to)) {
+            self.moved.push((from.clone(), to.clone()));
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct WatcherState {
+    // One monitor for each folder, since GIO monitors are not recursive
+    monitors: RefCell<HashMap<String, (gio::File, gio::FileMonitor)>>,
+    pending: RefCell<PendingChanges>,
+    timeout: RefCell<Option<glib::SourceId>>,
+    cancellable: RefCell<Option<gio::Cancellable>>,
+}
+
+// Keeps an eye on the music folders, and sends the changes inside them
+// in batches, once the file system has been quiet for a little while
+#[derive(Debug)]
+pub struct FolderWatcher {
+    sender: Sender<FolderChanges>,
+    state: Rc<WatcherState>,
+}
+
+impl FolderWatcher {
+    pub fn new(sender: Sender<FolderChanges>) -> Self {
+        Self {
+            sender,
+            state: Rc::new(WatcherState::default()),
+        }
+    }
+
+    // Replaces the watched folders; walking through large music folders
+    // can take a while, so we do it on a worker thread
+    pub fn watch(&self, folders: Vec<gio::File>) {
+        self.stop();
+
+        let cancellable = gio::Cancellable::new();
+        self.state.cancellable.replace(Some(cancellable.clone()));
+
+        let options = ImportOptions::from_settings(true);
+        let state = self.state.clone();
+        let sender = self.sender.clone();
+        glib::spawn_future_local(async move {
+            let folders = gio::spawn_blocking(move || {
+                folders
+                    .iter()
+                    .flat_map(|f| collect_folders(f, &options))
+                    .collect::<Vec<gio::File>>()
+            })
+            .await
+            .unwrap_or_default();
+
+            if cancellable.is_cancelled() {
+                return;
+            }
+
+            debug!("Watching {} folders", folders.len());
+            for folder in folders {
+                watch_folder(&state, &sender, &folder);
+            }
+        });
+    }
+
+    pub fn stop(&self) {
+        if let Some(cancellable) = self.state.cancellable.take() {
+            cancellable.cancel();
+        }
+        if let Some(id) = self.state.timeout.take() {
+            id.remove();
+        }
+        for (_, (_, monitor)) in self.state.monitors.take() {
+            monitor.cancel();
+        }
+        self.state.pending.replace(PendingChanges::default());
+    }
+}
+
+impl Drop for FolderWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// The folder itself, and every folder below it that we would import from
+fn collect_folders(folder: &gio::File, options: &ImportOptions) -> Vec<gio::File> {
+    let mut res = vec![folder.clone()];
+
+    let flags = if options.follow_symlinks {
+        gio::FileQueryInfoFlags::NONE
+    } else {
+        gio::FileQueryInfoFlags::NOFOLLOW_SYMLINKS
+    };
+    let enumerator = match folder.enumerate_children(
+        "standard::name,standard::type,standard::is-hidden",
+        flags,
+        gio::Cancellable::NONE,
+    ) {
+        Ok(enumerator) => enumerator,
+        Err(e) => {
+            warn!("Unable to watch '{}': {}", folder.uri(), e);
+            return res;
+        }
+    };
+
+    for info in enumerator.flatten() {
+        if info.file_type() != gio::FileType::Directory || (options.skip_hidden && info.is_hidden())
+        {
+            continue;
+        }
+
+        let child = folder.child(info.name());
+        if !options.is_excluded(&child) {
+            res.append(&mut collect_folders(&child, options));
+        }
+    }
+
+    res
+}
+
+fn watch_folder(state: &Rc<WatcherState>, sender: &Sender<FolderChanges>, folder: &gio::File) {
+    let uri = folder.uri().to_string();
+    if state.monitors.borrow().contains_key(&uri) {
+        return;
+    }
+
+    let monitor = match folder
+        .monitor_directory(gio::FileMonitorFlags::WATCH_MOVES, gio::Cancellable::NONE)
+    {
+        Ok(monitor) => monitor,
+        Err(e) => {
+            warn!("Unable to watch '{}': {}", uri, e);
+            return;
+        }
+    };
+
+    monitor.connect_changed(clone!(
+        #[weak]
+        state,
+        #[strong]
+        sender,
+        move |_, file, other_file, event| {
+            {
+                let mut pending = state.pending.borrow_mut();
+                match (event, other_file) {
+                    (gio::FileMonitorEvent::Created, _)
+                    | (gio::FileMonitorEvent::MovedIn, None) => pending.create(file),
+                    (gio::FileMonitorEvent::Deleted, _)
+                    | (gio::FileMonitorEvent::MovedOut, None) => pending.delete(file),
+                    // For files moved into the folder, `file` is the
+                    // destination; otherwise, it's the source
+                    (gio::FileMonitorEvent::MovedIn, Some(other)) => pending.rename(other, file),
+                    (gio::FileMonitorEvent::Renamed, Some(other))
+                    | (gio::FileMonitorEvent::MovedOut, Some(other)) => pending.rename(file, other),
+                    _ => (),
+                }
+            }
+
+            // Every event, including the ones for files that are still
+            // being written, pushes the batch back
+            if let Some(id) = state.timeout.take() {
+                id.remove();
+            }
+            let id = glib::timeout_add_local_once(
+                WATCH_DEBOUNCE,
+                clone!(
+                    #[weak]
+                    state,
+                    #[strong]
+                    sender,
+                    move || {
+                        state.timeout.replace(None);
+                        flush(&state, &sender);
+                    }
+                ),
+            );
+            state.timeout.replace(Some(id));
+        }
+    ));
+
+    state
+        .monitors
+        .borrow_mut()
+        .insert(uri, (folder.clone(), monitor));
+}
+
+fn unwatch_folder(state: &WatcherState, folder: &gio::File) {
+    state.monitors.borrow_mut().retain(|_, (f, monitor)| {
+        if f.equal(folder) || f.has_prefix(folder) {
+            monitor.cancel();
+            false
+        } else {
+            true
+        }
+    });
+}
+
+fn flush(state: &Rc<WatcherState>, sender: &Sender<FolderChanges>) {
+    let pending = state.pending.take();
+    let options = ImportOptions::from_settings(true);
+
+    // Folders that went away, or moved, take their monitors with them
+    for file in pending.deleted.values() {
+        unwatch_folder(state, file);
+    }
+    for (from, _) in &pending.moved {
+        unwatch_folder(state, from);
+    }
+
+    let created: Vec<gio::File> = pending
+        .created
+        .into_values()
+        .chain(pending.moved.iter().map(|(_, to)| to.clone()))
+        .collect();
+    let changes = FolderChanges {
+        added: vec![],
+        removed: pending.deleted.into_values().collect(),
+        moved: pending.moved,
+    };
+    let moved_to: HashSet<String> = changes
+        .moved
+        .iter()
+        .map(|(_, to)| to.uri().to_string())
+        .collect();
+
+    let state = state.clone();
+    let sender = sender.clone();
+    glib::spawn_future_local(async move {
+        // Looking at new folders means walking through all of them, so
+        // we leave the main context alone
+        let res = gio::spawn_blocking(move || {
+            let mut added = Vec::new();
+            let mut folders = Vec::new();
+            for file in created {
+                if options.is_excluded(&file) {
+                    continue;
+                }
+
+                let info = match file.query_info(
+                    "standard::type,standard::is-hidden,standard::content-type",
+                    gio::FileQueryInfoFlags::NONE,
+                    gio::Cancellable::NONE,
+                ) {
+                    Ok(info) => info,
+                    // Gone already
+                    Err(_) => continue,
+                };
+                if options.skip_hidden && info.is_hidden() {
+                    continue;
+                }
+
+                let moved = moved_to.contains(file.uri().as_str());
+                match info.file_type() {
+                    gio::FileType::Directory => {
+                        folders.append(&mut collect_folders(&file, &options));
+                        // Moved folders keep their songs
+                        if !moved {
+                            added.append(&mut utils::load_files_from_folder(&file, &options).files);
+                        }
+                    }
+                    gio::FileType::Regular if !moved => {
+                        let is_audio = info
+                            .content_type()
+                            .is_some_and(|ct| utils::is_audio_content_type(&ct));
+                        if is_audio {
+                            added.push(file);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+
+            (added, folders)
+        })
+        .await;
+
+        let mut changes = changes;
+        if let Ok((added, folders)) = res {
+            for folder in folders {
+                watch_folder(&state, &sender, &folder);
+            }
+            changes.added = added;
+        }
+
+        if !changes.is_empty() {
+            debug!(
+                "Music folders changed: {} added, {} removed, {} moved",
+                changes.added.len(),
+                changes.removed.len(),
+                changes.moved.len()
+            );
+            if let Err(e) = sender.send(changes).await {
+                warn!("Unable to send folder changes: {e}");
+            }
+        }
+    });
+}
@@ -0,0 +1,589 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{cell::RefCell, time::Duration};
+
+use adw::{prelude::*, subclass::prelude::*};
+use glib::clone;
+use gtk::{gio, glib, CompositeTemplate};
+
+use crate::{
+    audio::{
+        load_smart_playlists, store_smart_playlists, BrowseKind, Library, LibraryGroup,
+        SmartPlaylist, Song,
+    },
+    cover_picture::{CoverPicture, CoverSize},
+    i18n::{i18n, i18n_f, ni18n_f},
+    smart_playlist_dialog::SmartPlaylistDialog,
+    utils,
+};
+
+// The library emits a change for every batch of songs while scanning, and
+// grouping the whole library is not free
+const UPDATE_DELAY: Duration = Duration::from_millis(500);
+
+mod imp {
+    use super::*;
+
+    #[derive(Debug, Default, CompositeTemplate)]
+    #[template(resource = "/com/axos-project/Axuralis/library-dialog.ui")]
+    pub struct LibraryDialog {
+        // Template widgets
+        #[template_child]
+        pub navigation_view: TemplateChild<adw::NavigationView>,
+        #[template_child]
+        pub scanning_spinner: TemplateChild<gtk::Spinner>,
+        #[template_child]
+        pub main_stack: TemplateChild<gtk::Stack>,
+        #[template_child]
+        pub albums_view: TemplateChild<gtk::GridView>,
+        #[template_child]
+        pub artists_view: TemplateChild<gtk::ListView>,
+        #[template_child]
+        pub genres_view: TemplateChild<gtk::ListView>,
+        #[template_child]
+        pub years_view: TemplateChild<gtk::ListView>,
+        #[template_child]
+        pub playlists_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub new_playlist_button: TemplateChild<gtk::Button>,
+
+        pub library: RefCell<Option<Library>>,
+        pub library_handlers: RefCell<Vec<glib::SignalHandlerId>>,
+        pub models: RefCell<Vec<(BrowseKind, gio::ListStore)>>,
+        pub update_id: RefCell<Option<glib::SourceId>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for LibraryDialog {
+        const NAME: &'static str = "AxuralisLibraryDialog";
+        type Type = super::LibraryDialog;
+        type ParentType = adw::Dialog;
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for LibraryDialog {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            self.obj().setup_views();
+        }
+
+        fn dispose(&self) {
+            if let Some(id) = self.update_id.take() {
+                id.remove();
+            }
+            if let Some(library) = self.library.take() {
+                for handler in self.library_handlers.take() {
+                    library.disconnect(handler);
+                }
+            }
+        }
+    }
+
+    impl WidgetImpl for LibraryDialog {}
+    impl AdwDialogImpl for LibraryDialog {}
+}
+
+glib::wrapper! {
+    pub struct LibraryDialog(ObjectSubclass<imp::LibraryDialog>)
+        @extends gtk::Widget, adw::Dialog,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget;
+}
+
+impl LibraryDialog {
+    pub fn new(library: &Library) -> Self {
+        let dialog: Self = glib::Object::new();
+        dialog.set_library(library);
+        dialog
+    }
+
+    fn setup_views(&self) {
+        let imp = self.imp();
+
+        let views: [(gtk::Widget, BrowseKind); 4] = [
+            (imp.albums_view.get().upcast(), BrowseKind::Albums),
+            (imp.artists_view.get().upcast(), BrowseKind::Artists),
+            (imp.genres_view.get().upcast(), BrowseKind::Genres),
+            (imp.years_view.get().upcast(), BrowseKind::Years),
+        ];
+        for (view, kind) in views {
+            let store = gio::ListStore::new::<LibraryGroup>();
+            let selection = gtk::NoSelection::new(Some(store.clone()));
+
+            if let Some(grid) = view.downcast_ref::<gtk::GridView>() {
+                grid.set_model(Some(&selection));
+                grid.set_factory(Some(&album_factory()));
+                grid.connect_activate(clone!(
+                    #[weak(rename_to = this)]
+                    self,
+                    #[weak]
+                    store,
+                    move |_, pos| {
+                        if let Some(group) = store.item(pos).and_downcast::<LibraryGroup>() {
+                            this.show_group(&group);
+                        }
+                    }
+                ));
+            } else if let Some(list) = view.downcast_ref::<gtk::ListView>() {
+                list.set_model(Some(&selection));
+                list.set_factory(Some(&group_factory()));
+                list.connect_activate(clone!(
+                    #[weak(rename_to = this)]
+                    self,
+                    #[weak]
+                    store,
+                    move |_, pos| {
+                        if let Some(group) = store.item(pos).and_downcast::<LibraryGroup>() {
+                            this.show_group(&group);
+                        }
+                    }
+                ));
+            }
+
+            imp.models.borrow_mut().push((kind, store));
+        }
+
+        let placeholder = gtk::Label::builder()
+            .label(i18n("No Smart Playlists"))
+            .margin_top(12)
+            .margin_bottom(12)
+            .css_classes(["dim-label"])
+            .build();
+        imp.playlists_list.set_placeholder(Some(&placeholder));
+        imp.new_playlist_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.edit_playlist(None);
+            }
+        ));
+    }
+
+    fn set_library(&self, library: &Library) {
+        let imp = self.imp();
+
+        let changed = library.connect_local(
+            "changed",
+            false,
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                #[upgrade_or]
+                None,
+                move |_| {
+                    this.queue_update();
+                    None
+                }
+            ),
+        );
+        let scanning = library.connect_notify_local(
+            Some("scanning"),
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_, _| {
+                    this.update_state();
+                }
+            ),
+        );
+
+        imp.library_handlers.replace(vec![changed, scanning]);
+        imp.library.replace(Some(library.clone()));
+
+        self.update();
+    }
+
+    fn queue_update(&self) {
+        if self.imp().update_id.borrow().is_some() {
+            return;
+        }
+
+        let id = glib::timeout_add_local_once(
+            UPDATE_DELAY,
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                move || {
+                    this.imp().update_id.replace(None);
+                    this.update();
+                }
+            ),
+        );
+        self.imp().update_id.replace(Some(id));
+    }
+
+    fn update(&self) {
+        let library = match self.imp().library.borrow().as_ref() {
+            Some(library) => library.clone(),
+            None => return,
+        };
+
+        for (kind, store) in self.imp().models.borrow().iter() {
+            let groups = library.groups(*kind);
+            store.splice(0, store.n_items(), &groups);
+        }
+
+        self.update_playlists();
+        self.update_state();
+    }
+
+    // Smart playlists are evaluated again on every update, since the
+    // songs they pick depend on the library
+    fn update_playlists(&self) {
+        let library = match self.imp().library.borrow().as_ref() {
+            Some(library) => library.clone(),
+            None => return,
+        };
+
+        let list = &self.imp().playlists_list;
+        list.remove_all();
+        for (pos, playlist) in load_smart_playlists().into_iter().enumerate() {
+            let songs = library.smart_playlist_songs(&playlist);
+            list.append(&self.playlist_row(pos, &playlist, &songs));
+        }
+    }
+
+    fn playlist_row(&self, pos: usize, playlist: &SmartPlaylist, songs: &[Song]) -> adw::ActionRow {
+        let n_songs = songs.len();
+        let row = adw::ActionRow::builder()
+            .title(glib::markup_escape_text(&playlist.name))
+            .subtitle(format!(
+                "{} — {}",
+                ni18n_f(
+                    // Translators: the `{}` must be left unmodified;
+                    // it will be expanded to the number of songs
+                    "{} song",
+                    "{} songs",
+                    n_songs as u32,
+                    &[&n_songs.to_string()],
+                ),
+                playlist.description()
+            ))
+            .build();
+
+        let uris: Vec<String> = songs.iter().map(|s| s.uri()).collect();
+        let play_button = gtk::Button::builder()
+            .icon_name("media-playback-start-symbolic")
+            .tooltip_text(i18n("Play"))
+            .valign(gtk::Align::Center)
+            .sensitive(!uris.is_empty())
+            .css_classes(["flat"])
+            .build();
+        play_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            #[strong]
+            uris,
+            move |_| {
+                this.activate_library_action("library.play", &uris);
+                this.close();
+            }
+        ));
+        row.add_suffix(&play_button);
+
+        let enqueue_button = gtk::Button::builder()
+            .icon_name("list-add-symbolic")
+            .tooltip_text(i18n("Add to Playlist"))
+            .valign(gtk::Align::Center)
+            .sensitive(!uris.is_empty())
+            .css_classes(["flat"])
+            .build();
+        enqueue_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.activate_library_action("library.enqueue", &uris);
+            }
+        ));
+        row.add_suffix(&enqueue_button);
+
+        let edit_button = gtk::Button::builder()
+            .icon_name("document-edit-symbolic")
+            .tooltip_text(i18n("Edit Rules"))
+            .valign(gtk::Align::Center)
+            .css_classes(["flat"])
+            .build();
+        edit_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.edit_playlist(Some(pos));
+            }
+        ));
+        row.add_suffix(&edit_button);
+
+        let remove_button = gtk::Button::builder()
+            .icon_name("user-trash-symbolic")
+            // Translators: the `{}` must be left unmodified;
+            // it will be expanded to the name of a playlist
+            .tooltip_text(i18n_f("Remove “{}”", &[&playlist.name]))
+            .valign(gtk::Align::Center)
+            .css_classes(["flat"])
+            .build();
+        remove_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                let mut playlists = load_smart_playlists();
+                if pos < playlists.len() {
+                    playlists.remove(pos);
+                    store_smart_playlists(&playlists);
+                }
+                this.update_playlists();
+            }
+        ));
+        row.add_suffix(&remove_button);
+
+        row
+    }
+
+    fn edit_playlist(&self, pos: Option<usize>) {
+        let library = match self.imp().library.borrow().as_ref() {
+            Some(library) => library.clone(),
+            None => return,
+        };
+
+        let dialog = SmartPlaylistDialog::new(&library, pos);
+        dialog.connect_local(
+            "saved",
+            false,
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                #[upgrade_or]
+                None,
+                move |_| {
+                    this.update_playlists();
+                    None
+                }
+            ),
+        );
+        dialog.present(Some(self));
+    }
+
+    fn update_state(&self) {
+        let imp = self.imp();
+        let (n_songs, scanning) = match imp.library.borrow().as_ref() {
+            Some(library) => (library.n_songs(), library.is_scanning()),
+            None => (0, false),
+        };
+
+        imp.scanning_spinner.set_visible(scanning);
+        imp.scanning_spinner.set_spinning(scanning);
+
+        if n_songs > 0 || scanning {
+            imp.main_stack.set_visible_child_name("browse");
+        } else {
+            imp.main_stack.set_visible_child_name("empty");
+        }
+    }
+
+    fn show_group(&self, group: &LibraryGroup) {
+        let songs = group.songs();
+        let uris: Vec<String> = songs.iter().map(|s| s.uri()).collect();
+
+        let play_button = gtk::Button::builder()
+            .icon_name("media-playback-start-symbolic")
+            .tooltip_text(i18n("Play"))
+            .build();
+        play_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            #[strong]
+            uris,
+            move |_| {
+                this.activate_library_action("library.play", &uris);
+                this.close();
+            }
+        ));
+
+        let enqueue_button = gtk::Button::builder()
+            .icon_name("list-add-symbolic")
+            .tooltip_text(i18n("Add to Playlist"))
+            .build();
+        enqueue_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            #[strong]
+            uris,
+            move |_| {
+                this.activate_library_action("library.enqueue", &uris);
+            }
+        ));
+
+        let header_bar = adw::HeaderBar::new();
+        header_bar.pack_start(&play_button);
+        header_bar.pack_end(&enqueue_button);
+
+        let list = gtk::ListBox::builder()
+            .selection_mode(gtk::SelectionMode::None)
+            .valign(gtk::Align::Start)
+            .css_classes(["boxed-list"])
+            .build();
+        for song in &songs {
+            list.append(&self.song_row(song));
+        }
+
+        let clamp = adw::Clamp::builder()
+            .margin_top(12)
+            .margin_bottom(12)
+            .margin_start(12)
+            .margin_end(12)
+            .child(&list)
+            .build();
+        let scrolled = gtk::ScrolledWindow::builder()
+            .hscrollbar_policy(gtk::PolicyType::Never)
+            .vexpand(true)
+            .child(&clamp)
+            .build();
+
+        let toolbar_view = adw::ToolbarView::new();
+        toolbar_view.add_top_bar(&header_bar);
+        toolbar_view.set_content(Some(&scrolled));
+
+        let page = adw::NavigationPage::builder()
+            .title(group.title())
+            .child(&toolbar_view)
+            .build();
+        self.imp().navigation_view.push(&page);
+    }
+
+    fn song_row(&self, song: &Song) -> adw::ActionRow {
+        let row = adw::ActionRow::builder()
+            .title(glib::markup_escape_text(&song.title()))
+            .subtitle(glib::markup_escape_text(&format!(
+                "{} — {}",
+                song.artist(),
+                song.album()
+            )))
+            .build();
+
+        let duration = gtk::Label::builder()
+            .label(utils::format_time(song.duration() as i64))
+            .css_classes(["dim-label", "numeric"])
+            .build();
+        row.add_suffix(&duration);
+
+        let button = gtk::Button::builder()
+            .icon_name("list-add-symbolic")
+            .tooltip_text(i18n("Add to Playlist"))
+            .valign(gtk::Align::Center)
+            .css_classes(["flat"])
+            .build();
+        let uri = song.uri();
+        button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.activate_library_action("library.enqueue", &[uri.clone()]);
+            }
+        ));
+        row.add_suffix(&button);
+
+        row
+    }
+
+    // The window owns the queue, so we ask it to do the loading
+    fn activate_library_action(&self, action: &str, uris: &[String]) {
+        if let Err(e) = self.activate_action(action, Some(&uris.to_variant())) {
+            log::warn!("Unable to activate {action}: {e}");
+        }
+    }
+}
+
+fn album_factory() -> gtk::SignalListItemFactory {
+    let factory = gtk::SignalListItemFactory::new();
+    factory.connect_setup(|_, item| {
+        let list_item = item.downcast_ref::<gtk::ListItem>().unwrap();
+
+        let cover = CoverPicture::new();
+        cover.set_cover_size(CoverSize::Large);
+        cover.set_halign(gtk::Align::Center);
+        let title = gtk::Label::builder()
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .css_classes(["heading"])
+            .build();
+        let subtitle = gtk::Label::builder()
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .css_classes(["dim-label", "caption"])
+            .build();
+
+        let child = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .spacing(6)
+            .margin_top(6)
+            .margin_bottom(6)
+            .margin_start(6)
+            .margin_end(6)
+            .build();
+        child.append(&cover);
+        child.append(&title);
+        child.append(&subtitle);
+        list_item.set_child(Some(&child));
+
+        let item = list_item.property_expression("item");
+        item.chain_property::<LibraryGroup>("cover")
+            .bind(&cover, "cover", gtk::Widget::NONE);
+        item.chain_property::<LibraryGroup>("title")
+            .bind(&title, "label", gtk::Widget::NONE);
+        item.chain_property::<LibraryGroup>("subtitle")
+            .bind(&subtitle, "label", gtk::Widget::NONE);
+    });
+
+    factory
+}
+
+fn group_factory() -> gtk::SignalListItemFactory {
+    let factory = gtk::SignalListItemFactory::new();
+    factory.connect_setup(|_, item| {
+        let list_item = item.downcast_ref::<gtk::ListItem>().unwrap();
+
+        let cover = CoverPicture::new();
+        cover.set_cover_size(CoverSize::Small);
+        let title = gtk::Label::builder()
+            .xalign(0.0)
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .build();
+        let subtitle = gtk::Label::builder()
+            .xalign(0.0)
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .css_classes(["dim-label", "caption"])
+            .build();
+
+        let labels = gtk::Box::builder()
+            .orientation(gtk::Orientation::Vertical)
+            .valign(gtk::Align::Center)
+            .hexpand(true)
+            .build();
+        labels.append(&title);
+        labels.append(&subtitle);
+
+        let child = gtk::Box::builder()
+            .spacing(12)
+            .margin_top(6)
+            .margin_bottom(6)
+            .build();
+        child.append(&cover);
+        child.append(&labels);
+        child.append(&gtk::Image::from_icon_name("go-next-symbolic"));
+        list_item.set_child(Some(&child));
+
+        let item = list_item.property_expression("item");
+        item.chain_property::<LibraryGroup>("cover")
+            .bind(&cover, "cover", gtk::Widget::NONE);
+        item.chain_property::<LibraryGroup>("title")
+            .bind(&title, "label", gtk::Widget::NONE);
+        item.chain_property::<LibraryGroup>("subtitle")
+            .bind(&subtitle, "label", gtk::Widget::NONE);
+    });
+
+    factory
+}
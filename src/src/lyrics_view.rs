@@ -0,0 +1,172 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::cell::{Cell, RefCell};
+
+use adw::subclass::prelude::*;
+use glib::{clone, subclass::Signal};
+use gtk::{glib, prelude::*, CompositeTemplate};
+use once_cell::sync::Lazy;
+
+use crate::audio::Lyrics;
+
+mod imp {
+    use super::*;
+
+    #[derive(Debug, Default, CompositeTemplate)]
+    #[template(resource = "/com/axos-project/Axuralis/lyrics-view.ui")]
+    pub struct LyricsView {
+        // Template widgets
+        #[template_child]
+        pub stack: TemplateChild<gtk::Stack>,
+        #[template_child]
+        pub plain_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub lines_window: TemplateChild<gtk::ScrolledWindow>,
+        #[template_child]
+        pub lines_list: TemplateChild<gtk::ListBox>,
+
+        pub lyrics: RefCell<Option<Lyrics>>,
+        pub current_line: Cell<Option<usize>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for LyricsView {
+        const NAME: &'static str = "AxuralisLyricsView";
+        type Type = super::LyricsView;
+        type ParentType = gtk::Widget;
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+
+            klass.set_layout_manager_type::<gtk::BinLayout>();
+            klass.set_css_name("lyricsview");
+            klass.set_accessible_role(gtk::AccessibleRole::Group);
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for LyricsView {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            self.obj().init_widgets();
+        }
+
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+                // The position to seek to, in milliseconds
+                vec![Signal::builder("seek")
+                    .param_types([u64::static_type()])
+                    .build()]
+            });
+
+            SIGNALS.as_ref()
+        }
+
+        fn dispose(&self) {
+            while let Some(child) = self.obj().first_child() {
+                child.unparent();
+            }
+        }
+    }
+
+    impl WidgetImpl for LyricsView {}
+}
+
+glib::wrapper! {
+    pub struct LyricsView(ObjectSubclass<imp::LyricsView>)
+        @extends gtk::Widget;
+}
+
+impl Default for LyricsView {
+    fn default() -> Self {
+        glib::Object::new()
+    }
+}
+
+impl LyricsView {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn init_widgets(&self) {
+        self.imp().lines_list.connect_row_activated(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_, row| {
+                let time = match this.imp().lyrics.borrow().as_ref() {
+                    Some(Lyrics::Synced(lines)) => lines.get(row.index() as usize).map(|l| l.time),
+                    _ => None,
+                };
+                if let Some(time) = time {
+                    this.emit_by_name::<()>("seek", &[&time]);
+                }
+            }
+        ));
+    }
+
+    // While the lyrics are being looked for
+    pub fn set_loading(&self) {
+        self.imp().stack.set_visible_child_name("loading");
+    }
+
+    pub fn set_lyrics(&self, lyrics: Option<Lyrics>) {
+        let imp = self.imp();
+
+        imp.current_line.set(None);
+        imp.lines_list.remove_all();
+        match lyrics {
+            Some(Lyrics::Plain(ref text)) => {
+                imp.plain_label.set_label(text);
+                imp.stack.set_visible_child_name("plain");
+            }
+            Some(Lyrics::Synced(ref lines)) => {
+                for line in lines {
+                    let label = gtk::Label::builder()
+                        .label(&line.text)
+                        .wrap(true)
+                        .justify(gtk::Justification::Center)
+                        .build();
+                    imp.lines_list.append(&label);
+                }
+                imp.lines_window.vadjustment().set_value(0.0);
+                imp.stack.set_visible_child_name("synced");
+            }
+            None => imp.stack.set_visible_child_name("empty"),
+        }
+        imp.lyrics.replace(lyrics);
+    }
+
+    // Highlights the line being sung at `position`, in milliseconds, and
+    // scrolls it to the middle of the view
+    pub fn set_position(&self, position: u64) {
+        let imp = self.imp();
+
+        let line = match imp.lyrics.borrow().as_ref() {
+            Some(lyrics) => lyrics.line_at(position),
+            None => return,
+        };
+        let previous = imp.current_line.replace(line);
+        if previous == line {
+            return;
+        }
+
+        if let Some(row) = previous.and_then(|i| imp.lines_list.row_at_index(i as i32)) {
+            row.remove_css_class("current");
+        }
+        if let Some(row) = line.and_then(|i| imp.lines_list.row_at_index(i as i32)) {
+            row.add_css_class("current");
+
+            if let Some(point) = row.compute_point(&*imp.lines_list, &gtk::graphene::Point::zero())
+            {
+                let adjustment = imp.lines_window.vadjustment();
+                let center = point.y() as f64 + row.height() as f64 / 2.0;
+                adjustment.set_value(center - adjustment.page_size() / 2.0);
+            }
+        }
+    }
+}
@@ -1,20 +1,32 @@
 // SPDX-FileCopyrightText: 2025 Ardox
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+mod album_header;
+mod album_sections;
 mod application;
 mod audio;
 mod config;
 mod cover_picture;
 mod drag_overlay;
+mod folder_watcher;
 mod i18n;
+mod library_dialog;
+mod lyrics_view;
 mod marquee;
 mod playback_control;
 mod playlist_view;
+mod preferences_dialog;
+mod query;
 mod queue_row;
+mod rating_bar;
 mod search;
+mod session;
+mod smart_playlist_dialog;
 mod song_cover;
 mod song_details;
+mod song_properties_dialog;
 mod sort;
+mod tag_editor_dialog;
 mod utils;
 mod volume_control;
 mod waveform_view;
@@ -1,10 +1,17 @@
 // SPDX-FileCopyrightText: 2025 Ardox
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use std::cell::RefCell;
+
 use adw::subclass::prelude::*;
-use gtk::{gio, glib, prelude::*, CompositeTemplate};
+use glib::clone;
+use gtk::{gdk, gio, glib, prelude::*, CompositeTemplate};
 
-use crate::{audio::RepeatMode, i18n::i18n, volume_control::VolumeControl};
+use crate::{
+    audio::{RepeatMode, ShuffleMode},
+    i18n::i18n,
+    volume_control::VolumeControl,
+};
 
 mod imp {
     use super::*;
@@ -38,6 +45,10 @@ mod imp {
         pub repeat_button: TemplateChild<gtk::Button>,
         #[template_child]
         pub menu_button: TemplateChild<gtk::MenuButton>,
+        #[template_child]
+        pub shuffle_menu: TemplateChild<gio::MenuModel>,
+
+        pub shuffle_popover: RefCell<Option<gtk::PopoverMenu>>,
     }
 
     #[glib::object_subclass]
@@ -62,6 +73,10 @@ mod imp {
 
     impl ObjectImpl for PlaybackControl {
         fn dispose(&self) {
+            if let Some(popover) = self.shuffle_popover.take() {
+                popover.unparent();
+            }
+
             while let Some(child) = self.obj().first_child() {
                 child.unparent();
             }
@@ -71,6 +86,7 @@ mod imp {
             self.parent_constructed();
 
             self.menu_button.set_primary(true);
+            self.obj().setup_shuffle_menu();
         }
     }
 
@@ -106,6 +122,52 @@ impl PlaybackControl {
         self.imp().volume_control.get()
     }
 
+    // The shuffle mode is selected with a secondary click, or a long
+    // press, on the shuffle button
+    fn setup_shuffle_menu(&self) {
+        let imp = self.imp();
+        let shuffle_button = imp.shuffle_button.get();
+
+        let popover = gtk::PopoverMenu::from_model(Some(&imp.shuffle_menu.get()));
+        popover.set_parent(&shuffle_button);
+        imp.shuffle_popover.replace(Some(popover.clone()));
+
+        let click = gtk::GestureClick::builder()
+            .button(gdk::BUTTON_SECONDARY)
+            .build();
+        click.connect_pressed(clone!(
+            #[weak]
+            popover,
+            move |gesture, _, _, _| {
+                gesture.set_state(gtk::EventSequenceState::Claimed);
+                popover.popup();
+            }
+        ));
+        shuffle_button.add_controller(click);
+
+        let long_press = gtk::GestureLongPress::new();
+        long_press.connect_pressed(clone!(
+            #[weak]
+            popover,
+            move |gesture, _, _| {
+                gesture.set_state(gtk::EventSequenceState::Claimed);
+                popover.popup();
+            }
+        ));
+        shuffle_button.add_controller(long_press);
+    }
+
+    pub fn set_shuffle_mode(&self, shuffle_mode: ShuffleMode) {
+        let shuffle_button = self.imp().shuffle_button.get();
+        let tooltip = match shuffle_mode {
+            ShuffleMode::Songs => i18n("Shuffle the Playlist"),
+            ShuffleMode::Albums => i18n("Shuffle Albums"),
+            ShuffleMode::Spread => i18n("Shuffle and Spread Artists"),
+            ShuffleMode::Rated => i18n("Shuffle, Favoring Rated Songs"),
+        };
+        shuffle_button.set_tooltip_text(Some(&tooltip));
+    }
+
     pub fn set_repeat_mode(&self, repeat_mode: RepeatMode) {
         let repeat_button = self.imp().repeat_button.get();
         match repeat_mode {
@@ -117,6 +179,10 @@ impl PlaybackControl {
                 repeat_button.set_icon_name("media-playlist-repeat-symbolic");
                 repeat_button.set_tooltip_text(Some(&i18n("Repeat All Songs")));
             }
+            RepeatMode::RepeatAlbum => {
+                repeat_button.set_icon_name("media-playlist-repeat-album-symbolic");
+                repeat_button.set_tooltip_text(Some(&i18n("Repeat the Current Album")));
+            }
             RepeatMode::RepeatOne => {
                 repeat_button.set_icon_name("media-playlist-repeat-song-symbolic");
                 repeat_button.set_tooltip_text(Some(&i18n("Repeat the Current Song")));
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use adw::subclass::prelude::*;
+use glib::clone;
 use gtk::{gio, glib, prelude::*, CompositeTemplate};
 
 mod imp {
@@ -13,8 +14,16 @@ mod imp {
         #[template_child]
         pub back_button: TemplateChild<gtk::Button>,
         #[template_child]
+        pub queue_stack: TemplateChild<gtk::Stack>,
+        #[template_child]
         pub queue_view: TemplateChild<gtk::ListView>,
         #[template_child]
+        pub clear_search_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub history_expander: TemplateChild<gtk::Expander>,
+        #[template_child]
+        pub history_view: TemplateChild<gtk::ListBox>,
+        #[template_child]
         pub queue_length_label: TemplateChild<gtk::Label>,
         #[template_child]
         pub queue_actionbar: TemplateChild<gtk::ActionBar>,
@@ -25,8 +34,12 @@ mod imp {
         #[template_child]
         pub queue_selected_label: TemplateChild<gtk::Label>,
         #[template_child]
+        pub loading_box: TemplateChild<gtk::Box>,
+        #[template_child]
         pub playlist_progress: TemplateChild<gtk::ProgressBar>,
         #[template_child]
+        pub loading_cancel_button: TemplateChild<gtk::Button>,
+        #[template_child]
         pub playlist_searchbar: TemplateChild<gtk::SearchBar>,
         #[template_child]
         pub playlist_searchentry: TemplateChild<gtk::SearchEntry>,
@@ -88,6 +101,15 @@ impl PlaylistView {
     fn setup_searchbar(&self) {
         let entry = self.imp().playlist_searchentry.get();
         self.imp().playlist_searchbar.connect_entry(&entry);
+
+        self.imp().clear_search_button.connect_clicked(clone!(
+            #[weak]
+            entry,
+            move |_| {
+                entry.set_text("");
+                entry.grab_focus();
+            }
+        ));
     }
 
     pub fn back_button(&self) -> gtk::Button {
@@ -114,6 +136,14 @@ impl PlaylistView {
         self.imp().queue_view.get()
     }
 
+    pub fn history_expander(&self) -> gtk::Expander {
+        self.imp().history_expander.get()
+    }
+
+    pub fn history_view(&self) -> gtk::ListBox {
+        self.imp().history_view.get()
+    }
+
     pub fn queue_length_label(&self) -> gtk::Label {
         self.imp().queue_length_label.get()
     }
@@ -126,21 +156,36 @@ impl PlaylistView {
         self.imp().playlist_searchentry.get()
     }
 
+    pub fn loading_cancel_button(&self) -> gtk::Button {
+        self.imp().loading_cancel_button.get()
+    }
+
     pub fn begin_loading(&self) {
         self.imp().playlist_progress.set_fraction(0.0);
-        self.imp().playlist_progress.set_visible(true);
+        self.imp().loading_box.set_visible(true);
     }
 
     pub fn end_loading(&self) {
-        self.imp().playlist_progress.set_visible(false);
+        self.imp().loading_box.set_visible(false);
     }
 
     pub fn update_loading(&self, cur: u32, max: u32) {
-        let step = cur as f64 / max as f64;
+        let step = if max > 0 {
+            cur as f64 / max as f64
+        } else {
+            0.0
+        };
         self.imp().playlist_progress.set_fraction(step);
     }
 
     pub fn set_search(&self, search: bool) {
         self.imp().playlist_searchbar.set_search_mode(search);
     }
+
+    // Replaces the queue with a status page, when the search filtered
+    // out every song
+    pub fn set_no_results(&self, no_results: bool) {
+        let page = if no_results { "no-results" } else { "queue" };
+        self.imp().queue_stack.set_visible_child_name(page);
+    }
 }
@@ -0,0 +1,517 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use adw::{prelude::*, subclass::prelude::*};
+use glib::clone;
+use gtk::{gio, glib, CompositeTemplate};
+
+use crate::{
+    audio::{RatingTag, SkipKind, SkipList},
+    i18n::i18n,
+    utils,
+};
+
+mod imp {
+    use super::*;
+
+    #[derive(Debug, CompositeTemplate)]
+    #[template(resource = "/com/axos-project/Axuralis/preferences-dialog.ui")]
+    pub struct PreferencesDialog {
+        // Template widgets
+        #[template_child]
+        pub add_music_folder_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub music_folders_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub watch_add_to_queue_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub skip_hidden_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub follow_symlinks_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub excludes_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub exclude_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub separators_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub separator_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub lyrics_fetch_row: TemplateChild<adw::SwitchRow>,
+        #[template_child]
+        pub lyrics_server_row: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub rating_tag_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub songs_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub artists_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub artist_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub albums_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub album_entry: TemplateChild<adw::EntryRow>,
+
+        pub settings: gio::Settings,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for PreferencesDialog {
+        const NAME: &'static str = "AxuralisPreferencesDialog";
+        type Type = super::PreferencesDialog;
+        type ParentType = adw::PreferencesDialog;
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+
+        fn new() -> Self {
+            Self {
+                add_music_folder_button: TemplateChild::default(),
+                music_folders_list: TemplateChild::default(),
+                watch_add_to_queue_row: TemplateChild::default(),
+                skip_hidden_row: TemplateChild::default(),
+                follow_symlinks_row: TemplateChild::default(),
+                excludes_list: TemplateChild::default(),
+                exclude_entry: TemplateChild::default(),
+                separators_list: TemplateChild::default(),
+                separator_entry: TemplateChild::default(),
+                lyrics_fetch_row: TemplateChild::default(),
+                lyrics_server_row: TemplateChild::default(),
+                rating_tag_row: TemplateChild::default(),
+                songs_list: TemplateChild::default(),
+                artists_list: TemplateChild::default(),
+                artist_entry: TemplateChild::default(),
+                albums_list: TemplateChild::default(),
+                album_entry: TemplateChild::default(),
+                settings: utils::settings_manager(),
+            }
+        }
+    }
+
+    impl ObjectImpl for PreferencesDialog {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            self.obj().init_widgets();
+        }
+    }
+
+    impl WidgetImpl for PreferencesDialog {}
+    impl AdwDialogImpl for PreferencesDialog {}
+    impl PreferencesDialogImpl for PreferencesDialog {}
+}
+
+glib::wrapper! {
+    pub struct PreferencesDialog(ObjectSubclass<imp::PreferencesDialog>)
+        @extends gtk::Widget, adw::Dialog, adw::PreferencesDialog,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget;
+}
+
+impl Default for PreferencesDialog {
+    fn default() -> Self {
+        glib::Object::new()
+    }
+}
+
+impl PreferencesDialog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn init_widgets(&self) {
+        let imp = self.imp();
+
+        let placeholder = gtk::Label::builder()
+            .label(i18n("No Music Folders"))
+            .margin_top(12)
+            .margin_bottom(12)
+            .css_classes(["dim-label"])
+            .build();
+        imp.music_folders_list.set_placeholder(Some(&placeholder));
+        imp.add_music_folder_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                glib::spawn_future_local(clone!(
+                    #[weak]
+                    this,
+                    async move {
+                        this.add_music_folder().await;
+                    }
+                ));
+            }
+        ));
+        imp.settings.connect_changed(
+            Some("music-folders"),
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_, _| {
+                    this.update_music_folders();
+                }
+            ),
+        );
+        self.update_music_folders();
+
+        imp.settings
+            .bind("watch-add-to-queue", &*imp.watch_add_to_queue_row, "active")
+            .build();
+        imp.settings
+            .bind("import-skip-hidden", &*imp.skip_hidden_row, "active")
+            .build();
+        imp.settings
+            .bind(
+                "import-follow-symlinks",
+                &*imp.follow_symlinks_row,
+                "active",
+            )
+            .build();
+
+        imp.exclude_entry.connect_apply(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |entry| {
+                let text = entry.text();
+                let pattern = text.trim();
+                let mut patterns = this.exclude_patterns();
+                if !pattern.is_empty() && !patterns.iter().any(|p| p == pattern) {
+                    patterns.push(pattern.to_string());
+                    this.store_exclude_patterns(&patterns);
+                }
+                entry.set_text("");
+            }
+        ));
+        imp.settings.connect_changed(
+            Some("import-exclude-patterns"),
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_, _| {
+                    this.update_excludes();
+                }
+            ),
+        );
+        self.update_excludes();
+
+        imp.separator_entry.connect_apply(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |entry| {
+                // Spaces are part of the separator: " & " should not
+                // split "Tom&Jerry"
+                let separator = entry.text().to_string();
+                let mut separators = this.artist_separators();
+                if !separator.trim().is_empty() && !separators.contains(&separator) {
+                    separators.push(separator);
+                    this.store_artist_separators(&separators);
+                }
+                entry.set_text("");
+            }
+        ));
+        imp.settings.connect_changed(
+            Some("artist-separators"),
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_, _| {
+                    this.update_separators();
+                }
+            ),
+        );
+        self.update_separators();
+
+        imp.settings
+            .bind("lyrics-fetch", &*imp.lyrics_fetch_row, "active")
+            .build();
+        imp.settings
+            .bind("lyrics-fetch", &*imp.lyrics_server_row, "sensitive")
+            .get()
+            .build();
+        imp.lyrics_server_row
+            .set_text(&imp.settings.string("lyrics-server"));
+        imp.lyrics_server_row.connect_apply(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |row| {
+                this.imp()
+                    .settings
+                    .set_string("lyrics-server", row.text().trim())
+                    .expect("Unable to store setting");
+            }
+        ));
+
+        // The rows follow the order of the RatingTag enum
+        imp.rating_tag_row
+            .set_selected(imp.settings.enum_("rating-tag") as u32);
+        imp.rating_tag_row.connect_selected_notify(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |row| {
+                let format = RatingTag::from(row.selected() as i32);
+                this.imp()
+                    .settings
+                    .set_enum("rating-tag", format.into())
+                    .expect("Unable to store setting");
+            }
+        ));
+
+        let placeholder = gtk::Label::builder()
+            .label(i18n("No Skipped Songs"))
+            .margin_top(12)
+            .margin_bottom(12)
+            .css_classes(["dim-label"])
+            .build();
+        imp.songs_list.set_placeholder(Some(&placeholder));
+
+        for (entry, kind) in [
+            (imp.artist_entry.get(), SkipKind::Artist),
+            (imp.album_entry.get(), SkipKind::Album),
+        ] {
+            entry.connect_apply(move |entry| {
+                let text = entry.text();
+                let value = text.trim();
+                if !value.is_empty() {
+                    SkipList::add(kind, &[value.to_string()]);
+                }
+                entry.set_text("");
+            });
+        }
+
+        // The lists follow the settings, so they stay up to date when
+        // songs are marked from the playlist while the dialog is open
+        for kind in [SkipKind::Song, SkipKind::Artist, SkipKind::Album] {
+            imp.settings.connect_changed(
+                Some(kind.settings_key()),
+                clone!(
+                    #[weak(rename_to = this)]
+                    self,
+                    move |_, _| {
+                        this.update_list(kind);
+                    }
+                ),
+            );
+            self.update_list(kind);
+        }
+    }
+
+    fn update_list(&self, kind: SkipKind) {
+        let imp = self.imp();
+        let (list, entry) = match kind {
+            SkipKind::Song => (imp.songs_list.get(), None),
+            SkipKind::Artist => (imp.artists_list.get(), Some(imp.artist_entry.get())),
+            SkipKind::Album => (imp.albums_list.get(), Some(imp.album_entry.get())),
+        };
+
+        list.remove_all();
+        for value in SkipList::entries(kind) {
+            let row = adw::ActionRow::new();
+            if kind == SkipKind::Song {
+                // Songs are stored by URI, which is not very readable
+                let file = gio::File::for_uri(&value);
+                let title = file
+                    .basename()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_else(|| value.clone());
+                let subtitle = file
+                    .parent()
+                    .map(|p| p.parse_name().to_string())
+                    .unwrap_or_else(|| value.clone());
+                row.set_title(&glib::markup_escape_text(&title));
+                row.set_subtitle(&glib::markup_escape_text(&subtitle));
+            } else {
+                row.set_title(&glib::markup_escape_text(&value));
+            }
+
+            let button = gtk::Button::builder()
+                .icon_name("user-trash-symbolic")
+                .tooltip_text(i18n("Remove"))
+                .valign(gtk::Align::Center)
+                .css_classes(["flat"])
+                .build();
+            button.connect_clicked(move |_| {
+                SkipList::remove(kind, &value);
+            });
+            row.add_suffix(&button);
+
+            list.append(&row);
+        }
+
+        // The entry rows for adding new values always come last
+        if let Some(entry) = entry {
+            list.append(&entry);
+        }
+    }
+
+    fn exclude_patterns(&self) -> Vec<String> {
+        self.imp()
+            .settings
+            .strv("import-exclude-patterns")
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    fn store_exclude_patterns(&self, patterns: &[String]) {
+        self.imp()
+            .settings
+            .set_strv("import-exclude-patterns", patterns)
+            .expect("Unable to store setting");
+    }
+
+    fn update_excludes(&self) {
+        let imp = self.imp();
+
+        imp.excludes_list.remove_all();
+        for pattern in self.exclude_patterns() {
+            let row = adw::ActionRow::builder()
+                .title(glib::markup_escape_text(&pattern))
+                .build();
+
+            let button = gtk::Button::builder()
+                .icon_name("user-trash-symbolic")
+                .tooltip_text(i18n("Remove"))
+                .valign(gtk::Align::Center)
+                .css_classes(["flat"])
+                .build();
+            button.connect_clicked(clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| {
+                    let mut patterns = this.exclude_patterns();
+                    patterns.retain(|p| *p != pattern);
+                    this.store_exclude_patterns(&patterns);
+                }
+            ));
+            row.add_suffix(&button);
+
+            imp.excludes_list.append(&row);
+        }
+
+        imp.excludes_list.append(&imp.exclude_entry.get());
+    }
+
+    fn artist_separators(&self) -> Vec<String> {
+        self.imp()
+            .settings
+            .strv("artist-separators")
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    fn store_artist_separators(&self, separators: &[String]) {
+        self.imp()
+            .settings
+            .set_strv("artist-separators", separators)
+            .expect("Unable to store setting");
+    }
+
+    fn update_separators(&self) {
+        let imp = self.imp();
+
+        imp.separators_list.remove_all();
+        for separator in self.artist_separators() {
+            // Quoted, so that the spaces around it can be seen
+            let row = adw::ActionRow::builder()
+                .title(glib::markup_escape_text(&format!("“{separator}”")))
+                .build();
+
+            let button = gtk::Button::builder()
+                .icon_name("user-trash-symbolic")
+                .tooltip_text(i18n("Remove"))
+                .valign(gtk::Align::Center)
+                .css_classes(["flat"])
+                .build();
+            button.connect_clicked(clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| {
+                    let mut separators = this.artist_separators();
+                    separators.retain(|s| *s != separator);
+                    this.store_artist_separators(&separators);
+                }
+            ));
+            row.add_suffix(&button);
+
+            imp.separators_list.append(&row);
+        }
+
+        imp.separators_list.append(&imp.separator_entry.get());
+    }
+
+    async fn add_music_folder(&self) {
+        let dialog = gtk::FileDialog::builder()
+            .accept_label(i18n("_Add Folder"))
+            .modal(true)
+            .title(i18n("Add Music Folder"))
+            .build();
+        if let Some(music) = glib::user_special_dir(glib::UserDirectory::Music) {
+            dialog.set_initial_folder(Some(&gio::File::for_path(music)));
+        }
+
+        let root = self.root().and_downcast::<gtk::Window>();
+        if let Ok(folder) = dialog.select_folder_future(root.as_ref()).await {
+            let uri = folder.uri().to_string();
+            let mut folders = self.imp().settings.strv("music-folders");
+            if !folders.iter().any(|f| f.as_str() == uri) {
+                folders.push(uri.into());
+                self.imp()
+                    .settings
+                    .set_strv("music-folders", folders)
+                    .expect("Unable to store setting");
+            }
+        }
+    }
+
+    fn update_music_folders(&self) {
+        let imp = self.imp();
+
+        imp.music_folders_list.remove_all();
+        for uri in imp.settings.strv("music-folders").iter() {
+            let uri = uri.to_string();
+            let file = gio::File::for_uri(&uri);
+            let title = file
+                .basename()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| uri.clone());
+            let row = adw::ActionRow::builder()
+                .title(glib::markup_escape_text(&title))
+                .subtitle(glib::markup_escape_text(&file.parse_name()))
+                .build();
+
+            let button = gtk::Button::builder()
+                .icon_name("user-trash-symbolic")
+                .tooltip_text(i18n("Remove"))
+                .valign(gtk::Align::Center)
+                .css_classes(["flat"])
+                .build();
+            button.connect_clicked(clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| {
+                    let folders: Vec<String> = this
+                        .imp()
+                        .settings
+                        .strv("music-folders")
+                        .iter()
+                        .map(|f| f.to_string())
+                        .filter(|f| *f != uri)
+                        .collect();
+                    this.imp()
+                        .settings
+                        .set_strv("music-folders", folders)
+                        .expect("Unable to store setting");
+                }
+            ));
+            row.add_suffix(&button);
+
+            imp.music_folders_list.append(&row);
+        }
+    }
+}
@@ -0,0 +1,598 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::ops::Range;
+
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+
+use crate::{
+    audio::Song,
+    utils::{fold_text, fold_text_with_offsets},
+};
+
+// A match in the title ranks a song above a match in the album, which
+// ranks it above a match in the artist
+const TITLE_WEIGHT: i64 = 3;
+const ALBUM_WEIGHT: i64 = 2;
+const ARTIST_WEIGHT: i64 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+}
+
+impl TextField {
+    const ALL: [TextField; 5] = [
+        TextField::Title,
+        TextField::Album,
+        TextField::Artist,
+        TextField::AlbumArtist,
+        TextField::Genre,
+    ];
+
+    fn weight(self) -> i64 {
+        match self {
+            TextField::Title => TITLE_WEIGHT,
+            TextField::Album => ALBUM_WEIGHT,
+            TextField::Artist | TextField::AlbumArtist | TextField::Genre => ARTIST_WEIGHT,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NumberField {
+    Year,
+    // In seconds
+    Duration,
+    // In stars, 0 for the songs we did not rate
+    Rating,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn test(self, value: u64, reference: u64) -> bool {
+        match self {
+            Comparison::Less => value < reference,
+            Comparison::LessOrEqual => value <= reference,
+            Comparison::Equal => value == reference,
+            Comparison::GreaterOrEqual => value >= reference,
+            Comparison::Greater => value > reference,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Condition {
+    // Bare words are matched loosely, as we type them; without a field,
+    // they can match any of them
+    Fuzzy(Option<TextField>, String),
+    // Quoted text has to appear as is
+    Phrase(Option<TextField>, String),
+    Number(NumberField, Comparison, u64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Term {
+    negated: bool,
+    condition: Condition,
+}
+
+// The parts of a song a query looks at, folded with fold_text()
+#[derive(Debug, Default)]
+pub struct SearchFields {
+    pub title: String,
+    pub artist: String,
+    // Each of the artists, when there are many, so that a search matches
+    // a name as well as it would on a song of its own
+    pub artists: Vec<String>,
+    pub album: String,
+    // The sort tags often hold a reading of names written in another
+    // script, so they can be searched too
+    pub title_sort: Option<String>,
+    pub artist_sort: Option<String>,
+    pub album_sort: Option<String>,
+    pub album_artist: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<u32>,
+    pub duration: u64,
+    pub rating: u32,
+}
+
+impl SearchFields {
+    pub fn from_song(song: &Song) -> Self {
+        Self {
+            title: fold_text(&song.title()),
+            artist: fold_text(&song.artist()),
+            artists: match song.artists() {
+                artists if artists.len() > 1 => artists.iter().map(|a| fold_text(a)).collect(),
+                _ => Vec::new(),
+            },
+            album: fold_text(&song.album()),
+            title_sort: song.title_sort().map(|s| fold_text(&s)),
+            artist_sort: song.artist_sort().map(|s| fold_text(&s)),
+            album_sort: song.album_sort().map(|s| fold_text(&s)),
+            album_artist: song.album_artist().map(|s| fold_text(&s)),
+            genre: song.genre().map(|s| fold_text(&s)),
+            year: song.year(),
+            duration: song.duration(),
+            rating: song.rating(),
+        }
+    }
+
+    fn texts(&self, field: TextField) -> impl Iterator<Item = &str> {
+        let (text, sort) = match field {
+            TextField::Title => (Some(self.title.as_str()), self.title_sort.as_deref()),
+            TextField::Artist => (Some(self.artist.as_str()), self.artist_sort.as_deref()),
+            TextField::Album => (Some(self.album.as_str()), self.album_sort.as_deref()),
+            TextField::AlbumArtist => (self.album_artist.as_deref(), None),
+            TextField::Genre => (self.genre.as_deref(), None),
+        };
+        let names: &[String] = match field {
+            TextField::Artist => &self.artists,
+            _ => &[],
+        };
+        text.into_iter()
+            .chain(sort)
+            .chain(names.iter().map(|s| s.as_str()))
+    }
+
+    fn number(&self, field: NumberField) -> Option<u64> {
+        match field {
+            NumberField::Year => self.year.map(u64::from),
+            NumberField::Duration => Some(self.duration),
+            NumberField::Rating => Some(u64::from(self.rating)),
+        }
+    }
+}
+
+// A search typed in the queue filter, e.g.
+//
+//   artist:radiohead album:"ok computer" year:>1995 -duration:<3m rating:>=4 karma
+//
+// Every term has to match for a song to match
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchQuery {
+    terms: Vec<Term>,
+}
+
+impl SearchQuery {
+    pub fn parse(search: &str) -> Self {
+        let terms = tokenize(&fold_text(search))
+            .into_iter()
+            .filter_map(parse_term)
+            .collect();
+
+        Self { terms }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn matches(&self, fields: &SearchFields) -> bool {
+        self.score(fields).is_some()
+    }
+
+    // How well the song matches the query, if it does at all; negated
+    // and numeric terms only filter, they do not change the rank
+    pub fn score(&self, fields: &SearchFields) -> Option<i64> {
+        let matcher = SkimMatcherV2::default();
+
+        let mut total = 0;
+        for term in &self.terms {
+            let score = term.condition.score(&matcher, fields);
+            match (term.negated, score) {
+                (true, Some(_)) | (false, None) => return None,
+                (false, Some(score)) => total += score,
+                (true, None) => (),
+            }
+        }
+
+        Some(total)
+    }
+
+    // The parts of a field, as displayed, that the query matched; these
+    // are byte ranges inside the text, in order
+    pub fn highlights(&self, field: TextField, text: &str) -> Vec<Range<usize>> {
+        let (folded, offsets) = fold_text_with_offsets(text);
+        let matcher = SkimMatcherV2::default();
+
+        // Which of the folded characters matched
+        let mut matched = vec![false; offsets.len()];
+        for term in self.terms.iter().filter(|t| !t.negated) {
+            match &term.condition {
+                Condition::Fuzzy(f, word) if f.is_none() || *f == Some(field) => {
+                    if let Some((_, indices)) = matcher.fuzzy_indices(&folded, word) {
+                        for i in indices {
+                            matched[i] = true;
+                        }
+                    }
+                }
+                Condition::Phrase(f, phrase) if f.is_none() || *f == Some(field) => {
+                    if phrase.is_empty() {
+                        continue;
+                    }
+                    let n_chars = phrase.chars().count();
+                    for (start, _) in folded.match_indices(phrase.as_str()) {
+                        let first = folded[..start].chars().count();
+                        matched[first..first + n_chars].fill(true);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        // Back to the original text, merging the neighbouring characters
+        let mut res: Vec<Range<usize>> = Vec::new();
+        for (i, _) in matched.iter().enumerate().filter(|(_, m)| **m) {
+            let start = offsets[i];
+            let end = start + text[start..].chars().next().map_or(0, char::len_utf8);
+            match res.last_mut() {
+                Some(last) if last.end >= start => last.end = last.end.max(end),
+                _ => res.push(start..end),
+            }
+        }
+
+        res
+    }
+}
+
+impl Condition {
+    fn score(&self, matcher: &SkimMatcherV2, fields: &SearchFields) -> Option<i64> {
+        match self {
+            Condition::Fuzzy(field, word) => text_fields(*field)
+                .flat_map(|f| fields.texts(f).map(move |text| (f, text)))
+                .filter_map(|(f, text)| matcher.fuzzy_match(text, word).map(|s| s * f.weight()))
+                .max(),
+            Condition::Phrase(field, phrase) => text_fields(*field)
+                .flat_map(|f| fields.texts(f).map(move |text| (f, text)))
+                .filter(|(_, text)| text.contains(phrase.as_str()))
+                .map(|(f, text)| matcher.fuzzy_match(text, phrase).unwrap_or_default() * f.weight())
+                .max(),
+            Condition::Number(field, comparison, reference) => fields
+                .number(*field)
+                .filter(|value| comparison.test(*value, *reference))
+                .map(|_| 0),
+        }
+    }
+}
+
+fn text_fields(field: Option<TextField>) -> impl Iterator<Item = TextField> {
+    TextField::ALL
+        .into_iter()
+        .filter(move |f| field.is_none() || field == Some(*f))
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Token {
+    text: String,
+    quoted: bool,
+    // Where the field name ends, if the token starts with one
+    colon: Option<usize>,
+}
+
+// Splits the search on white space, except inside quotes; an unfinished
+// quote runs until the end, since we filter while the search is typed
+fn tokenize(search: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut current = Token::default();
+    let mut in_quotes = false;
+
+    for c in search.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.quoted = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.text.is_empty() || current.quoted {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            ':' if !current.quoted && current.colon.is_none() => {
+                current.colon = Some(current.text.len());
+                current.text.push(c);
+            }
+            c => current.text.push(c),
+        }
+    }
+    if !current.text.is_empty() || current.quoted {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+fn parse_term(token: Token) -> Option<Term> {
+    let negated = token.text.starts_with('-');
+    let offset = usize::from(negated);
+    let text = &token.text[offset..];
+    // Nothing to look for yet
+    if text.is_empty() {
+        return None;
+    }
+
+    let field = token.colon.map(|colon| {
+        let (name, value) = text.split_at(colon - offset);
+        (name, &value[1..])
+    });
+
+    let condition = match field {
+        Some((name, value)) => match parse_field(name, value, token.quoted) {
+            Some(condition) => condition,
+            // Most likely, the value is still being typed
+            None if is_field_name(name) => return None,
+            None => bare_condition(text, token.quoted),
+        },
+        None => bare_condition(text, token.quoted),
+    };
+
+    Some(Term { negated, condition })
+}
+
+fn bare_condition(text: &str, quoted: bool) -> Condition {
+    if quoted {
+        Condition::Phrase(None, text.to_string())
+    } else {
+        Condition::Fuzzy(None, text.to_string())
+    }
+}
+
+fn is_field_name(name: &str) -> bool {
+    text_field(name).is_some() || number_field(name).is_some()
+}
+
+fn text_field(name: &str) -> Option<TextField> {
+    match name {
+        "title" => Some(TextField::Title),
+        "artist" => Some(TextField::Artist),
+        "album" => Some(TextField::Album),
+        "albumartist" => Some(TextField::AlbumArtist),
+        "genre" => Some(TextField::Genre),
+        _ => None,
+    }
+}
+
+fn number_field(name: &str) -> Option<NumberField> {
+    match name {
+        "year" => Some(NumberField::Year),
+        "duration" | "length" => Some(NumberField::Duration),
+        "rating" | "stars" => Some(NumberField::Rating),
+        _ => None,
+    }
+}
+
+fn parse_field(name: &str, value: &str, quoted: bool) -> Option<Condition> {
+    if value.is_empty() {
+        return None;
+    }
+
+    if let Some(field) = text_field(name) {
+        let value = value.to_string();
+        return if quoted {
+            Some(Condition::Phrase(Some(field), value))
+        } else {
+            Some(Condition::Fuzzy(Some(field), value))
+        };
+    }
+
+    let field = number_field(name)?;
+    let (comparison, value) = parse_comparison(value);
+    let reference = match field {
+        NumberField::Year | NumberField::Rating => value.parse::<u64>().ok()?,
+        NumberField::Duration => parse_duration(value)?,
+    };
+
+    Some(Condition::Number(field, comparison, reference))
+}
+
+fn parse_comparison(value: &str) -> (Comparison, &str) {
+    for (prefix, comparison) in [
+        (">=", Comparison::GreaterOrEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">", Comparison::Greater),
+        ("<", Comparison::Less),
+        ("=", Comparison::Equal),
+    ] {
+        if let Some(rest) = value.strip_prefix(prefix) {
+            return (comparison, rest);
+        }
+    }
+
+    (Comparison::Equal, value)
+}
+
+// Durations can be written as "3:30", "1:02:03", "3m30s", "1h", or as a
+// number of seconds; durations too long to count are not valid either
+pub fn parse_duration(value: &str) -> Option<u64> {
+    if value.contains(':') {
+        return value.split(':').try_fold(0u64, |total, part| {
+            total
+                .checked_mul(60)?
+                .checked_add(part.parse::<u64>().ok()?)
+        });
+    }
+
+    let mut total = 0;
+    let mut number: Option<u64> = None;
+    for c in value.chars() {
+        match c {
+            '0'..='9' => {
+                let digit = c.to_digit(10).unwrap() as u64;
+                number = Some(
+                    number
+                        .unwrap_or_default()
+                        .checked_mul(10)?
+                        .checked_add(digit)?,
+                );
+            }
+            'h' | 'm' | 's' => {
+                let unit = match c {
+                    'h' => 3600,
+                    'm' => 60,
+                    _ => 1,
+                };
+                total = number
+                    .take()?
+                    .checked_mul(unit)
+                    .and_then(|n| n.checked_add(total))?;
+            }
+            _ => return None,
+        }
+    }
+
+    match number {
+        Some(n) => total.checked_add(n),
+        None if !value.is_empty() => Some(total),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(title: &str, artist: &str, album: &str, year: u32, duration: u64) -> SearchFields {
+        SearchFields {
+            title: title.to_string(),
+            artist: artist.to_string(),
+            album: album.to_string(),
+            year: Some(year),
+            duration,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let query = SearchQuery::parse(r#"artist:Radiohead -album:"OK Computer" year:>2000 creep"#);
+        assert_eq!(
+            query.terms,
+            vec![
+                Term {
+                    negated: false,
+                    condition: Condition::Fuzzy(Some(TextField::Artist), "radiohead".into()),
+                },
+                Term {
+                    negated: true,
+                    condition: Condition::Phrase(Some(TextField::Album), "ok computer".into()),
+                },
+                Term {
+                    negated: false,
+                    condition: Condition::Number(NumberField::Year, Comparison::Greater, 2000),
+                },
+                Term {
+                    negated: false,
+                    condition: Condition::Fuzzy(None, "creep".into()),
+                },
+            ]
+        );
+
+        // Unknown fields and half typed terms
+        assert_eq!(
+            SearchQuery::parse("re:stacks").terms[0].condition,
+            Condition::Fuzzy(None, "re:stacks".into())
+        );
+        assert!(SearchQuery::parse(r#"artist: - year:> """#).is_empty());
+        assert_eq!(
+            SearchQuery::parse(r#""title: x"#).terms[0].condition,
+            Condition::Phrase(None, "title: x".into())
+        );
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("3m"), Some(180));
+        assert_eq!(parse_duration("3m30s"), Some(210));
+        assert_eq!(parse_duration("1h"), Some(3600));
+        assert_eq!(parse_duration("3:30"), Some(210));
+        assert_eq!(parse_duration("1:02:03"), Some(3723));
+        assert_eq!(parse_duration("90"), Some(90));
+        assert_eq!(parse_duration("m"), None);
+        assert_eq!(parse_duration("3x"), None);
+        assert_eq!(parse_duration("99999999999999999999"), None);
+        assert_eq!(parse_duration("9999999999999999999h"), None);
+        assert_eq!(parse_duration("9999999999999999999:00"), None);
+    }
+
+    #[test]
+    fn test_query_matches() {
+        let airbag = fields("airbag", "radiohead", "ok computer", 1997, 284);
+        let creep = fields("creep", "radiohead", "pablo honey", 1993, 238);
+
+        let query = SearchQuery::parse(r#"album:"ok computer""#);
+        assert!(query.matches(&airbag));
+        assert!(!query.matches(&creep));
+
+        let query = SearchQuery::parse("radiohead -airbag duration:<4m");
+        assert!(!query.matches(&airbag));
+        assert!(query.matches(&creep));
+
+        let query = SearchQuery::parse("year:>=1997");
+        assert!(query.matches(&airbag));
+        assert!(!query.matches(&creep));
+
+        let rated = SearchFields {
+            rating: 4,
+            ..fields("lucky", "radiohead", "ok computer", 1997, 259)
+        };
+        assert!(SearchQuery::parse("rating:>=4").matches(&rated));
+        assert!(!SearchQuery::parse("rating:>=4").matches(&creep));
+        assert!(SearchQuery::parse("stars:0").matches(&creep));
+
+        assert!(SearchQuery::parse("").matches(&creep));
+
+        // Diacritics and sort tags
+        let song = SearchFields {
+            artist: fold_text("Beyoncé"),
+            title_sort: Some(fold_text("Hikari")),
+            title: fold_text("光"),
+            ..Default::default()
+        };
+        assert!(SearchQuery::parse("BEYONCÉ").matches(&song));
+        assert!(SearchQuery::parse("artist:beyonce title:hikari").matches(&song));
+    }
+
+    #[test]
+    fn test_query_highlights() {
+        let query = SearchQuery::parse("beyonce");
+        assert_eq!(query.highlights(TextField::Artist, "Beyoncé"), vec![0..8]);
+
+        let query = SearchQuery::parse(r#"title:"ok" -computer"#);
+        assert_eq!(
+            query.highlights(TextField::Title, "Okay, OK Computer"),
+            vec![0..2, 6..8]
+        );
+        assert!(query.highlights(TextField::Artist, "OK").is_empty());
+
+        // Folding "ß" gives two characters, but we highlight one
+        let query = SearchQuery::parse("strasse");
+        assert_eq!(query.highlights(TextField::Title, "Straße"), vec![0..7]);
+    }
+
+    #[test]
+    fn test_query_ranking() {
+        let in_title = fields("karma police", "radiohead", "ok computer", 1997, 261);
+        let in_album = fields("lucky", "radiohead", "karma", 1997, 259);
+        let in_artist = fields("lucky", "karma", "ok computer", 1997, 259);
+
+        let query = SearchQuery::parse("karma");
+        let title = query.score(&in_title).unwrap();
+        let album = query.score(&in_album).unwrap();
+        let artist = query.score(&in_artist).unwrap();
+        assert!(title > album);
+        assert!(album > artist);
+    }
+}
@@ -5,12 +5,19 @@ use std::cell::{Cell, RefCell};
 
 use adw::subclass::prelude::*;
 use glib::clone;
-use gtk::{gdk, gio, glib, prelude::*, CompositeTemplate};
+use gtk::{gdk, gio, glib, pango, prelude::*, CompositeTemplate};
 
-use crate::{audio::Song, cover_picture::CoverPicture};
+use crate::{
+    audio::{Song, MAX_RATING},
+    cover_picture::CoverPicture,
+    query::{SearchQuery, TextField},
+    rating_bar::RatingBar,
+};
 
 mod imp {
-    use glib::{ParamSpec, ParamSpecBoolean, ParamSpecObject, ParamSpecString, Value};
+    use glib::{
+        ParamSpec, ParamSpecBoolean, ParamSpecObject, ParamSpecString, ParamSpecUInt, Value,
+    };
     use once_cell::sync::Lazy;
 
     use super::*;
@@ -30,6 +37,12 @@ mod imp {
         #[template_child]
         pub song_artist_label: TemplateChild<gtk::Inscription>,
         #[template_child]
+        pub song_radio_image: TemplateChild<gtk::Image>,
+        #[template_child]
+        pub song_skip_image: TemplateChild<gtk::Image>,
+        #[template_child]
+        pub song_rating_bar: TemplateChild<RatingBar>,
+        #[template_child]
         pub song_playing_image: TemplateChild<gtk::Image>,
         #[template_child]
         pub selection_title_label: TemplateChild<gtk::Inscription>,
@@ -43,6 +56,8 @@ mod imp {
         pub song: RefCell<Option<Song>>,
         pub playing: Cell<bool>,
         pub selection_mode: Cell<bool>,
+        pub search: RefCell<Option<String>>,
+        pub query: RefCell<Option<SearchQuery>>,
     }
 
     #[glib::object_subclass]
@@ -87,6 +102,10 @@ mod imp {
                     ParamSpecBoolean::builder("playing").build(),
                     ParamSpecBoolean::builder("selection-mode").build(),
                     ParamSpecBoolean::builder("selected").build(),
+                    ParamSpecBoolean::builder("auto-added").build(),
+                    ParamSpecBoolean::builder("skip-shuffle").build(),
+                    ParamSpecUInt::builder("rating").maximum(MAX_RATING).build(),
+                    ParamSpecString::builder("search").build(),
                 ]
             });
             PROPERTIES.as_ref()
@@ -128,6 +147,28 @@ mod imp {
                         .expect("The value needs to be a boolean");
                     self.selected_button.set_active(p);
                 }
+                "auto-added" => {
+                    let p = value
+                        .get::<bool>()
+                        .expect("The value needs to be a boolean");
+                    self.song_radio_image.set_visible(p);
+                }
+                "skip-shuffle" => {
+                    let p = value
+                        .get::<bool>()
+                        .expect("The value needs to be a boolean");
+                    self.song_skip_image.set_visible(p);
+                }
+                "rating" => {
+                    let p = value.get::<u32>().expect("The value needs to be a u32");
+                    self.song_rating_bar.set_rating(p);
+                }
+                "search" => {
+                    let p = value
+                        .get::<Option<String>>()
+                        .expect("The value needs to be a string");
+                    self.obj().set_search(p);
+                }
                 _ => unimplemented!(),
             }
         }
@@ -141,6 +182,10 @@ mod imp {
                 "playing" => self.playing.get().to_value(),
                 "selection-mode" => self.selection_mode.get().to_value(),
                 "selected" => self.selected_button.is_active().to_value(),
+                "auto-added" => self.song_radio_image.is_visible().to_value(),
+                "skip-shuffle" => self.song_skip_image.is_visible().to_value(),
+                "rating" => self.song_rating_bar.rating().to_value(),
+                "search" => self.search.borrow().to_value(),
                 _ => unimplemented!(),
             }
         }
@@ -210,12 +255,53 @@ impl QueueRow {
         let imp = self.imp();
         imp.song_title_label.set_text(Some(title));
         imp.selection_title_label.set_text(Some(title));
+        self.update_highlights();
     }
 
     fn set_song_artist(&self, artist: &str) {
         let imp = self.imp();
         imp.song_artist_label.set_text(Some(artist));
         imp.selection_artist_label.set_text(Some(artist));
+        self.update_highlights();
+    }
+
+    fn set_search(&self, search: Option<String>) {
+        let imp = self.imp();
+        if *imp.search.borrow() == search {
+            return;
+        }
+
+        imp.query.replace(
+            search
+                .as_deref()
+                .map(SearchQuery::parse)
+                .filter(|q| !q.is_empty()),
+        );
+        imp.search.replace(search);
+        self.update_highlights();
+        self.notify("search");
+    }
+
+    // Shows which parts of the title and of the artist the search matched
+    fn update_highlights(&self) {
+        let imp = self.imp();
+        let query = imp.query.borrow();
+
+        let title = imp.song_title_label.text().unwrap_or_default();
+        let title_attrs = query
+            .as_ref()
+            .map(|q| highlight_attributes(&q.highlights(TextField::Title, &title)));
+        imp.song_title_label.set_attributes(title_attrs.as_ref());
+        imp.selection_title_label
+            .set_attributes(title_attrs.as_ref());
+
+        let artist = imp.song_artist_label.text().unwrap_or_default();
+        let artist_attrs = query
+            .as_ref()
+            .map(|q| highlight_attributes(&q.highlights(TextField::Artist, &artist)));
+        imp.song_artist_label.set_attributes(artist_attrs.as_ref());
+        imp.selection_artist_label
+            .set_attributes(artist_attrs.as_ref());
     }
 
     fn set_song_cover(&self, cover: Option<gdk::Texture>) {
@@ -232,4 +318,24 @@ impl QueueRow {
     pub fn song(&self) -> Option<Song> {
         self.imp().song.borrow().clone()
     }
+
+    pub fn rating_bar(&self) -> RatingBar {
+        self.imp().song_rating_bar.get()
+    }
+}
+
+fn highlight_attributes(ranges: &[std::ops::Range<usize>]) -> pango::AttrList {
+    let attrs = pango::AttrList::new();
+    for range in ranges {
+        let mut weight = pango::AttrInt::new_weight(pango::Weight::Heavy);
+        weight.set_start_index(range.start as u32);
+        weight.set_end_index(range.end as u32);
+        attrs.insert(weight);
+
+        let mut underline = pango::AttrInt::new_underline(pango::Underline::Single);
+        underline.set_start_index(range.start as u32);
+        underline.set_end_index(range.end as u32);
+        attrs.insert(underline);
+    }
+    attrs
 }
@@ -0,0 +1,167 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::cell::{Cell, RefCell};
+
+use glib::{clone, subclass::Signal};
+use gtk::{glib, prelude::*, subclass::prelude::*};
+use once_cell::sync::Lazy;
+
+use crate::{audio::MAX_RATING, i18n::ni18n_f};
+
+mod imp {
+    use glib::{ParamSpec, ParamSpecUInt, Value};
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    pub struct RatingBar {
+        pub buttons: RefCell<Vec<gtk::Button>>,
+        pub rating: Cell<u32>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for RatingBar {
+        const NAME: &'static str = "AxuralisRatingBar";
+        type Type = super::RatingBar;
+        type ParentType = gtk::Widget;
+
+        fn class_init(klass: &mut Self::Class) {
+            klass.set_layout_manager_type::<gtk::BoxLayout>();
+            klass.set_css_name("ratingbar");
+            klass.set_accessible_role(gtk::AccessibleRole::Group);
+        }
+    }
+
+    impl ObjectImpl for RatingBar {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            self.obj().init_widgets();
+        }
+
+        fn dispose(&self) {
+            while let Some(child) = self.obj().first_child() {
+                child.unparent();
+            }
+        }
+
+        fn properties() -> &'static [ParamSpec] {
+            static PROPERTIES: Lazy<Vec<ParamSpec>> = Lazy::new(|| {
+                vec![ParamSpecUInt::builder("rating")
+                    .maximum(MAX_RATING)
+                    .explicit_notify()
+                    .build()]
+            });
+            PROPERTIES.as_ref()
+        }
+
+        fn set_property(&self, _id: usize, value: &Value, pspec: &ParamSpec) {
+            match pspec.name() {
+                "rating" => self.obj().set_rating(value.get::<u32>().unwrap()),
+                _ => unimplemented!(),
+            }
+        }
+
+        fn property(&self, _id: usize, pspec: &ParamSpec) -> Value {
+            match pspec.name() {
+                "rating" => self.rating.get().to_value(),
+                _ => unimplemented!(),
+            }
+        }
+
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| {
+                // The number of stars picked; 0 clears the rating
+                vec![Signal::builder("rate")
+                    .param_types([u32::static_type()])
+                    .build()]
+            });
+
+            SIGNALS.as_ref()
+        }
+    }
+
+    impl WidgetImpl for RatingBar {}
+}
+
+// A row of stars; picking one emits "rate", and whoever owns the song
+// updates the rating
+glib::wrapper! {
+    pub struct RatingBar(ObjectSubclass<imp::RatingBar>)
+        @extends gtk::Widget;
+}
+
+impl Default for RatingBar {
+    fn default() -> Self {
+        glib::Object::new()
+    }
+}
+
+impl RatingBar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn init_widgets(&self) {
+        let mut buttons = self.imp().buttons.borrow_mut();
+        for stars in 1..=MAX_RATING {
+            let button = gtk::Button::builder()
+                .icon_name("non-starred-symbolic")
+                .tooltip_text(ni18n_f(
+                    // Translators: the `{}` must be left unmodified;
+                    // it will be expanded to a number of stars
+                    "Rate {} Star",
+                    "Rate {} Stars",
+                    stars,
+                    &[&stars.to_string()],
+                ))
+                .valign(gtk::Align::Center)
+                .css_classes(["flat", "star"])
+                .build();
+            button.connect_clicked(clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| {
+                    // Picking the current rating again clears it
+                    let rating = if this.rating() == stars { 0 } else { stars };
+                    this.emit_by_name::<()>("rate", &[&rating]);
+                }
+            ));
+            button.set_parent(self);
+            buttons.push(button);
+        }
+        drop(buttons);
+
+        self.update_stars();
+    }
+
+    pub fn rating(&self) -> u32 {
+        self.imp().rating.get()
+    }
+
+    pub fn set_rating(&self, rating: u32) {
+        let rating = rating.min(MAX_RATING);
+        if rating != self.imp().rating.replace(rating) {
+            self.update_stars();
+            self.notify("rating");
+        }
+    }
+
+    fn update_stars(&self) {
+        let rating = self.rating();
+        for (i, button) in self.imp().buttons.borrow().iter().enumerate() {
+            if (i as u32) < rating {
+                button.set_icon_name("starred-symbolic");
+            } else {
+                button.set_icon_name("non-starred-symbolic");
+            }
+        }
+
+        if rating > 0 {
+            self.add_css_class("rated");
+        } else {
+            self.remove_css_class("rated");
+        }
+    }
+}
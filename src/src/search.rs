@@ -3,11 +3,12 @@
 
 use gtk::{glib, prelude::*, subclass::prelude::*};
 
+use crate::query::SearchQuery;
+
 mod imp {
 
     use std::cell::RefCell;
 
-    use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
     use gtk::{
         glib::{self, ParamSpec, ParamSpecString, Value},
         prelude::*,
@@ -15,11 +16,12 @@ mod imp {
     };
     use once_cell::sync::Lazy;
 
-    use crate::audio::Song;
+    use crate::{audio::Song, query::SearchQuery};
 
     #[derive(Default)]
     pub struct FuzzyFilter {
         pub search: RefCell<Option<String>>,
+        pub query: RefCell<Option<SearchQuery>>,
     }
 
     #[glib::object_subclass]
@@ -57,12 +59,9 @@ mod imp {
         fn match_(&self, song: &glib::Object) -> bool {
             let song = song.downcast_ref::<Song>().unwrap();
 
-            if let Some(search) = self.search.borrow().as_ref() {
-                let key = song.search_key();
-                let matcher = SkimMatcherV2::default();
-                matcher.fuzzy_match(&key, search).is_some() || search.is_empty()
-            } else {
-                true
+            match self.query.borrow().as_ref() {
+                Some(query) if !query.is_empty() => query.matches(&song.search_fields()),
+                _ => true,
             }
         }
     }
@@ -91,7 +90,8 @@ impl FuzzyFilter {
 
     pub fn set_search(&self, search: Option<String>) {
         if *self.imp().search.borrow() != search {
-            *self.imp().search.borrow_mut() = search.map(|x| x.to_lowercase());
+            *self.imp().query.borrow_mut() = search.as_deref().map(SearchQuery::parse);
+            *self.imp().search.borrow_mut() = search;
             self.changed(gtk::FilterChange::Different);
         }
     }
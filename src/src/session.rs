@@ -0,0 +1,172 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::path::PathBuf;
+
+use gtk::{gio, glib, prelude::*};
+use log::{debug, warn};
+
+use crate::{
+    audio::{AudioPlayer, RepeatMode},
+    utils,
+};
+
+// Bump this whenever the layout of the session file changes in a way
+// that older versions of Axuralis would misinterpret
+const SESSION_VERSION: i64 = 1;
+const SESSION_GROUP: &str = "session";
+
+// A snapshot of the playback session: the songs in the queue, in their
+// order, alongside everything we need to resume playback exactly where
+// it stopped
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    pub files: Vec<gio::File>,
+    // When each of the files was added to the queue, for sorting
+    pub added: Vec<i64>,
+    pub current: Option<gio::File>,
+    pub position: u64,
+    pub shuffle: Option<Vec<u32>>,
+    pub repeat_mode: RepeatMode,
+    pub volume: f64,
+}
+
+fn session_path() -> PathBuf {
+    let mut path = glib::user_cache_dir();
+    path.push("axuralis");
+    path.push("playlists");
+    path.push("current.session");
+    path
+}
+
+impl Session {
+    pub fn capture(player: &AudioPlayer) -> Self {
+        let queue = player.queue();
+        let state = player.state();
+
+        Session {
+            files: queue.songs().iter().map(|s| s.file()).collect(),
+            added: queue.songs().iter().map(|s| s.added()).collect(),
+            current: state.current_song().map(|s| s.file()),
+            position: state.position(),
+            shuffle: queue.shuffle_order(),
+            repeat_mode: queue.repeat_mode(),
+            volume: state.volume(),
+        }
+    }
+
+    pub fn save(&self) {
+        let kf = glib::KeyFile::new();
+        kf.set_int64(SESSION_GROUP, "Version", SESSION_VERSION);
+        kf.set_int64(SESSION_GROUP, "NumberOfEntries", self.files.len() as i64);
+
+        for (i, file) in self.files.iter().enumerate() {
+            kf.set_value(SESSION_GROUP, &format!("File{i}"), &file.uri());
+        }
+        for (i, added) in self.added.iter().enumerate() {
+            kf.set_int64(SESSION_GROUP, &format!("Added{i}"), *added);
+        }
+
+        if let Some(file) = self.current.as_ref() {
+            kf.set_value(SESSION_GROUP, "Current", &file.uri());
+        }
+        kf.set_uint64(SESSION_GROUP, "Position", self.position);
+
+        if let Some(ref shuffle) = self.shuffle {
+            let order: Vec<String> = shuffle.iter().map(|p| p.to_string()).collect();
+            kf.set_string(SESSION_GROUP, "Shuffle", &order.join(","));
+        }
+
+        kf.set_string(SESSION_GROUP, "RepeatMode", &self.repeat_mode.to_string());
+        kf.set_double(SESSION_GROUP, "Volume", self.volume);
+
+        let path = session_path();
+        if let Some(parent) = path.parent() {
+            glib::mkdir_with_parents(parent, 0o755);
+        }
+
+        // GKeyFile writes to a temporary file and renames it on top of the
+        // existing one, so we never leave a truncated session behind
+        match kf.save_to_file(&path) {
+            Ok(_) => debug!("Session stored in: {:?}", &path),
+            Err(e) => warn!("Unable to store session: {e}"),
+        }
+    }
+
+    pub fn load() -> Option<Self> {
+        let path = session_path();
+
+        let kf = glib::KeyFile::new();
+        if let Err(e) = kf.load_from_file(&path, glib::KeyFileFlags::NONE) {
+            debug!("Unable to load session: {e}");
+            return None;
+        }
+
+        match kf.int64(SESSION_GROUP, "Version") {
+            Ok(SESSION_VERSION) => (),
+            Ok(v) => {
+                warn!("Ignoring session with unsupported version {v}");
+                return None;
+            }
+            Err(e) => {
+                warn!("Ignoring session without version: {e}");
+                return None;
+            }
+        }
+
+        let n_entries = kf.int64(SESSION_GROUP, "NumberOfEntries").unwrap_or(0) as usize;
+        let mut files = Vec::with_capacity(n_entries);
+        let mut added = Vec::with_capacity(n_entries);
+        for i in 0..n_entries {
+            match kf.value(SESSION_GROUP, &format!("File{i}")) {
+                Ok(p) => {
+                    files.push(utils::file_for_location(&p));
+                    added.push(kf.int64(SESSION_GROUP, &format!("Added{i}")).unwrap_or(0));
+                }
+                Err(e) => debug!("Skipping File{i} from session: {e}"),
+            }
+        }
+
+        let current = kf
+            .value(SESSION_GROUP, "Current")
+            .ok()
+            .map(|p| utils::file_for_location(&p));
+
+        // The shuffle order is only meaningful if it's a permutation of
+        // every entry in the session
+        let shuffle = kf.string(SESSION_GROUP, "Shuffle").ok().and_then(|s| {
+            let order: Vec<u32> = s.split(',').filter_map(|p| p.parse().ok()).collect();
+            let mut sorted = order.clone();
+            sorted.sort_unstable();
+            if sorted.iter().copied().eq(0..files.len() as u32) {
+                Some(order)
+            } else {
+                warn!("Discarding invalid shuffle order from session");
+                None
+            }
+        });
+
+        let repeat_mode = kf
+            .string(SESSION_GROUP, "RepeatMode")
+            .ok()
+            .and_then(|s| s.parse::<RepeatMode>().ok())
+            .unwrap_or_default();
+
+        Some(Session {
+            files,
+            added,
+            current,
+            position: kf.uint64(SESSION_GROUP, "Position").unwrap_or(0),
+            shuffle,
+            repeat_mode,
+            volume: kf
+                .double(SESSION_GROUP, "Volume")
+                .unwrap_or(1.0)
+                .clamp(0.0, 1.0),
+        })
+    }
+
+    pub fn exists() -> bool {
+        session_path().exists()
+    }
+}
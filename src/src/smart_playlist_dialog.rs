@@ -0,0 +1,369 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::cell::{Cell, RefCell};
+
+use adw::{prelude::*, subclass::prelude::*};
+use glib::{clone, subclass::Signal};
+use gtk::{glib, CompositeTemplate};
+use once_cell::sync::Lazy;
+
+use crate::{
+    audio::{
+        load_smart_playlists, store_smart_playlists, FieldKind, Library, Rule, RuleField,
+        RuleOperator, SmartPlaylist,
+    },
+    i18n::{i18n, ni18n_f},
+};
+
+// The widgets that edit a single rule
+#[derive(Debug)]
+struct RuleRow {
+    row: gtk::ListBoxRow,
+    field: gtk::DropDown,
+    operator: gtk::DropDown,
+    value: gtk::Entry,
+}
+
+impl RuleRow {
+    fn field(&self) -> RuleField {
+        RuleField::ALL[self.field.selected() as usize]
+    }
+
+    fn operator(&self) -> RuleOperator {
+        let operators = RuleOperator::for_kind(self.field().kind());
+        operators[(self.operator.selected() as usize).min(operators.len() - 1)]
+    }
+
+    fn rule(&self) -> Rule {
+        Rule::new(self.field(), self.operator(), self.value.text().trim())
+    }
+
+    // Only offer the operators that make sense for the field
+    fn update_operators(&self) {
+        let kind = self.field().kind();
+        let labels: Vec<String> = RuleOperator::for_kind(kind)
+            .iter()
+            .map(|o| o.label())
+            .collect();
+        let labels: Vec<&str> = labels.iter().map(|s| s.as_str()).collect();
+        self.operator
+            .set_model(Some(&gtk::StringList::new(&labels)));
+
+        let placeholder = match kind {
+            FieldKind::Text => i18n("Text"),
+            FieldKind::Number => i18n("Number"),
+            FieldKind::Duration => i18n("Duration, e.g. 3:30"),
+            FieldKind::Date => i18n("Days"),
+        };
+        self.value.set_placeholder_text(Some(&placeholder));
+    }
+
+    fn update_value(&self) {
+        self.value.set_sensitive(self.operator().needs_value());
+    }
+}
+
+mod imp {
+    use super::*;
+
+    #[derive(Debug, Default, CompositeTemplate)]
+    #[template(resource = "/com/axos-project/Axuralis/smart-playlist-dialog.ui")]
+    pub struct SmartPlaylistDialog {
+        // Template widgets
+        #[template_child]
+        pub cancel_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub save_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub name_row: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub match_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub add_rule_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub rules_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub count_label: TemplateChild<gtk::Label>,
+
+        pub library: RefCell<Option<Library>>,
+        pub rule_rows: RefCell<Vec<RuleRow>>,
+        // The position of the playlist we are editing, if any
+        pub position: Cell<Option<usize>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for SmartPlaylistDialog {
+        const NAME: &'static str = "AxuralisSmartPlaylistDialog";
+        type Type = super::SmartPlaylistDialog;
+        type ParentType = adw::Dialog;
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for SmartPlaylistDialog {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            self.obj().init_widgets();
+        }
+
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> =
+                Lazy::new(|| vec![Signal::builder("saved").build()]);
+
+            SIGNALS.as_ref()
+        }
+    }
+
+    impl WidgetImpl for SmartPlaylistDialog {}
+    impl AdwDialogImpl for SmartPlaylistDialog {}
+}
+
+glib::wrapper! {
+    pub struct SmartPlaylistDialog(ObjectSubclass<imp::SmartPlaylistDialog>)
+        @extends gtk::Widget, adw::Dialog,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget;
+}
+
+impl SmartPlaylistDialog {
+    // Edits the stored playlist at `position`, or creates a new one
+    pub fn new(library: &Library, position: Option<usize>) -> Self {
+        let dialog: Self = glib::Object::new();
+        let imp = dialog.imp();
+        imp.library.replace(Some(library.clone()));
+        imp.position.set(position);
+
+        let playlist = position
+            .and_then(|pos| load_smart_playlists().into_iter().nth(pos))
+            .unwrap_or_else(|| {
+                let mut playlist = SmartPlaylist::new("");
+                playlist
+                    .rules
+                    .push(Rule::new(RuleField::Genre, RuleOperator::Is, ""));
+                playlist
+            });
+        dialog.set_playlist(&playlist);
+
+        dialog
+    }
+
+    fn init_widgets(&self) {
+        let imp = self.imp();
+
+        imp.cancel_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.close();
+            }
+        ));
+        imp.save_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.save();
+            }
+        ));
+        imp.add_rule_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.add_rule(&Rule::new(RuleField::Title, RuleOperator::Contains, ""));
+                this.update();
+            }
+        ));
+        imp.name_row.connect_changed(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.update();
+            }
+        ));
+        imp.match_row.connect_selected_notify(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.update();
+            }
+        ));
+
+        let placeholder = gtk::Label::builder()
+            .label(i18n("Every Song in the Library"))
+            .margin_top(12)
+            .margin_bottom(12)
+            .css_classes(["dim-label"])
+            .build();
+        imp.rules_list.set_placeholder(Some(&placeholder));
+    }
+
+    fn set_playlist(&self, playlist: &SmartPlaylist) {
+        let imp = self.imp();
+
+        imp.name_row.set_text(&playlist.name);
+        imp.match_row
+            .set_selected(if playlist.match_all { 0 } else { 1 });
+        for rule in &playlist.rules {
+            self.add_rule(rule);
+        }
+
+        self.update();
+    }
+
+    fn playlist(&self) -> SmartPlaylist {
+        let imp = self.imp();
+
+        SmartPlaylist {
+            name: imp.name_row.text().trim().to_string(),
+            match_all: imp.match_row.selected() == 0,
+            rules: imp.rule_rows.borrow().iter().map(|r| r.rule()).collect(),
+        }
+    }
+
+    fn add_rule(&self, rule: &Rule) {
+        let fields: Vec<String> = RuleField::ALL.iter().map(|f| f.label()).collect();
+        let fields: Vec<&str> = fields.iter().map(|s| s.as_str()).collect();
+        let field = gtk::DropDown::from_strings(&fields);
+        let operator = gtk::DropDown::builder().build();
+        let value = gtk::Entry::builder()
+            .text(&rule.value)
+            .hexpand(true)
+            .build();
+        let remove_button = gtk::Button::builder()
+            .icon_name("user-trash-symbolic")
+            .tooltip_text(i18n("Remove Rule"))
+            .valign(gtk::Align::Center)
+            .css_classes(["flat"])
+            .build();
+
+        let child = gtk::Box::builder()
+            .spacing(6)
+            .margin_top(6)
+            .margin_bottom(6)
+            .margin_start(6)
+            .margin_end(6)
+            .build();
+        child.append(&field);
+        child.append(&operator);
+        child.append(&value);
+        child.append(&remove_button);
+        let row = gtk::ListBoxRow::builder()
+            .activatable(false)
+            .child(&child)
+            .build();
+
+        let rule_row = RuleRow {
+            row: row.clone(),
+            field: field.clone(),
+            operator: operator.clone(),
+            value: value.clone(),
+        };
+        let field_pos = RuleField::ALL.iter().position(|f| *f == rule.field);
+        field.set_selected(field_pos.unwrap_or(0) as u32);
+        rule_row.update_operators();
+        let operator_pos = RuleOperator::for_kind(rule.field.kind())
+            .iter()
+            .position(|o| *o == rule.operator);
+        operator.set_selected(operator_pos.unwrap_or(0) as u32);
+        rule_row.update_value();
+
+        field.connect_selected_notify(clone!(
+            #[weak(rename_to = this)]
+            self,
+            #[weak]
+            row,
+            move |_| {
+                if let Some(rule_row) = this.imp().rule_rows.borrow().iter().find(|r| r.row == row)
+                {
+                    rule_row.update_operators();
+                    rule_row.update_value();
+                }
+                this.update();
+            }
+        ));
+        operator.connect_selected_notify(clone!(
+            #[weak(rename_to = this)]
+            self,
+            #[weak]
+            row,
+            move |_| {
+                if let Some(rule_row) = this.imp().rule_rows.borrow().iter().find(|r| r.row == row)
+                {
+                    rule_row.update_value();
+                }
+                this.update();
+            }
+        ));
+        value.connect_changed(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.update();
+            }
+        ));
+        remove_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            #[weak]
+            row,
+            move |_| {
+                this.imp().rule_rows.borrow_mut().retain(|r| r.row != row);
+                this.imp().rules_list.remove(&row);
+                this.update();
+            }
+        ));
+
+        self.imp().rules_list.append(&row);
+        self.imp().rule_rows.borrow_mut().push(rule_row);
+    }
+
+    // Checks the rules, and counts the songs that follow them
+    fn update(&self) {
+        let imp = self.imp();
+
+        let playlist = self.playlist();
+        let rules_valid = playlist.rules.iter().all(|r| r.is_valid());
+        imp.save_button
+            .set_sensitive(rules_valid && !playlist.name.is_empty());
+
+        if !rules_valid {
+            imp.count_label
+                .set_label(&i18n("Some rules are incomplete"));
+            return;
+        }
+
+        let n_songs = imp
+            .library
+            .borrow()
+            .as_ref()
+            .map(|l| l.smart_playlist_songs(&playlist).len())
+            .unwrap_or(0);
+        imp.count_label.set_label(&ni18n_f(
+            // Translators: the `{}` must be left unmodified;
+            // it will be expanded to the number of songs
+            "{} song in the library follows these rules",
+            "{} songs in the library follow these rules",
+            n_songs as u32,
+            &[&n_songs.to_string()],
+        ));
+    }
+
+    fn save(&self) {
+        let playlist = self.playlist();
+        let mut playlists = load_smart_playlists();
+        match self.imp().position.get() {
+            Some(pos) if pos < playlists.len() => playlists[pos] = playlist,
+            _ => playlists.push(playlist),
+        }
+        store_smart_playlists(&playlists);
+
+        self.emit_by_name::<()>("saved", &[]);
+        self.close();
+    }
+}
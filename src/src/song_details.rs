@@ -4,7 +4,7 @@
 use adw::subclass::prelude::*;
 use gtk::{glib, prelude::*, CompositeTemplate};
 
-use crate::marquee::Marquee;
+use crate::{marquee::Marquee, rating_bar::RatingBar};
 
 mod imp {
     use super::*;
@@ -19,6 +19,8 @@ mod imp {
         pub song_artist_label: TemplateChild<Marquee>,
         #[template_child]
         pub song_album_label: TemplateChild<Marquee>,
+        #[template_child]
+        pub song_rating_bar: TemplateChild<RatingBar>,
     }
 
     #[glib::object_subclass]
@@ -78,4 +80,8 @@ impl SongDetails {
     pub fn album_label(&self) -> Marquee {
         self.imp().song_album_label.get()
     }
+
+    pub fn rating_bar(&self) -> RatingBar {
+        self.imp().song_rating_bar.get()
+    }
 }
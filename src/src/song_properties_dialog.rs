@@ -0,0 +1,224 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::cell::RefCell;
+
+use adw::{prelude::*, subclass::prelude::*};
+use glib::clone;
+use gtk::{gdk, gio, glib, CompositeTemplate};
+use log::warn;
+
+use crate::{
+    audio::{cache_section, inspect_file, report_text, ReportRow, ReportSection, Song},
+    i18n::i18n,
+};
+
+// How large the embedded pictures are shown
+const PICTURE_SIZE: i32 = 48;
+
+mod imp {
+    use super::*;
+
+    #[derive(Debug, Default, CompositeTemplate)]
+    #[template(resource = "/com/axos-project/Axuralis/song-properties-dialog.ui")]
+    pub struct SongPropertiesDialog {
+        // Template widgets
+        #[template_child]
+        pub toast_overlay: TemplateChild<adw::ToastOverlay>,
+        #[template_child]
+        pub copy_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub report_page: TemplateChild<adw::PreferencesPage>,
+        #[template_child]
+        pub location_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub open_folder_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub size_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub loading_group: TemplateChild<adw::PreferencesGroup>,
+
+        pub song: RefCell<Option<Song>>,
+        pub sections: RefCell<Vec<ReportSection>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for SongPropertiesDialog {
+        const NAME: &'static str = "AxuralisSongPropertiesDialog";
+        type Type = super::SongPropertiesDialog;
+        type ParentType = adw::Dialog;
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for SongPropertiesDialog {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            self.obj().init_widgets();
+        }
+    }
+
+    impl WidgetImpl for SongPropertiesDialog {}
+    impl AdwDialogImpl for SongPropertiesDialog {}
+}
+
+glib::wrapper! {
+    pub struct SongPropertiesDialog(ObjectSubclass<imp::SongPropertiesDialog>)
+        @extends gtk::Widget, adw::Dialog,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget;
+}
+
+impl SongPropertiesDialog {
+    pub fn new(song: &Song) -> Self {
+        let dialog: Self = glib::Object::new();
+        dialog.set_song(song);
+        dialog
+    }
+
+    fn init_widgets(&self) {
+        let imp = self.imp();
+
+        imp.copy_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.copy_report();
+            }
+        ));
+        imp.open_folder_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                glib::spawn_future_local(clone!(
+                    #[weak]
+                    this,
+                    async move {
+                        this.open_folder().await;
+                    }
+                ));
+            }
+        ));
+    }
+
+    fn set_song(&self, song: &Song) {
+        let imp = self.imp();
+        imp.song.replace(Some(song.clone()));
+
+        let file = song.file();
+        imp.location_row.set_subtitle(file.parse_name().as_str());
+        // Only local files live in a folder we can show
+        imp.open_folder_button.set_visible(file.path().is_some());
+        imp.size_row
+            .set_subtitle(glib::format_size(song.file_size()).as_str());
+
+        let file_section = ReportSection {
+            title: i18n("File"),
+            rows: vec![
+                ReportRow {
+                    label: i18n("Location"),
+                    value: file.parse_name().to_string(),
+                    image: None,
+                },
+                ReportRow {
+                    label: i18n("Size"),
+                    value: glib::format_size(song.file_size()).to_string(),
+                    image: None,
+                },
+            ],
+        };
+        imp.sections.replace(vec![file_section]);
+
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = this)]
+            self,
+            #[strong]
+            song,
+            async move {
+                let sections = gio::spawn_blocking(move || inspect_file(&file))
+                    .await
+                    .unwrap_or_default();
+                this.add_sections(sections);
+                this.add_sections(vec![cache_section(&song)]);
+
+                let imp = this.imp();
+                imp.report_page.remove(&*imp.loading_group);
+                imp.copy_button.set_sensitive(true);
+            }
+        ));
+    }
+
+    fn add_sections(&self, sections: Vec<ReportSection>) {
+        let imp = self.imp();
+
+        for section in sections.iter().filter(|s| !s.rows.is_empty()) {
+            let group = adw::PreferencesGroup::builder()
+                .title(&section.title)
+                .build();
+            for row in &section.rows {
+                group.add(&self.report_row(row));
+            }
+            imp.report_page.add(&group);
+        }
+
+        imp.sections.borrow_mut().extend(sections);
+    }
+
+    fn report_row(&self, row: &ReportRow) -> adw::ActionRow {
+        let action_row = adw::ActionRow::builder()
+            .title(&row.label)
+            .subtitle(&row.value)
+            .use_markup(false)
+            .subtitle_selectable(true)
+            .build();
+
+        if let Some(ref bytes) = row.image {
+            match gdk::Texture::from_bytes(bytes) {
+                Ok(texture) => {
+                    let picture = gtk::Picture::builder()
+                        .paintable(&texture)
+                        .content_fit(gtk::ContentFit::Cover)
+                        .width_request(PICTURE_SIZE)
+                        .height_request(PICTURE_SIZE)
+                        .valign(gtk::Align::Center)
+                        .css_classes(["card"])
+                        .overflow(gtk::Overflow::Hidden)
+                        .build();
+                    action_row.add_prefix(&picture);
+                }
+                Err(e) => warn!("Unable to load embedded picture: {}", e),
+            }
+        }
+
+        action_row
+    }
+
+    fn copy_report(&self) {
+        let text = report_text(&self.imp().sections.borrow());
+        self.clipboard().set_text(&text);
+        self.imp()
+            .toast_overlay
+            .add_toast(adw::Toast::new(&i18n("Report copied")));
+    }
+
+    // The file manager shows the folder with the file selected; in a
+    // sandbox, this goes through the portal
+    async fn open_folder(&self) {
+        let file = match self.imp().song.borrow().as_ref() {
+            Some(song) => song.file(),
+            None => return,
+        };
+
+        let root = self.root().and_downcast::<gtk::Window>();
+        let launcher = gtk::FileLauncher::new(Some(&file));
+        if let Err(e) = launcher.open_containing_folder_future(root.as_ref()).await {
+            warn!("Unable to open the folder of {}: {}", file.uri(), e);
+        }
+    }
+}
@@ -3,11 +3,12 @@
 
 use gtk::{glib, prelude::*, subclass::prelude::*};
 
+use crate::query::SearchQuery;
+
 mod imp {
 
     use std::cell::RefCell;
 
-    use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
     use gtk::{
         glib::{self, ParamSpec, ParamSpecString, Value},
         prelude::*,
@@ -15,11 +16,12 @@ mod imp {
     };
     use once_cell::sync::Lazy;
 
-    use crate::{audio::Song, utils::cmp_two_files};
+    use crate::{audio::Song, query::SearchQuery};
 
     #[derive(Default)]
     pub struct FuzzySorter {
         pub search: RefCell<Option<String>>,
+        pub query: RefCell<Option<SearchQuery>>,
     }
 
     #[glib::object_subclass]
@@ -54,15 +56,14 @@ mod imp {
             let item1 = item1.downcast_ref::<Song>().unwrap();
             let item2 = item2.downcast_ref::<Song>().unwrap();
 
-            if let Some(search) = self.search.borrow().as_ref() {
-                let matcher = SkimMatcherV2::default();
-                let item1_key = item1.search_key();
-                let item2_key = item2.search_key();
-                let item1_score = matcher.fuzzy_match(&item1_key, search);
-                let item2_score = matcher.fuzzy_match(&item2_key, search);
-                item1_score.cmp(&item2_score).reverse().into()
-            } else {
-                cmp_two_files(None, &item1.file(), &item2.file()).into()
+            match self.query.borrow().as_ref() {
+                Some(query) if !query.is_empty() => {
+                    let item1_score = query.score(&item1.search_fields());
+                    let item2_score = query.score(&item2.search_fields());
+                    item1_score.cmp(&item2_score).reverse().into()
+                }
+                // Without a search, we follow the order of the queue
+                _ => gtk::Ordering::Equal,
             }
         }
 
@@ -95,6 +96,7 @@ impl FuzzySorter {
 
     pub fn set_search(&self, search: Option<String>) {
         if *self.imp().search.borrow() != search {
+            *self.imp().query.borrow_mut() = search.as_deref().map(SearchQuery::parse);
             *self.imp().search.borrow_mut() = search;
             self.changed(gtk::SorterChange::Different);
         }
@@ -0,0 +1,318 @@
+// SPDX-FileCopyrightText: 2025 Ardox
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::cell::RefCell;
+
+use adw::{prelude::*, subclass::prelude::*};
+use glib::{clone, subclass::Signal};
+use gtk::{gdk, gio, glib, CompositeTemplate};
+use log::warn;
+use once_cell::sync::Lazy;
+
+use crate::{
+    audio::{CoverChange, Song, TagChanges, TagField},
+    cover_picture::CoverPicture,
+    i18n::{i18n, ni18n_f},
+};
+
+fn field_label(field: TagField) -> String {
+    match field {
+        TagField::Title => i18n("Title"),
+        TagField::Artist => i18n("Artist"),
+        TagField::Album => i18n("Album"),
+        TagField::AlbumArtist => i18n("Album Artist"),
+        TagField::Genre => i18n("Genre"),
+        TagField::Composer => i18n("Composer"),
+        TagField::Year => i18n("Year"),
+        TagField::TrackNumber => i18n("Track Number"),
+        TagField::TrackTotal => i18n("Number of Tracks"),
+        TagField::DiscNumber => i18n("Disc Number"),
+        TagField::DiscTotal => i18n("Number of Discs"),
+        TagField::Comment => i18n("Comment"),
+    }
+}
+
+// The row editing a field, and what the field looked like before
+#[derive(Debug)]
+struct FieldRow {
+    field: TagField,
+    row: adw::EntryRow,
+    initial: String,
+    // The songs do not agree on the value; unless something is typed in,
+    // we leave each of them alone
+    mixed: bool,
+}
+
+impl FieldRow {
+    fn change(&self) -> Option<String> {
+        let text = self.row.text().trim().to_string();
+        let changed = if self.mixed {
+            !text.is_empty()
+        } else {
+            text != self.initial
+        };
+
+        changed.then_some(text)
+    }
+
+    fn is_valid(&self) -> bool {
+        let text = self.row.text();
+        let text = text.trim();
+        !self.field.is_number() || text.is_empty() || text.parse::<u32>().is_ok()
+    }
+}
+
+mod imp {
+    use super::*;
+
+    #[derive(Debug, Default, CompositeTemplate)]
+    #[template(resource = "/com/axos-project/Axuralis/tag-editor-dialog.ui")]
+    pub struct TagEditorDialog {
+        // Template widgets
+        #[template_child]
+        pub cancel_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub save_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub cover_picture: TemplateChild<CoverPicture>,
+        #[template_child]
+        pub cover_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub fields_group: TemplateChild<adw::PreferencesGroup>,
+
+        pub songs: RefCell<Vec<Song>>,
+        pub field_rows: RefCell<Vec<FieldRow>>,
+        pub cover: RefCell<Option<Vec<u8>>>,
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for TagEditorDialog {
+        const NAME: &'static str = "AxuralisTagEditorDialog";
+        type Type = super::TagEditorDialog;
+        type ParentType = adw::Dialog;
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            CoverPicture::static_type();
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for TagEditorDialog {
+        fn constructed(&self) {
+            self.parent_constructed();
+
+            self.obj().init_widgets();
+        }
+
+        fn signals() -> &'static [Signal] {
+            static SIGNALS: Lazy<Vec<Signal>> = Lazy::new(|| vec![Signal::builder("save").build()]);
+
+            SIGNALS.as_ref()
+        }
+    }
+
+    impl WidgetImpl for TagEditorDialog {}
+    impl AdwDialogImpl for TagEditorDialog {}
+}
+
+glib::wrapper! {
+    pub struct TagEditorDialog(ObjectSubclass<imp::TagEditorDialog>)
+        @extends gtk::Widget, adw::Dialog,
+        @implements gtk::Accessible, gtk::Buildable, gtk::ConstraintTarget;
+}
+
+impl TagEditorDialog {
+    pub fn new(songs: &[Song]) -> Self {
+        let dialog: Self = glib::Object::new();
+        dialog.set_songs(songs);
+        dialog
+    }
+
+    fn init_widgets(&self) {
+        let imp = self.imp();
+
+        imp.cancel_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.close();
+            }
+        ));
+        imp.save_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                this.emit_by_name::<()>("save", &[]);
+                this.close();
+            }
+        ));
+        imp.cover_button.connect_clicked(clone!(
+            #[weak(rename_to = this)]
+            self,
+            move |_| {
+                glib::spawn_future_local(clone!(
+                    #[weak]
+                    this,
+                    async move {
+                        this.choose_cover().await;
+                    }
+                ));
+            }
+        ));
+    }
+
+    fn set_songs(&self, songs: &[Song]) {
+        let imp = self.imp();
+        imp.songs.replace(songs.to_vec());
+
+        let single = songs.len() == 1;
+        if single {
+            imp.fields_group
+                .set_description(Some(songs[0].file().parse_name().as_str()));
+        } else {
+            let description = ni18n_f(
+                // Translators: the `{}` must be left unmodified;
+                // it will be expanded to the number of songs
+                "Editing {} song; mixed values stay as they are unless you fill them in",
+                "Editing {} songs; mixed values stay as they are unless you fill them in",
+                songs.len() as u32,
+                &[&songs.len().to_string()],
+            );
+            imp.fields_group.set_description(Some(description.as_str()));
+        }
+
+        // Songs of the same album usually share the cover
+        let cover = songs[0].cover_texture();
+        let same_cover = songs
+            .iter()
+            .all(|s| s.cover_uuid() == songs[0].cover_uuid());
+        if same_cover {
+            imp.cover_picture.set_cover(cover.as_ref());
+        }
+
+        for field in TagField::ALL {
+            if !single && field.is_per_song() {
+                continue;
+            }
+
+            let initial = songs[0].tag_text(field);
+            let mixed = songs.iter().any(|s| s.tag_text(field) != initial);
+
+            let row = adw::EntryRow::builder().title(field_label(field)).build();
+            if field.is_number() {
+                row.set_input_purpose(gtk::InputPurpose::Digits);
+            }
+            if mixed {
+                let label = gtk::Label::builder()
+                    .label(i18n("Mixed"))
+                    .css_classes(["dim-label"])
+                    .build();
+                row.add_suffix(&label);
+                row.connect_changed(move |row| {
+                    label.set_visible(row.text().is_empty());
+                });
+            } else {
+                row.set_text(&initial);
+            }
+            row.connect_changed(clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_| {
+                    this.update();
+                }
+            ));
+
+            imp.fields_group.add(&row);
+            imp.field_rows.borrow_mut().push(FieldRow {
+                field,
+                row,
+                initial,
+                mixed,
+            });
+        }
+
+        self.update();
+    }
+
+    async fn choose_cover(&self) {
+        let filters = gio::ListStore::new::<gtk::FileFilter>();
+        let filter = gtk::FileFilter::new();
+        filter.set_name(Some(&i18n("Images")));
+        filter.add_mime_type("image/jpeg");
+        filter.add_mime_type("image/png");
+        filters.append(&filter);
+
+        let dialog = gtk::FileDialog::builder()
+            .accept_label(i18n("_Replace"))
+            .filters(&filters)
+            .modal(true)
+            .title(i18n("Choose Cover"))
+            .build();
+
+        let root = self.root().and_downcast::<gtk::Window>();
+        let file = match dialog.open_future(root.as_ref()).await {
+            Ok(file) => file,
+            Err(_) => return,
+        };
+
+        match file.load_contents_future().await {
+            Ok((bytes, _)) => {
+                let texture = match gdk::Texture::from_bytes(&glib::Bytes::from(&bytes[..])) {
+                    Ok(texture) => texture,
+                    Err(e) => {
+                        warn!("Unable to load cover {}: {}", file.uri(), e);
+                        return;
+                    }
+                };
+                self.imp().cover_picture.set_cover(Some(&texture));
+                self.imp().cover.replace(Some(bytes.to_vec()));
+                self.update();
+            }
+            Err(e) => warn!("Unable to read cover {}: {}", file.uri(), e),
+        }
+    }
+
+    fn update(&self) {
+        let imp = self.imp();
+
+        let mut valid = true;
+        for field_row in imp.field_rows.borrow().iter() {
+            if field_row.is_valid() {
+                field_row.row.remove_css_class("error");
+            } else {
+                field_row.row.add_css_class("error");
+                valid = false;
+            }
+        }
+
+        imp.save_button
+            .set_sensitive(valid && !self.changes().is_empty());
+    }
+
+    pub fn songs(&self) -> Vec<Song> {
+        self.imp().songs.borrow().clone()
+    }
+
+    // What to write into every song
+    pub fn changes(&self) -> TagChanges {
+        let imp = self.imp();
+
+        TagChanges {
+            fields: imp
+                .field_rows
+                .borrow()
+                .iter()
+                .filter_map(|r| {
+                    r.change()
+                        .map(|text| (r.field, r.field.values_from_text(&text)))
+                })
+                .collect(),
+            cover: imp.cover.borrow().clone().map(CoverChange::Replace),
+            artists: None,
+        }
+    }
+}
@@ -2,14 +2,20 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
 use core::cmp::Ordering;
-use std::path::PathBuf;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    path::PathBuf,
+    rc::Rc,
+};
 
 use color_thief::{get_palette, ColorFormat};
 use gtk::{gdk, gio, glib, prelude::*};
 use log::{debug, warn};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 
 use crate::{
-    audio::{Queue, Song},
+    audio::{cmp_track_order, Queue, Song},
     config::APPLICATION_ID,
 };
 
@@ -19,6 +25,24 @@ pub fn settings_manager() -> gio::Settings {
     gio::Settings::new(app_id)
 }
 
+// The folders where the user keeps their music; if none are set, we
+// fall back to the XDG music directory
+pub fn music_folders() -> Vec<gio::File> {
+    let folders: Vec<gio::File> = settings_manager()
+        .strv("music-folders")
+        .iter()
+        .map(|uri| gio::File::for_uri(uri))
+        .collect();
+
+    if folders.is_empty() {
+        glib::user_special_dir(glib::UserDirectory::Music)
+            .map(|p| vec![gio::File::for_path(p)])
+            .unwrap_or_default()
+    } else {
+        folders
+    }
+}
+
 pub fn format_remaining_time(t: i64) -> String {
     // We use an explicit LRM character so the MINUS SIGN character
     // stays in front of the remaining time even in RTL locales, instead
@@ -30,6 +54,150 @@ pub fn format_time(t: i64) -> String {
     format!("{}:{:02}", (t - (t % 60)) / 60, t % 60)
 }
 
+// Folds text for searching: case and diacritics are dropped, and the
+// compatibility forms, like full-width letters, are replaced by their
+// plain counterparts, so that "beyonce" finds "Beyoncé". This is not
+// meant for sorting, which is the job of the locale's collation
+pub fn fold_text(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        fold_char(c, &mut res);
+    }
+    res
+}
+
+// Like fold_text(), but also returns the byte offset, inside the original
+// text, of the character each folded character comes from; we need them
+// to show where a search matched
+pub fn fold_text_with_offsets(text: &str) -> (String, Vec<usize>) {
+    let mut res = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len());
+    for (offset, c) in text.char_indices() {
+        let start = res.len();
+        fold_char(c, &mut res);
+        let n_folded = res[start..].chars().count();
+        offsets.extend(std::iter::repeat(offset).take(n_folded));
+    }
+    (res, offsets)
+}
+
+fn fold_char(c: char, res: &mut String) {
+    for c in c.nfkd().filter(|c| !is_combining_mark(*c)) {
+        // A few letters do not decompose into a base letter and a mark
+        match c {
+            'ß' | 'ẞ' => res.push_str("ss"),
+            'Æ' | 'æ' => res.push_str("ae"),
+            'Œ' | 'œ' => res.push_str("oe"),
+            'Ø' | 'ø' => res.push('o'),
+            'Ł' | 'ł' => res.push('l'),
+            'Đ' | 'đ' => res.push('d'),
+            c => res.extend(c.to_lowercase()),
+        }
+    }
+}
+
+thread_local! {
+    // Songs are split all the time while sorting and searching, so we
+    // keep the separators around instead of asking the settings; the
+    // generation goes up each time they change, so that songs know when
+    // their split artists are stale
+    static ARTIST_SEPARATORS: (gio::Settings, Rc<RefCell<Vec<String>>>, Rc<Cell<u32>>) = {
+        let settings = settings_manager();
+        let separators = Rc::new(RefCell::new(read_artist_separators(&settings)));
+        let generation = Rc::new(Cell::new(0));
+        settings.connect_changed(Some("artist-separators"), {
+            let separators = Rc::clone(&separators);
+            let generation = Rc::clone(&generation);
+            move |settings, _| update_artist_separators(settings, &separators, &generation)
+        });
+        (settings, separators, generation)
+    };
+}
+
+fn update_artist_separators(
+    settings: &gio::Settings,
+    separators: &RefCell<Vec<String>>,
+    generation: &Cell<u32>,
+) {
+    let new_separators = read_artist_separators(settings);
+    if *separators.borrow() != new_separators {
+        separators.replace(new_separators);
+        generation.set(generation.get().wrapping_add(1));
+    }
+}
+
+// Reads the separators again, if they changed; the handlers of the
+// setting are not called in any particular order, so the ones which use
+// the new separators call this first
+pub fn reload_artist_separators() {
+    ARTIST_SEPARATORS.with(|(settings, separators, generation)| {
+        update_artist_separators(settings, separators, generation)
+    })
+}
+
+fn read_artist_separators(settings: &gio::Settings) -> Vec<String> {
+    settings
+        .strv("artist-separators")
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+// Runs `f` with what goes between the names of the artists in a single
+// tag value
+pub fn with_artist_separators<R>(f: impl FnOnce(&[String]) -> R) -> R {
+    ARTIST_SEPARATORS.with(|(_, separators, _)| f(&separators.borrow()))
+}
+
+pub fn artist_separators_generation() -> u32 {
+    ARTIST_SEPARATORS.with(|(_, _, generation)| generation.get())
+}
+
+// Splits the values of an artist tag into the names of the artists:
+// with " feat. " and " & " as separators, "A feat. B & C" gives "A", "B"
+// and "C". Separators match regardless of their ASCII case, and each
+// name is listed once, in the order it first appears
+pub fn split_artists<S: AsRef<str>>(values: &[S], separators: &[String]) -> Vec<String> {
+    let separators: Vec<String> = separators
+        .iter()
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_ascii_lowercase())
+        .collect();
+
+    let mut res: Vec<String> = Vec::new();
+    let mut push = |name: &str| {
+        let name = name.trim();
+        let folded = name.to_lowercase();
+        if !name.is_empty() && !res.iter().any(|n| n.to_lowercase() == folded) {
+            res.push(name.to_string());
+        }
+    };
+    for value in values {
+        // ID3v2 keeps the values of a frame in a single string, separated
+        // by a NUL character
+        for value in value.as_ref().split('\0') {
+            // ASCII lowercasing keeps the byte offsets of the original
+            let lower = value.to_ascii_lowercase();
+            let mut start = 0;
+            // The earliest separator wins, then the longest one
+            while let Some((pos, len)) = separators
+                .iter()
+                .filter_map(|s| {
+                    lower[start..]
+                        .find(s.as_str())
+                        .map(|p| (start + p, s.len()))
+                })
+                .min_by_key(|(pos, len)| (*pos, std::cmp::Reverse(*len)))
+            {
+                push(&value[start..pos]);
+                start = pos + len;
+            }
+            push(&value[start..]);
+        }
+    }
+    res
+}
+
 // The base cover size is 192px, but we need to account for HiDPI;
 // better to scale down when rendering on displays with a scaling
 // factor of 1 than having to scale up on displays with a scaling
@@ -64,13 +232,21 @@ pub fn load_cover_texture(buffer: &glib::Bytes) -> Option<gdk_pixbuf::Pixbuf> {
     }
 }
 
-pub fn cache_cover_art(uuid: &str, pixbuf: &gdk_pixbuf::Pixbuf) -> Option<PathBuf> {
+// Where we store the cover art with the given UUID
+pub fn cover_cache_path(uuid: &str) -> PathBuf {
     let mut cache_dir = glib::user_cache_dir();
     cache_dir.push("axuralis");
     cache_dir.push("covers");
-    glib::mkdir_with_parents(&cache_dir, 0o755);
-
     cache_dir.push(format!("{}.png", &uuid));
+    cache_dir
+}
+
+pub fn cache_cover_art(uuid: &str, pixbuf: &gdk_pixbuf::Pixbuf) -> Option<PathBuf> {
+    let cache_dir = cover_cache_path(uuid);
+    if let Some(parent) = cache_dir.parent() {
+        glib::mkdir_with_parents(parent, 0o755);
+    }
+
     let file = gio::File::for_path(&cache_dir);
     match file.create(gio::FileCreateFlags::NONE, gio::Cancellable::NONE) {
         Ok(stream) => {
@@ -136,38 +312,191 @@ pub fn load_palette(pixbuf: &gdk_pixbuf::Pixbuf) -> Option<Vec<gdk::RGBA>> {
     None
 }
 
-fn load_files_from_folder_internal(
-    base: &gio::File,
-    folder: &gio::File,
-    recursive: bool,
-) -> Vec<gio::File> {
-    let mut enumerator = folder
-        .enumerate_children(
-            "standard::name,standard::type",
-            gio::FileQueryInfoFlags::NOFOLLOW_SYMLINKS,
-            None::<&gio::Cancellable>,
-        )
-        .expect("Unable to enumerate");
-
-    let mut files = Vec::new();
-    while let Some(info) = enumerator.next().and_then(|s| s.ok()) {
-        let child = enumerator.child(&info);
-        if recursive && info.file_type() == gio::FileType::Directory {
-            let mut res = load_files_from_folder_internal(base, &child, recursive);
-            files.append(&mut res);
-        } else if info.file_type() == gio::FileType::Regular {
-            files.push(child.clone());
+// Playlists that shared-mime-info files under audio/*
+const PLAYLIST_MIME_TYPES: &[&str] = &[
+    "audio/x-mpegurl",
+    "audio/mpegurl",
+    "audio/x-scpls",
+    "audio/x-ms-asx",
+    "audio/x-ms-wax",
+];
+
+// Songs are the only thing we want out of a folder, but music folders
+// also hold covers, playlists, logs and cue sheets
+pub fn is_audio_content_type(content_type: &str) -> bool {
+    if PLAYLIST_MIME_TYPES
+        .iter()
+        .any(|t| gio::content_type_is_mime_type(content_type, t))
+    {
+        return false;
+    }
+
+    gio::content_type_is_mime_type(content_type, "audio/*")
+        || gio::content_type_is_mime_type(content_type, "application/ogg")
+}
+
+// Matches `text` against a shell-like glob, where `*` matches any run of
+// characters, including path separators, and `?` matches one character
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Where to resume if the characters after the last `*` do not match
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
         }
     }
 
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+// How we walk through a folder when adding it
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub recursive: bool,
+    pub follow_symlinks: bool,
+    pub skip_hidden: bool,
+    // Glob patterns matched against the full path, or URI, of each file
+    // and folder, e.g. `*/Samples/*`
+    pub exclude_patterns: Vec<String>,
+}
+
+impl ImportOptions {
+    pub fn from_settings(recursive: bool) -> Self {
+        let settings = settings_manager();
+
+        Self {
+            recursive,
+            follow_symlinks: settings.boolean("import-follow-symlinks"),
+            skip_hidden: settings.boolean("import-skip-hidden"),
+            exclude_patterns: settings
+                .strv("import-exclude-patterns")
+                .iter()
+                .map(|s| s.to_string())
+                .filter(|s| !s.trim().is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn is_excluded(&self, file: &gio::File) -> bool {
+        if self.exclude_patterns.is_empty() {
+            return false;
+        }
+
+        let name = file.parse_name();
+        self.exclude_patterns
+            .iter()
+            .any(|pattern| glob_matches(pattern, &name))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FolderContents {
+    pub files: Vec<gio::File>,
+    // The folders we could not look into, e.g. because of their permissions
+    pub unreadable: Vec<gio::File>,
+}
+
+pub fn load_files_from_folder(folder: &gio::File, options: &ImportOptions) -> FolderContents {
+    use std::time::Instant;
+
+    let now = Instant::now();
+    let mut res = FolderContents::default();
+    let mut visited = HashSet::new();
+    load_files_from_folder_internal(folder, options, &mut visited, &mut res);
+
     // gio::FileEnumerator has no guaranteed order, so we should
     // rely on the basename being formatted in a way that gives us an
     // implicit order; if anything, this will queue songs in the same
     // order in which they appear in the directory when browsing its
     // contents
-    files.sort_by(|a, b| cmp_two_files(Some(base), a, b));
+    res.files.sort_by(|a, b| cmp_two_files(Some(folder), a, b));
+    debug!(
+        "Folder enumeration: {} us (recursive: {}), total files: {}, unreadable folders: {}",
+        now.elapsed().as_micros(),
+        options.recursive,
+        res.files.len(),
+        res.unreadable.len()
+    );
+
+    res
+}
+
+fn load_files_from_folder_internal(
+    folder: &gio::File,
+    options: &ImportOptions,
+    visited: &mut HashSet<String>,
+    res: &mut FolderContents,
+) {
+    let flags = if options.follow_symlinks {
+        gio::FileQueryInfoFlags::NONE
+    } else {
+        gio::FileQueryInfoFlags::NOFOLLOW_SYMLINKS
+    };
+
+    // Following symbolic links can lead us in circles, so we keep track
+    // of the folders we already walked through
+    if let Ok(info) = folder.query_info("id::file", flags, gio::Cancellable::NONE) {
+        if let Some(id) = info.attribute_string("id::file") {
+            if !visited.insert(id.to_string()) {
+                debug!("Skipping folder '{}': already visited", folder.uri());
+                return;
+            }
+        }
+    }
+
+    let mut enumerator = match folder.enumerate_children(
+        "standard::name,standard::type,standard::is-hidden,standard::content-type",
+        flags,
+        gio::Cancellable::NONE,
+    ) {
+        Ok(enumerator) => enumerator,
+        Err(e) => {
+            warn!("Unable to enumerate '{}': {}", folder.uri(), e);
+            res.unreadable.push(folder.clone());
+            return;
+        }
+    };
+
+    while let Some(info) = enumerator.next().and_then(|s| s.ok()) {
+        if options.skip_hidden && info.is_hidden() {
+            continue;
+        }
+
+        let child = enumerator.child(&info);
+        if options.is_excluded(&child) {
+            debug!("Skipping excluded file '{}'", child.uri());
+            continue;
+        }
 
-    files
+        match info.file_type() {
+            gio::FileType::Directory if options.recursive => {
+                load_files_from_folder_internal(&child, options, visited, res);
+            }
+            gio::FileType::Regular => {
+                let is_audio = info
+                    .content_type()
+                    .is_some_and(|ct| is_audio_content_type(&ct));
+                if is_audio {
+                    res.files.push(child);
+                }
+            }
+            _ => (),
+        }
+    }
 }
 
 pub fn cmp_two_files(base: Option<&gio::File>, a: &gio::File, b: &gio::File) -> Ordering {
@@ -209,8 +538,8 @@ pub fn cmp_two_files(base: Option<&gio::File>, a: &gio::File, b: &gio::File) ->
 fn cmp_like_nautilus(filename_a: &str, filename_b: &str) -> Ordering {
     let order;
 
-    let sort_last_a = filename_a.as_bytes()[0] == b'.' || filename_a.as_bytes()[0] == b'#';
-    let sort_last_b = filename_b.as_bytes()[0] == b'.' || filename_b.as_bytes()[0] == b'#';
+    let sort_last_a = filename_a.starts_with(['.', '#']);
+    let sort_last_b = filename_b.starts_with(['.', '#']);
 
     if !sort_last_a && sort_last_b {
         order = Ordering::Less;
@@ -225,19 +554,26 @@ fn cmp_like_nautilus(filename_a: &str, filename_b: &str) -> Ordering {
     order
 }
 
-pub fn load_files_from_folder(folder: &gio::File, recursive: bool) -> Vec<gio::File> {
-    use std::time::Instant;
+// Files coming from a folder are sorted by name, but the disc and track
+// tags are a better source for the order of an album; we only use them
+// if every song in the folder has a track number, otherwise we keep the
+// original order
+pub fn sort_by_track_order(songs: &mut [Song]) {
+    let mut start = 0;
+    while start < songs.len() {
+        let folder = songs[start].file().parent().map(|p| p.uri());
+        let mut end = start + 1;
+        while end < songs.len() && songs[end].file().parent().map(|p| p.uri()) == folder {
+            end += 1;
+        }
 
-    let now = Instant::now();
-    let res = load_files_from_folder_internal(folder, folder, recursive);
-    debug!(
-        "Folder enumeration: {} us (recursive: {}), total files: {}",
-        now.elapsed().as_micros(),
-        recursive,
-        res.len()
-    );
+        let group = &mut songs[start..end];
+        if group.iter().all(|s| s.track_number().is_some()) {
+            group.sort_by(cmp_track_order);
+        }
 
-    res
+        start = end;
+    }
 }
 
 pub fn store_playlist(queue: &Queue) {
@@ -250,9 +586,7 @@ pub fn store_playlist(queue: &Queue) {
     for i in 0..model.n_items() {
         let item = model.item(i).unwrap();
         let song = item.downcast_ref::<Song>().unwrap();
-        let path = song.file().path().expect("Unknown file");
-        let path_str = path.to_string_lossy();
-        pls.set_value("playlist", &format!("File{i}"), &path_str);
+        pls.set_value("playlist", &format!("File{i}"), &song.uri());
     }
 
     let mut pls_cache = glib::user_cache_dir();
@@ -267,6 +601,16 @@ pub fn store_playlist(queue: &Queue) {
     }
 }
 
+// Playlists used to store local paths, while they now store URIs, so that
+// songs on remote locations can be restored as well
+pub fn file_for_location(location: &str) -> gio::File {
+    if glib::Uri::peek_scheme(location).is_some() {
+        gio::File::for_uri(location)
+    } else {
+        gio::File::for_path(location)
+    }
+}
+
 pub fn load_cached_songs() -> Option<Vec<gio::File>> {
     let mut pls_cache = glib::user_cache_dir();
     pls_cache.push("axuralis");
@@ -288,7 +632,7 @@ pub fn load_cached_songs() -> Option<Vec<gio::File>> {
 
     for i in 0..n_entries {
         match pls.value("playlist", &format!("File{i}")) {
-            Ok(p) => res.push(gio::File::for_path(p)),
+            Ok(p) => res.push(file_for_location(&p)),
             Err(e) => debug!("Skipping File{i} from playlist: {e}"),
         }
     }
@@ -304,3 +648,59 @@ pub fn has_cached_playlist() -> bool {
 
     pls_cache.exists()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches(
+            "*/Samples/*",
+            "/home/user/Music/Samples/kick.wav"
+        ));
+        assert!(!glob_matches("*/Samples/*", "/home/user/Music/Samples"));
+        assert!(glob_matches("*.cue", "/music/album/disc.cue"));
+        assert!(!glob_matches("*.cue", "/music/album/disc.flac"));
+        assert!(glob_matches("/music/track??.mp3", "/music/track01.mp3"));
+        assert!(!glob_matches("/music/track??.mp3", "/music/track1.mp3"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*a*b*", "xxaxxbxx"));
+        assert!(!glob_matches("*a*b", "xxbxxa"));
+    }
+
+    #[test]
+    fn test_split_artists() {
+        let separators: Vec<String> = [";", " feat. ", " & "]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            split_artists(
+                &["Daft Punk feat. Pharrell Williams & Nile Rodgers"],
+                &separators
+            ),
+            ["Daft Punk", "Pharrell Williams", "Nile Rodgers"]
+        );
+        assert_eq!(
+            split_artists(&["A FEAT. B; a", "C\0B"], &separators),
+            ["A", "B", "C"]
+        );
+        assert_eq!(
+            split_artists(&["Simon & Garfunkel"], &[]),
+            ["Simon & Garfunkel"]
+        );
+        assert_eq!(split_artists(&[" ; "], &separators), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_fold_text() {
+        assert_eq!(fold_text("Beyoncé"), "beyonce");
+        assert_eq!(fold_text("Sigur Rós"), "sigur ros");
+        assert_eq!(fold_text("Motörhead"), "motorhead");
+        assert_eq!(fold_text("Die Ärzte – Straße"), "die arzte – strasse");
+        assert_eq!(fold_text("Ｒａｄｉｏｈｅａｄ"), "radiohead");
+        assert_eq!(fold_text("Mø"), "mo");
+        assert_eq!(fold_text("坂本龍一"), "坂本龍一");
+    }
+}
@@ -3,6 +3,8 @@
 
 use std::{
     cell::{Cell, RefCell},
+    collections::{BTreeMap, HashSet},
+    path::PathBuf,
     rc::Rc,
     time::Instant,
 };
@@ -10,30 +12,58 @@ use std::{
 use adw::subclass::prelude::*;
 use glib::{clone, closure_local};
 use gtk::{gdk, gio, glib, prelude::*, CompositeTemplate};
-use log::debug;
+use log::{debug, warn};
 
 use crate::{
-    audio::{AudioPlayer, RepeatMode, ReplayGainMode, Song},
+    album_header::AlbumHeader,
+    album_sections::AlbumSectionModel,
+    audio::{
+        find_lyrics, write_rating, write_tags, AudioPlayer, Library, LyricsRequest, RatingTag,
+        RepeatMode, ReplayGainMode, ShuffleMode, SkipKind, SkipList, Song, SongData, SongLoader,
+        SortKey, TagChanges, MAX_RATING,
+    },
     config::APPLICATION_ID,
     drag_overlay::DragOverlay,
-    i18n::{i18n, i18n_k, ni18n_f, ni18n_k},
+    folder_watcher::{FolderChanges, FolderWatcher},
+    i18n::{i18n, i18n_f, i18n_k, ni18n_f, ni18n_k},
+    lyrics_view::LyricsView,
     playback_control::PlaybackControl,
     playlist_view::PlaylistView,
     queue_row::QueueRow,
     search::FuzzyFilter,
+    session::Session,
     song_cover::SongCover,
     song_details::SongDetails,
+    song_properties_dialog::SongPropertiesDialog,
     sort::FuzzySorter,
+    tag_editor_dialog::TagEditorDialog,
     utils,
     volume_control::VolumeControl,
     waveform_view::WaveformView,
 };
 
+// How many loaded songs we add to the queue at once
+const LOAD_BATCH_SIZE: usize = 200;
+
 pub enum WindowMode {
     InitialView,
     MainView,
 }
 
+// How the songs we load end up in the queue
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LoadMode {
+    // New files: songs are added as soon as they are ready, and the songs
+    // of each folder are sorted using their disc and track numbers
+    Import,
+    // The songs of a previous session: the order must match the stored
+    // one, so the songs are added all at once
+    Restore,
+    // Songs picked from the library to replace the queue: like Import,
+    // but playback starts as soon as the first song is ready
+    Play,
+}
+
 mod imp {
     use glib::{ParamSpec, ParamSpecBoolean, ParamSpecEnum, Value};
     use once_cell::sync::Lazy;
@@ -57,6 +87,10 @@ mod imp {
         #[template_child]
         pub song_details: TemplateChild<SongDetails>,
         #[template_child]
+        pub art_stack: TemplateChild<gtk::Stack>,
+        #[template_child]
+        pub lyrics_view: TemplateChild<LyricsView>,
+        #[template_child]
         pub waveform_view: TemplateChild<WaveformView>,
         #[template_child]
         pub elapsed_label: TemplateChild<gtk::Label>,
@@ -80,9 +114,24 @@ mod imp {
         pub playlist_visible: Cell<bool>,
         pub playlist_selection: Cell<bool>,
         pub playlist_search: Cell<bool>,
+        pub playlist_grouped: Cell<bool>,
+        pub lyrics_visible: Cell<bool>,
         pub replaygain_mode: Cell<ReplayGainMode>,
+        pub shuffle_mode: Cell<ShuffleMode>,
+        pub sort_key: Cell<SortKey>,
+        pub sort_descending: Cell<bool>,
 
         pub playlist_filtermodel: RefCell<Option<gio::ListModel>>,
+        pub playlist_sections: RefCell<Option<AlbumSectionModel>>,
+
+        pub pending_session: RefCell<Option<Session>>,
+        pub loaders: RefCell<Vec<gio::Cancellable>>,
+        pub loading_done: Cell<u32>,
+        pub loading_total: Cell<u32>,
+        pub save_session_id: RefCell<Option<glib::SourceId>>,
+        pub folder_watcher: RefCell<Option<FolderWatcher>>,
+        // What the last tag edit changed, so that we can put it back
+        pub tags_undo: RefCell<Vec<(Song, TagChanges)>>,
 
         pub notify_playing_id: RefCell<Option<glib::SignalHandlerId>>,
         pub notify_position_id: RefCell<Option<glib::SignalHandlerId>>,
@@ -197,6 +246,65 @@ mod imp {
                 debug!("Window::win.copy()");
                 win.copy_song();
             });
+            klass.install_action("win.edit-tags", None, move |win, _, _| {
+                debug!("Window::win.edit-tags()");
+                win.edit_tags();
+            });
+            klass.install_action("win.song-properties", None, move |win, _, _| {
+                debug!("Window::win.song-properties()");
+                win.show_song_properties();
+            });
+            klass.install_action_async("win.undo-tags", None, |win, _, _| async move {
+                debug!("Window::win.undo-tags()");
+                let edits = win.imp().tags_undo.take();
+                win.write_tags(edits, false).await;
+            });
+            klass.install_action_async(
+                "win.rate",
+                Some(glib::VariantTy::INT32),
+                |win, _, param| async move {
+                    if let Some(rating) = param.and_then(i32::from_variant) {
+                        debug!("Window::win.rate({})", rating);
+                        let rating = rating.clamp(0, MAX_RATING as i32) as u32;
+                        win.rate_songs(win.selected_or_current_songs(), rating)
+                            .await;
+                    }
+                },
+            );
+            klass.install_action(
+                "queue.skip-selected",
+                Some(glib::VariantTy::STRING),
+                move |win, _, param| {
+                    if let Some(kind) = param
+                        .and_then(String::from_variant)
+                        .and_then(|s| SkipKind::from_nick(&s))
+                    {
+                        debug!("Window::queue.skip-selected({:?})", kind);
+                        win.skip_selected(kind);
+                    }
+                },
+            );
+            klass.install_action(
+                "library.play",
+                Some(glib::VariantTy::STRING_ARRAY),
+                move |win, _, param| {
+                    if let Some(uris) = param.and_then(Vec::<String>::from_variant) {
+                        debug!("Window::library.play({} songs)", uris.len());
+                        win.clear_queue();
+                        win.queue_uris(&uris, LoadMode::Play);
+                    }
+                },
+            );
+            klass.install_action(
+                "library.enqueue",
+                Some(glib::VariantTy::STRING_ARRAY),
+                move |win, _, param| {
+                    if let Some(uris) = param.and_then(Vec::<String>::from_variant) {
+                        debug!("Window::library.enqueue({} songs)", uris.len());
+                        win.queue_uris(&uris, LoadMode::Import);
+                    }
+                },
+            );
             klass.install_action("queue.clear", None, move |win, _, _| {
                 debug!("Window::queue.clear()");
                 win.clear_queue();
@@ -205,7 +313,12 @@ mod imp {
             klass.install_property_action("queue.shuffle", "playlist-shuffled");
             klass.install_property_action("queue.select", "playlist-selection");
             klass.install_property_action("queue.search", "playlist-search");
+            klass.install_property_action("queue.group-by-album", "playlist-grouped");
+            klass.install_property_action("win.show-lyrics", "lyrics-visible");
             klass.install_property_action("win.replaygain", "replaygain-mode");
+            klass.install_property_action("queue.shuffle-mode", "shuffle-mode");
+            klass.install_property_action("queue.sort-key", "sort-key");
+            klass.install_property_action("queue.sort-descending", "sort-descending");
 
             klass.install_action(
                 "win.skip-to",
@@ -228,6 +341,8 @@ mod imp {
         fn new() -> Self {
             Self {
                 song_details: TemplateChild::default(),
+                art_stack: TemplateChild::default(),
+                lyrics_view: TemplateChild::default(),
                 song_cover: TemplateChild::default(),
                 split_view: TemplateChild::default(),
                 toast_overlay: TemplateChild::default(),
@@ -245,8 +360,21 @@ mod imp {
                 playlist_visible: Cell::new(true),
                 playlist_selection: Cell::new(false),
                 playlist_search: Cell::new(false),
+                playlist_grouped: Cell::new(false),
+                lyrics_visible: Cell::new(false),
                 playlist_filtermodel: RefCell::default(),
+                playlist_sections: RefCell::default(),
+                pending_session: RefCell::default(),
+                loaders: RefCell::default(),
+                loading_done: Cell::new(0),
+                loading_total: Cell::new(0),
+                save_session_id: RefCell::default(),
+                folder_watcher: RefCell::default(),
+                tags_undo: RefCell::default(),
                 replaygain_mode: Cell::new(ReplayGainMode::default()),
+                shuffle_mode: Cell::new(ShuffleMode::default()),
+                sort_key: Cell::new(SortKey::default()),
+                sort_descending: Cell::new(false),
                 provider: gtk::CssProvider::new(),
                 settings: utils::settings_manager(),
                 notify_playing_id: RefCell::new(None),
@@ -276,7 +404,12 @@ mod imp {
                     ParamSpecBoolean::builder("playlist-visible").build(),
                     ParamSpecBoolean::builder("playlist-selection").build(),
                     ParamSpecBoolean::builder("playlist-search").build(),
+                    ParamSpecBoolean::builder("playlist-grouped").build(),
+                    ParamSpecBoolean::builder("lyrics-visible").build(),
                     ParamSpecEnum::builder::<ReplayGainMode>("replaygain-mode").build(),
+                    ParamSpecEnum::builder::<ShuffleMode>("shuffle-mode").build(),
+                    ParamSpecEnum::builder::<SortKey>("sort-key").build(),
+                    ParamSpecBoolean::builder("sort-descending").build(),
                 ]
             });
             PROPERTIES.as_ref()
@@ -289,7 +422,12 @@ mod imp {
                 "playlist-visible" => obj.set_playlist_visible(value.get::<bool>().unwrap()),
                 "playlist-selection" => obj.set_playlist_selection(value.get::<bool>().unwrap()),
                 "playlist-search" => obj.set_playlist_search(value.get::<bool>().unwrap()),
+                "playlist-grouped" => obj.set_playlist_grouped(value.get::<bool>().unwrap()),
+                "lyrics-visible" => obj.set_lyrics_visible(value.get::<bool>().unwrap()),
                 "replaygain-mode" => obj.set_replaygain(value.get::<ReplayGainMode>().unwrap()),
+                "shuffle-mode" => obj.set_shuffle_mode(value.get::<ShuffleMode>().unwrap()),
+                "sort-key" => obj.set_sort_key(value.get::<SortKey>().unwrap()),
+                "sort-descending" => obj.set_sort_descending(value.get::<bool>().unwrap()),
                 _ => unimplemented!(),
             }
         }
@@ -301,7 +439,12 @@ mod imp {
                 "playlist-visible" => obj.playlist_visible().to_value(),
                 "playlist-selection" => obj.playlist_selection().to_value(),
                 "playlist-search" => obj.playlist_search().to_value(),
+                "playlist-grouped" => obj.playlist_grouped().to_value(),
+                "lyrics-visible" => obj.lyrics_visible().to_value(),
                 "replaygain-mode" => obj.replaygain().to_value(),
+                "shuffle-mode" => obj.shuffle_mode().to_value(),
+                "sort-key" => obj.sort_key().to_value(),
+                "sort-descending" => obj.sort_descending().to_value(),
                 _ => unimplemented!(),
             }
         }
@@ -330,6 +473,7 @@ impl Window {
         win.setup_playlist();
         win.setup_drop_target();
         win.setup_provider();
+        win.setup_folder_watcher();
         win.bind_state();
         win.bind_queue();
         win.connect_signals();
@@ -339,6 +483,12 @@ impl Window {
         win
     }
 
+    fn library(&self) -> Option<Library> {
+        self.application()
+            .and_downcast::<crate::application::Application>()
+            .map(|app| app.library())
+    }
+
     fn player(&self) -> Option<Rc<AudioPlayer>> {
         if let Some(app) = self.application() {
             let player = app
@@ -366,7 +516,23 @@ impl Window {
                     .set_boolean("enable-recoloring", enable_recoloring)
                     .expect("Unable to store setting");
             })
-            .build()])
+            .build()]);
+
+        let radio_mode = self.imp().settings.boolean("radio-mode");
+        self.add_action_entries([gio::ActionEntry::builder("radio-mode")
+            .state(radio_mode.to_variant())
+            .activate(|this: &Window, action, _| {
+                let state = action.state().unwrap();
+                let action_state: bool = state.get().unwrap();
+                let radio_mode = !action_state;
+                action.set_state(&radio_mode.to_variant());
+
+                this.imp()
+                    .settings
+                    .set_boolean("radio-mode", radio_mode)
+                    .expect("Unable to store setting");
+            })
+            .build()]);
     }
 
     fn setup_waveform(&self) {
@@ -412,6 +578,7 @@ impl Window {
     }
 
     fn clear_queue(&self) {
+        self.cancel_loading();
         if let Some(p) = self.player() {
             p.clear_queue();
         }
@@ -448,6 +615,7 @@ impl Window {
                 }
             }
 
+            self.queue_save_session();
             self.notify("playlist-shuffled");
         }
     }
@@ -480,6 +648,131 @@ impl Window {
         self.imp().playlist_search.get()
     }
 
+    fn playlist_grouped(&self) -> bool {
+        self.imp().playlist_grouped.get()
+    }
+
+    fn set_playlist_grouped(&self, grouped: bool) {
+        let imp = self.imp();
+
+        if grouped != imp.playlist_grouped.replace(grouped) {
+            let queue_view = imp.playlist_view.queue_view();
+            if grouped {
+                queue_view.set_header_factory(Some(&self.album_header_factory()));
+            } else {
+                queue_view.set_header_factory(gtk::ListItemFactory::NONE);
+                if let Some(ref sections) = *imp.playlist_sections.borrow() {
+                    sections.expand_all();
+                }
+            }
+
+            imp.settings
+                .set_boolean("group-by-album", grouped)
+                .expect("Unable to store setting");
+
+            self.notify("playlist-grouped");
+        }
+    }
+
+    fn album_header_factory(&self) -> gtk::ListItemFactory {
+        let factory = gtk::SignalListItemFactory::new();
+        factory.connect_setup(clone!(
+            #[weak(rename_to = win)]
+            self,
+            move |_, item| {
+                let list_header = item.downcast_ref::<gtk::ListHeader>().unwrap();
+                let header = AlbumHeader::new();
+                list_header.set_child(Some(&header));
+
+                header.connect_closure(
+                    "play",
+                    false,
+                    closure_local!(
+                        #[watch(rename_to = win)]
+                        win,
+                        #[weak]
+                        list_header,
+                        move |_header: AlbumHeader| {
+                            win.play_album_at(list_header.start());
+                        }
+                    ),
+                );
+                header.connect_closure(
+                    "toggle-collapsed",
+                    false,
+                    closure_local!(
+                        #[watch(rename_to = win)]
+                        win,
+                        #[weak]
+                        list_header,
+                        move |header: AlbumHeader| {
+                            if let Some(ref sections) = *win.imp().playlist_sections.borrow() {
+                                sections.toggle_collapsed(list_header.start());
+                                header.set_collapsed(sections.is_collapsed(list_header.start()));
+                            }
+                        }
+                    ),
+                );
+            }
+        ));
+        factory.connect_bind(clone!(
+            #[weak(rename_to = win)]
+            self,
+            move |_, item| {
+                let list_header = item.downcast_ref::<gtk::ListHeader>().unwrap();
+                let header = list_header.child().and_downcast::<AlbumHeader>().unwrap();
+                if let Some(song) = list_header.item().and_downcast::<Song>() {
+                    win.bind_album_header(&header, &song, list_header.start());
+                }
+            }
+        ));
+
+        factory.upcast::<gtk::ListItemFactory>()
+    }
+
+    fn bind_album_header(&self, header: &AlbumHeader, song: &Song, start: u32) {
+        let mut duration = 0;
+        let mut collapsed = false;
+        if let Some(ref sections) = *self.imp().playlist_sections.borrow() {
+            duration = sections
+                .section_songs(start)
+                .iter()
+                .map(|s| s.duration())
+                .sum();
+            collapsed = sections.is_collapsed(start);
+        }
+
+        let mut details = vec![song.album_artist().unwrap_or_else(|| song.artist())];
+        if let Some(year) = song.year() {
+            details.push(year.to_string());
+        }
+        details.push(utils::format_time(duration as i64));
+
+        header.set_album(&song.album());
+        header.set_details(&details.join(" · "));
+        header.set_cover(song.cover_texture());
+        header.set_collapsed(collapsed);
+    }
+
+    // Plays the album whose first song is at the given position of the
+    // visible playlist
+    fn play_album_at(&self, position: u32) {
+        let song = self
+            .imp()
+            .playlist_sections
+            .borrow()
+            .as_ref()
+            .and_then(|s| s.item(position))
+            .and_downcast::<Song>();
+
+        if let Some(player) = self.player() {
+            if let Some(pos) = song.and_then(|s| player.queue().song_position(&s)) {
+                player.skip_to(pos);
+                player.play();
+            }
+        }
+    }
+
     fn set_playlist_search(&self, search: bool) {
         let imp = self.imp();
 
@@ -490,135 +783,321 @@ impl Window {
     }
 
     fn restore_playlist(&self) {
-        if let Some(songs) = utils::load_cached_songs() {
-            self.queue_songs(songs);
+        if let Some(session) = Session::load() {
+            let files = session.files.clone();
+            self.imp().pending_session.replace(Some(session));
+            self.queue_songs(files, LoadMode::Restore);
+        } else if let Some(songs) = utils::load_cached_songs() {
+            self.queue_songs(songs, LoadMode::Restore);
         }
     }
 
-    fn queue_songs(&self, queue: Vec<gio::File>) {
-        if queue.is_empty() {
+    // Coalesce bursts of changes into a single write of the session file
+    fn queue_save_session(&self) {
+        if let Some(id) = self.imp().save_session_id.take() {
+            id.remove();
+        }
+
+        let id = glib::timeout_add_seconds_local_once(
+            1,
+            clone!(
+                #[weak(rename_to = win)]
+                self,
+                move || {
+                    win.imp().save_session_id.replace(None);
+                    win.save_session();
+                }
+            ),
+        );
+        self.imp().save_session_id.replace(Some(id));
+    }
+
+    fn save_session(&self) {
+        if let Some(id) = self.imp().save_session_id.take() {
+            id.remove();
+        }
+
+        // Do not clobber the stored session while we are restoring it
+        if self.imp().pending_session.borrow().is_some() {
+            return;
+        }
+
+        if let Some(player) = self.player() {
+            if !player.queue().is_empty() {
+                Session::capture(&player).save();
+            }
+        }
+    }
+
+    fn queue_uris(&self, uris: &[String], mode: LoadMode) {
+        let files = uris.iter().map(|uri| gio::File::for_uri(uri)).collect();
+        self.queue_songs(files, mode);
+    }
+
+    fn queue_songs(&self, files: Vec<gio::File>, mode: LoadMode) {
+        if files.is_empty() {
             self.add_toast(i18n("No available song found"));
             return;
         }
 
         self.switch_mode(WindowMode::MainView);
 
-        // Disable actions on the queue; loading is "atomic"
-        self.action_set_enabled("queue.add-song", false);
-        self.action_set_enabled("queue.add-folder", false);
-        self.action_set_enabled("queue.clear", false);
+        // Tags are read on worker threads, so the queue stays usable, and
+        // more songs can be added, while we load
+        let loader = SongLoader::new(files);
+        self.begin_loading(&loader);
 
-        self.imp().playlist_view.begin_loading();
+        glib::MainContext::default().spawn_local(clone!(
+            #[weak(rename_to = win)]
+            self,
+            async move {
+                win.load_songs(loader, mode).await;
+            }
+        ));
+    }
 
+    // Adds loaded songs to the end of the queue, with the songs of each
+    // folder in the order of their tracks; returns them in that order
+    fn queue_loaded_songs(
+        &self,
+        mut songs: Vec<Song>,
+        mode: LoadMode,
+        was_empty: bool,
+    ) -> Vec<Song> {
+        let player = match self.player() {
+            Some(player) => player,
+            None => return vec![],
+        };
+        if songs.is_empty() {
+            return songs;
+        }
+
+        utils::sort_by_track_order(&mut songs);
+
+        let queue = player.queue();
+        let started = !queue.is_empty();
+        queue.add_songs(&songs);
+        if was_empty && !started {
+            player.skip_to(0);
+            if mode == LoadMode::Play {
+                player.play();
+            }
+        }
+
+        songs
+    }
+
+    async fn load_songs(&self, loader: SongLoader, mode: LoadMode) {
         // Begin the trace
         let now = Instant::now();
 
-        // Turn the list of files into songs one at a time into the main loop
-        let n_files = queue.len() as u32;
-
-        let mut files = queue.into_iter();
-        let mut songs = Vec::new();
-        let mut cur_file: u32 = 0;
+        let was_empty = self.player().map_or(true, |p| p.queue().is_empty());
+        let mut songs: Vec<Song> = Vec::new();
+        let mut uris: HashSet<String> = HashSet::new();
         let mut duplicates: u32 = 0;
 
-        glib::idle_add_local(clone!(
-            #[weak(rename_to = win)]
-            self,
-            #[upgrade_or]
-            glib::ControlFlow::Break,
-            move || {
-                files
-                    .next()
-                    .map(|f| {
-                        win.imp().playlist_view.update_loading(cur_file, n_files);
-                        if let Ok(s) = Song::from_uri(f.uri().as_str()) {
-                            if let Some(player) = win.player() {
-                                let queue = player.queue();
-                                if queue.contains(&s) {
-                                    duplicates += 1;
-                                } else {
-                                    songs.push(s);
-                                    cur_file += 1;
-                                }
-                            }
-                        }
-                    })
-                    .map(|_| glib::ControlFlow::Continue)
-                    .unwrap_or_else(|| {
-                        debug!(
-                            "Total loading time for {} files: {} ms",
-                            n_files,
-                            now.elapsed().as_millis()
-                        );
-
-                        // Re-enable the actions
-                        win.action_set_enabled("queue.add-song", true);
-                        win.action_set_enabled("queue.add-folder", true);
-                        win.action_set_enabled("queue.clear", true);
-
-                        if songs.is_empty() {
-                            if duplicates == 0 {
-                                win.add_toast(i18n("No songs found"));
-                            }
-                        } else if let Some(player) = win.player() {
-                            let queue = player.queue();
-                            let was_empty = queue.is_empty();
+        // The workers do not finish in order, so we hold on to each song
+        // until the files before it are loaded; `ready` follows the order
+        // of the files, and `pending` holds what came back too early
+        let mut pending: BTreeMap<usize, Option<Song>> = BTreeMap::new();
+        let mut ready: Vec<Song> = Vec::new();
+        let mut next_pos = 0;
+
+        loop {
+            let batch = loader.next_batch(LOAD_BATCH_SIZE).await;
+            if batch.is_empty() || loader.cancellable().is_cancelled() {
+                break;
+            }
 
-                            win.imp().playlist_view.end_loading();
+            self.update_loading(batch.len() as u32);
 
-                            // Bulk add to avoid hammering the UI with list model updates
-                            queue.add_songs(&songs);
+            let queue = match self.player() {
+                Some(player) => player.queue(),
+                None => break,
+            };
 
-                            // Store the current state of the playlist
-                            utils::store_playlist(queue);
+            for (pos, data) in batch {
+                let song = Song::from_data(data).ok().filter(|s| {
+                    let duplicate = !uris.insert(s.uri()) || queue.contains(s);
+                    if duplicate {
+                        duplicates += 1;
+                    }
+                    !duplicate
+                });
+                pending.insert(pos, song);
+            }
+            while let Some(song) = pending.remove(&next_pos) {
+                ready.extend(song);
+                next_pos += 1;
+            }
 
-                            debug!(
-                                "Queue was empty: {}, new size: {}",
-                                was_empty,
-                                queue.n_songs()
-                            );
-                            if was_empty {
-                                player.skip_to(0);
-                            }
+            // New songs show up in the queue as soon as they are ready;
+            // the last folder may still be loading, and its songs need to
+            // be sorted together
+            if mode != LoadMode::Restore && next_pos < loader.n_files() as usize {
+                let last_folder = ready
+                    .last()
+                    .and_then(|s| s.file().parent())
+                    .map(|p| p.uri());
+                let n_complete = ready
+                    .iter()
+                    .rposition(|s| s.file().parent().map(|p| p.uri()) != last_folder)
+                    .map_or(0, |i| i + 1);
+                let complete: Vec<Song> = ready.drain(..n_complete).collect();
+                songs.extend(self.queue_loaded_songs(complete, mode, was_empty));
+            }
+        }
 
-                            // Allow jumping to the song we just added
-                            if songs.len() == 1 {
-                                // If we added a single song, and the queue was empty, we
-                                // dispense with the pleasantries and we start playing
-                                // immediately; otherwise, we let the user choose whether
-                                // to jump to the newly added song
-                                if was_empty {
-                                    player.play();
-                                } else {
-                                    win.add_skip_to_toast(
-                                        i18n("Added a new song"),
-                                        i18n("Play"),
-                                        queue.n_songs() - 1,
-                                    );
-                                }
-                            } else {
-                                let msg = ni18n_f(
-                                    // Translators: the `{}` must be left unmodified;
-                                    // it will be expanded to the number of songs added
-                                    // to the playlist
-                                    "Added one song",
-                                    "Added {} songs",
-                                    songs.len() as u32,
-                                    &[&songs.len().to_string()],
-                                );
-
-                                win.add_toast(msg);
-                            }
-                        }
+        // Whatever is left, including the songs after a file we never got
+        // because the load was cancelled
+        ready.extend(pending.into_values().flatten());
+        if mode == LoadMode::Restore {
+            songs.extend(ready);
+        } else {
+            songs.extend(self.queue_loaded_songs(ready, mode, was_empty));
+        }
 
-                        glib::ControlFlow::Break
-                    })
+        debug!(
+            "Total loading time for {} files: {} ms",
+            loader.n_files(),
+            now.elapsed().as_millis()
+        );
+
+        let cancelled = loader.cancellable().is_cancelled();
+        self.end_loading(&loader);
+
+        let player = match self.player() {
+            Some(player) => player,
+            None => return,
+        };
+        let queue = player.queue();
+
+        if songs.is_empty() {
+            self.imp().pending_session.replace(None);
+            if cancelled {
+                self.add_toast(i18n("Stopped loading songs"));
+            } else if duplicates == 0 {
+                self.add_toast(i18n("No songs found"));
             }
-        ));
+            return;
+        }
+
+        match mode {
+            LoadMode::Import | LoadMode::Play => {
+                // The songs were added in batches, as they were loaded;
+                // sorting by date added should follow the files instead
+                let added = glib::real_time();
+                for (i, song) in songs.iter().enumerate() {
+                    song.set_added(added + i as i64);
+                }
+            }
+            LoadMode::Restore => {
+                // Bulk add to avoid hammering the UI with list model updates
+                let was_empty = queue.is_empty();
+                queue.add_songs(&songs);
+
+                // Restoring a session resumes playback where it stopped,
+                // paused, instead of starting over
+                if let Some(session) = self.imp().pending_session.take() {
+                    utils::store_playlist(queue);
+                    if was_empty {
+                        player.restore_session(&session);
+                        self.set_playlist_shuffled(queue.is_shuffled());
+                    }
+                    self.save_session();
+                    return;
+                }
+
+                if was_empty {
+                    player.skip_to(0);
+                }
+            }
+        }
+
+        // Keep the queue sorted; a shuffled queue keeps its order
+        queue.sort(self.sort_key(), self.sort_descending());
+
+        // Store the current state of the playlist
+        utils::store_playlist(queue);
+
+        debug!(
+            "Queue was empty: {}, new size: {}",
+            was_empty,
+            queue.n_songs()
+        );
+
+        // Allow jumping to the song we just added
+        if songs.len() == 1 {
+            // If we added a single song, and the queue was empty, we
+            // dispense with the pleasantries and we start playing
+            // immediately; otherwise, we let the user choose whether
+            // to jump to the newly added song
+            if was_empty {
+                player.play();
+            } else {
+                self.add_skip_to_toast(
+                    i18n("Added a new song"),
+                    i18n("Play"),
+                    queue.song_position(&songs[0]).unwrap_or(0),
+                );
+            }
+        } else {
+            let msg = ni18n_f(
+                // Translators: the `{}` must be left unmodified;
+                // it will be expanded to the number of songs added
+                // to the playlist
+                "Added one song",
+                "Added {} songs",
+                songs.len() as u32,
+                &[&songs.len().to_string()],
+            );
+
+            self.add_toast(msg);
+        }
+    }
+
+    fn begin_loading(&self, loader: &SongLoader) {
+        let imp = self.imp();
+        if imp.loaders.borrow().is_empty() {
+            imp.loading_done.set(0);
+            imp.loading_total.set(0);
+            imp.playlist_view.begin_loading();
+        }
+
+        imp.loaders.borrow_mut().push(loader.cancellable().clone());
+        imp.loading_total
+            .set(imp.loading_total.get() + loader.n_files());
+        imp.playlist_view
+            .update_loading(imp.loading_done.get(), imp.loading_total.get());
+    }
+
+    fn update_loading(&self, n_loaded: u32) {
+        let imp = self.imp();
+        imp.loading_done.set(imp.loading_done.get() + n_loaded);
+        imp.playlist_view
+            .update_loading(imp.loading_done.get(), imp.loading_total.get());
+    }
+
+    fn end_loading(&self, loader: &SongLoader) {
+        let imp = self.imp();
+        imp.loaders
+            .borrow_mut()
+            .retain(|c| c != loader.cancellable());
+        if imp.loaders.borrow().is_empty() {
+            imp.playlist_view.end_loading();
+        }
+    }
+
+    fn cancel_loading(&self) {
+        for cancellable in self.imp().loaders.borrow().iter() {
+            cancellable.cancel();
+        }
     }
 
     fn add_files_to_queue(&self, model: &gio::ListModel) {
         let mut queue: Vec<gio::File> = vec![];
+        let mut unreadable: Vec<gio::File> = vec![];
+        let options = utils::ImportOptions::from_settings(true);
 
         for pos in 0..model.n_items() {
             let file = model.item(pos).unwrap().downcast::<gio::File>().unwrap();
@@ -631,7 +1110,7 @@ impl Window {
                 match info.file_type() {
                     gio::FileType::Regular => {
                         if let Some(content_type) = info.content_type() {
-                            if gio::content_type_is_mime_type(&content_type, "audio/*") {
+                            if utils::is_audio_content_type(&content_type) {
                                 debug!("Adding file '{}' to the queue", file.uri());
                                 queue.push(file);
                             }
@@ -639,15 +1118,36 @@ impl Window {
                     }
                     gio::FileType::Directory => {
                         debug!("Adding folder '{}' to the queue", file.uri());
-                        let files = utils::load_files_from_folder(&file, true);
-                        queue.extend(files);
+                        let mut contents = utils::load_files_from_folder(&file, &options);
+                        queue.append(&mut contents.files);
+                        unreadable.append(&mut contents.unreadable);
                     }
                     _ => (),
                 }
             }
         }
 
-        self.queue_songs(queue);
+        // Unreadable folders should not stop us from adding everything
+        // else, but the user should know that some songs are missing
+        if let Some(folder) = unreadable.first() {
+            let msg = if unreadable.len() == 1 {
+                // Translators: the `{}` must be left unmodified; it will
+                // be expanded to the location of the folder
+                i18n_f("Unable to read “{}”", &[&folder.parse_name()])
+            } else {
+                ni18n_f(
+                    // Translators: the `{}` must be left unmodified; it will
+                    // be expanded to the number of folders
+                    "Unable to read {} folder",
+                    "Unable to read {} folders",
+                    unreadable.len() as u32,
+                    &[&unreadable.len().to_string()],
+                )
+            };
+            self.add_toast(msg);
+        }
+
+        self.queue_songs(queue, LoadMode::Import);
     }
 
     // Bind the PlayerState to the UI
@@ -666,6 +1166,7 @@ impl Window {
                     move |_, _| {
                         win.set_playlist_selection(false);
                         win.update_play_button();
+                        win.queue_save_session();
                     }
                 ),
             );
@@ -680,6 +1181,7 @@ impl Window {
                     self,
                     move |_, _| {
                         win.update_position_labels();
+                        win.update_lyrics_position();
                     }
                 ),
             );
@@ -694,6 +1196,7 @@ impl Window {
                     self,
                     move |_, _| {
                         win.update_song();
+                        win.queue_save_session();
                     }
                 ),
             );
@@ -726,6 +1229,30 @@ impl Window {
                 .bind_property("album", &imp.song_details.get().album_label(), "label")
                 .sync_create()
                 .build();
+            state
+                .bind_property("rating", &imp.song_details.get().rating_bar(), "rating")
+                .sync_create()
+                .build();
+            imp.song_details.get().rating_bar().connect_local(
+                "rate",
+                false,
+                clone!(
+                    #[weak(rename_to = win)]
+                    self,
+                    #[upgrade_or]
+                    None,
+                    move |args| {
+                        let rating = args[1].get::<u32>().unwrap();
+                        let song = win.player().and_then(|p| p.state().current_song());
+                        if let Some(song) = song {
+                            glib::spawn_future_local(async move {
+                                win.rate_songs(vec![song], rating).await;
+                            });
+                        }
+                        None
+                    }
+                ),
+            );
             state
                 .bind_property(
                     "volume",
@@ -787,6 +1314,7 @@ impl Window {
                         }
 
                         win.update_playlist_time();
+                        win.queue_save_session();
                     }
                 ),
             );
@@ -801,6 +1329,7 @@ impl Window {
                         win.imp()
                             .playback_control
                             .set_repeat_mode(queue.repeat_mode());
+                        win.queue_save_session();
                     }
                 ),
             );
@@ -813,8 +1342,10 @@ impl Window {
                     move |queue, _| {
                         if queue.is_last_song() {
                             match queue.repeat_mode() {
+                                // The radio can add more songs at the end
                                 RepeatMode::Consecutive => {
-                                    win.action_set_enabled("win.next", false);
+                                    let radio = win.imp().settings.boolean("radio-mode");
+                                    win.action_set_enabled("win.next", radio);
                                 }
                                 _ => {
                                     win.action_set_enabled("win.next", true);
@@ -890,6 +1421,21 @@ impl Window {
             ),
         );
 
+        self.imp().lyrics_view.connect_closure(
+            "seek",
+            false,
+            closure_local!(
+                #[watch(rename_to = win)]
+                self,
+                move |_lv: LyricsView, time: u64| {
+                    if let Some(player) = win.player() {
+                        player.seek_position_abs(time);
+                        player.play();
+                    }
+                }
+            ),
+        );
+
         self.imp()
             .playback_control
             .volume_control()
@@ -904,6 +1450,7 @@ impl Window {
                         if let Some(p) = win.player() {
                             p.set_volume(volume);
                         }
+                        win.queue_save_session();
                     }
                 ),
             );
@@ -933,6 +1480,17 @@ impl Window {
                 }
             ));
 
+        self.imp()
+            .playlist_view
+            .loading_cancel_button()
+            .connect_clicked(clone!(
+                #[weak(rename_to = win)]
+                self,
+                move |_| {
+                    win.cancel_loading();
+                }
+            ));
+
         self.imp()
             .playlist_view
             .queue_remove_button()
@@ -942,19 +1500,12 @@ impl Window {
                 move |_| {
                     if let Some(player) = win.player() {
                         let queue = player.queue();
-                        let mut remove_songs: Vec<Song> = Vec::new();
                         // Collect all songs to be removed first, since we can't
                         // remove objects from the model while we're iterating it
-                        for idx in 0..queue.n_songs() {
-                            let song = queue.song_at(idx).unwrap();
-                            if song.selected() {
-                                remove_songs.push(song);
-                            }
-                        }
+                        let remove_songs: Vec<Song> =
+                            queue.songs().into_iter().filter(|s| s.selected()).collect();
 
-                        for song in remove_songs {
-                            win.remove_song(&song);
-                        }
+                        win.remove_songs(&remove_songs);
 
                         // Store the current state of the playlist
                         utils::store_playlist(queue);
@@ -995,6 +1546,66 @@ impl Window {
         );
         let _dummy = self.imp().settings.boolean("enable-recoloring");
 
+        self.imp().settings.connect_changed(
+            Some("radio-mode"),
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |settings, _| {
+                    let radio_mode = settings.boolean("radio-mode");
+                    debug!("GSettings:radio-mode: {radio_mode}");
+                    if let Some(player) = this.player() {
+                        player.set_radio_enabled(radio_mode);
+                        if player.queue().is_last_song() {
+                            let repeat_mode = player.queue().repeat_mode();
+                            this.action_set_enabled(
+                                "win.next",
+                                radio_mode || repeat_mode != RepeatMode::Consecutive,
+                            );
+                        }
+                    }
+                }
+            ),
+        );
+        let _dummy = self.imp().settings.boolean("radio-mode");
+
+        for kind in [SkipKind::Song, SkipKind::Artist, SkipKind::Album] {
+            self.imp().settings.connect_changed(
+                Some(kind.settings_key()),
+                clone!(
+                    #[weak(rename_to = this)]
+                    self,
+                    move |_, key| {
+                        debug!("GSettings:{key} changed");
+                        if let Some(player) = this.player() {
+                            player.queue().refresh_skip_list();
+                        }
+                    }
+                ),
+            );
+            let _dummy = self.imp().settings.strv(kind.settings_key());
+        }
+
+        self.imp().settings.connect_changed(
+            Some("artist-separators"),
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_, key| {
+                    debug!("GSettings:{key} changed");
+                    utils::reload_artist_separators();
+                    if let Some(player) = this.player() {
+                        let queue = player.queue();
+                        for song in queue.songs() {
+                            song.refresh_artists();
+                        }
+                        queue.refresh_skip_list();
+                    }
+                }
+            ),
+        );
+        let _dummy = self.imp().settings.strv("artist-separators");
+
         self.connect_close_request(move |window| {
             debug!("Saving window state");
             let width = window.default_size().0;
@@ -1008,6 +1619,8 @@ impl Window {
                 .set_int("window-height", height)
                 .expect("Unable to stop window-height");
 
+            window.save_session();
+
             window.unbind_queue();
             window.unbind_state();
             window.unbind_waveform();
@@ -1027,7 +1640,9 @@ impl Window {
             let queue = player.queue();
             self.action_set_enabled("win.play", !queue.is_empty());
             self.action_set_enabled("win.previous", !queue.is_empty());
-            self.action_set_enabled("win.next", !queue.is_last_song());
+            let radio_mode = self.imp().settings.boolean("radio-mode");
+            player.set_radio_enabled(radio_mode);
+            self.action_set_enabled("win.next", radio_mode || !queue.is_last_song());
 
             self.action_set_enabled("queue.toggle", !queue.is_empty());
             self.action_set_enabled("queue.shuffle", queue.n_songs() > 1);
@@ -1045,12 +1660,22 @@ impl Window {
                 .set_repeat_mode(queue.repeat_mode());
             self.set_playlist_shuffled(queue.is_shuffled());
 
+            let shuffle_mode = self.imp().settings.enum_("shuffle-mode").into();
+            self.set_shuffle_mode(shuffle_mode);
+            queue.set_shuffle_mode(shuffle_mode);
+            self.imp().playback_control.set_shuffle_mode(shuffle_mode);
+
+            let sort_key = self.imp().settings.enum_("sort-key").into();
+            self.set_sort_key(sort_key);
+            self.set_sort_descending(self.imp().settings.boolean("sort-descending"));
+            self.set_playlist_grouped(self.imp().settings.boolean("group-by-album"));
+
             // Manually update the icon on the initial empty state
             // to avoid generating the UI definition file at build
             // time
             self.imp().status_page.set_icon_name(Some(APPLICATION_ID));
 
-            if utils::has_cached_playlist() {
+            if Session::exists() || utils::has_cached_playlist() {
                 self.imp().restore_playlist_button.set_visible(true);
                 self.imp()
                     .restore_playlist_button
@@ -1103,6 +1728,12 @@ impl Window {
                 win.bind_property("playlist-selection", &row, "selection-mode")
                     .sync_create()
                     .build();
+                win.imp()
+                    .playlist_view
+                    .playlist_searchentry()
+                    .bind_property("text", &row, "search")
+                    .sync_create()
+                    .build();
 
                 list_item
                     .bind_property("item", &row, "song")
@@ -1129,6 +1760,40 @@ impl Window {
                     .property_expression("item")
                     .chain_property::<Song>("selected")
                     .bind(&row, "selected", gtk::Widget::NONE);
+                list_item
+                    .property_expression("item")
+                    .chain_property::<Song>("auto-added")
+                    .bind(&row, "auto-added", gtk::Widget::NONE);
+                list_item
+                    .property_expression("item")
+                    .chain_property::<Song>("skip-shuffle")
+                    .bind(&row, "skip-shuffle", gtk::Widget::NONE);
+                list_item
+                    .property_expression("item")
+                    .chain_property::<Song>("rating")
+                    .bind(&row, "rating", gtk::Widget::NONE);
+
+                row.rating_bar().connect_local(
+                    "rate",
+                    false,
+                    clone!(
+                        #[weak]
+                        win,
+                        #[weak]
+                        row,
+                        #[upgrade_or]
+                        None,
+                        move |args| {
+                            let rating = args[1].get::<u32>().unwrap();
+                            if let Some(song) = row.song() {
+                                glib::spawn_future_local(async move {
+                                    win.rate_songs(vec![song], rating).await;
+                                });
+                            }
+                            None
+                        }
+                    ),
+                );
             }
         ));
         imp.playlist_view
@@ -1143,7 +1808,8 @@ impl Window {
                 gtk::FilterListModel::new(Some(queue.model().clone()), Some(filter.clone()));
             let sorter = FuzzySorter::new();
             let sorter_model = gtk::SortListModel::new(Some(filter_model), Some(sorter.clone()));
-            let selection = gtk::NoSelection::new(Some(sorter_model.clone()));
+            let sections = AlbumSectionModel::new(Some(&sorter_model));
+            let selection = gtk::NoSelection::new(Some(sections.clone()));
             imp.playlist_view
                 .queue_view()
                 .set_model(Some(selection.upcast_ref::<gtk::SelectionModel>()));
@@ -1188,8 +1854,51 @@ impl Window {
                 }
             ));
 
+            imp.playlist_view
+                .history_view()
+                .bind_model(Some(queue.history()), |item| {
+                    let song = item.downcast_ref::<Song>().unwrap();
+                    glib::Object::builder::<QueueRow>()
+                        .property("song", song)
+                        .property("song-title", song.title())
+                        .property("song-artist", song.artist())
+                        .property("song-cover", song.cover_texture())
+                        .build()
+                        .upcast::<gtk::Widget>()
+                });
+            imp.playlist_view
+                .history_view()
+                .connect_row_activated(clone!(
+                    #[weak(rename_to = win)]
+                    self,
+                    move |_, row| {
+                        if let Some(player) = win.player() {
+                            let queue = player.queue();
+                            let song = queue
+                                .history()
+                                .item(row.index() as u32)
+                                .and_downcast::<Song>();
+                            if let Some(pos) = song.and_then(|s| queue.song_position(&s)) {
+                                player.skip_to(pos);
+                                player.play();
+                            }
+                        }
+                    }
+                ));
+            queue.history().connect_items_changed(clone!(
+                #[weak(rename_to = win)]
+                self,
+                move |history, _, _, _| {
+                    win.imp()
+                        .playlist_view
+                        .history_expander()
+                        .set_visible(history.n_items() > 0);
+                }
+            ));
+
             imp.playlist_filtermodel
-                .replace(Some(sorter_model.upcast::<gio::ListModel>()));
+                .replace(Some(sorter_model.clone().upcast::<gio::ListModel>()));
+            imp.playlist_sections.replace(Some(sections));
 
             imp.playlist_view
                 .playlist_searchentry()
@@ -1211,11 +1920,31 @@ impl Window {
                         {
                             adjustment.set_value(0.0);
                         }
+                        win.update_search_results();
                     }
                 ));
+            sorter_model.connect_items_changed(clone!(
+                #[weak(rename_to = win)]
+                self,
+                move |_, _, _, _| {
+                    win.update_search_results();
+                }
+            ));
         }
     }
 
+    fn update_search_results(&self) {
+        let imp = self.imp();
+        let searching = !imp.playlist_view.playlist_searchentry().text().is_empty();
+        let no_results = searching
+            && imp
+                .playlist_filtermodel
+                .borrow()
+                .as_ref()
+                .is_some_and(|model| model.n_items() == 0);
+        imp.playlist_view.set_no_results(no_results);
+    }
+
     fn setup_drop_target(&self) {
         let drop_target = gtk::DropTarget::builder()
             .name("file-drop-target")
@@ -1288,6 +2017,76 @@ impl Window {
             self.update_title(state.current_song().as_ref());
             self.update_style(state.current_song().as_ref());
         }
+        if self.lyrics_visible() {
+            self.update_lyrics();
+        }
+    }
+
+    fn lyrics_visible(&self) -> bool {
+        self.imp().lyrics_visible.get()
+    }
+
+    // The lyrics take the place of the cover
+    fn set_lyrics_visible(&self, visible: bool) {
+        let imp = self.imp();
+
+        if visible != imp.lyrics_visible.replace(visible) {
+            if visible {
+                imp.art_stack.set_visible_child_name("lyrics");
+                self.update_lyrics();
+            } else {
+                imp.art_stack.set_visible_child_name("cover");
+            }
+            self.notify("lyrics-visible");
+        }
+    }
+
+    // Looks for the lyrics of the current song in a thread, as it may
+    // involve asking a server
+    fn update_lyrics(&self) {
+        let lyrics_view = self.imp().lyrics_view.get();
+        let song = match self.player().and_then(|p| p.state().current_song()) {
+            Some(song) => song,
+            None => {
+                lyrics_view.set_lyrics(None);
+                return;
+            }
+        };
+
+        let settings = &self.imp().settings;
+        let server = settings
+            .boolean("lyrics-fetch")
+            .then(|| settings.string("lyrics-server").to_string())
+            .filter(|s| !s.is_empty());
+        let request = LyricsRequest::new(&song, server);
+
+        lyrics_view.set_loading();
+        glib::spawn_future_local(clone!(
+            #[weak(rename_to = win)]
+            self,
+            async move {
+                let lyrics = gio::spawn_blocking(move || find_lyrics(&request))
+                    .await
+                    .unwrap_or_default();
+
+                // The song may have changed while we were looking
+                let current = win.player().and_then(|p| p.state().current_song());
+                if current.is_some_and(|s| s.equals(&song)) {
+                    win.imp().lyrics_view.set_lyrics(lyrics);
+                    win.update_lyrics_position();
+                }
+            }
+        ));
+    }
+
+    fn update_lyrics_position(&self) {
+        if !self.lyrics_visible() {
+            return;
+        }
+        if let Some(player) = self.player() {
+            let position = player.state().position_ms();
+            self.imp().lyrics_view.set_position(position);
+        }
     }
 
     fn update_cover(&self) {
@@ -1482,14 +2281,139 @@ impl Window {
         self.add_files_to_queue(model.upcast_ref::<gio::ListModel>());
     }
 
-    pub fn remove_song(&self, song: &Song) {
+    fn setup_folder_watcher(&self) {
+        let (sender, receiver) = async_channel::unbounded();
+        self.imp()
+            .folder_watcher
+            .replace(Some(FolderWatcher::new(sender)));
+
+        let win = self.downgrade();
+        glib::spawn_future_local(async move {
+            while let Ok(changes) = receiver.recv().await {
+                match win.upgrade() {
+                    Some(win) => win.apply_folder_changes(changes),
+                    None => break,
+                }
+            }
+        });
+
+        self.imp().settings.connect_changed(
+            Some("music-folders"),
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |_, _| {
+                    debug!("GSettings:music-folders changed");
+                    this.update_folder_watcher();
+                }
+            ),
+        );
+        self.update_folder_watcher();
+    }
+
+    // Only the music folders that the user picked are watched; we do not
+    // want to keep an eye on the whole XDG music directory by default
+    fn update_folder_watcher(&self) {
+        if let Some(ref watcher) = *self.imp().folder_watcher.borrow() {
+            let folders: Vec<gio::File> = self
+                .imp()
+                .settings
+                .strv("music-folders")
+                .iter()
+                .map(|uri| gio::File::for_uri(uri))
+                .collect();
+            if folders.is_empty() {
+                watcher.stop();
+            } else {
+                watcher.watch(folders);
+            }
+        }
+    }
+
+    fn apply_folder_changes(&self, changes: FolderChanges) {
+        if let Some(library) = self.library() {
+            library.apply_folder_changes(&changes);
+        }
+
+        let player = match self.player() {
+            Some(player) => player,
+            None => return,
+        };
+        let queue = player.queue();
+
+        // Moved and renamed songs are still the same songs, so they keep
+        // their place in the queue and in the history
+        for (from, to) in &changes.moved {
+            for song in queue.songs_in(from) {
+                let file = song.file();
+                let new_file = if file.equal(from) {
+                    to.clone()
+                } else if let Some(path) = from.relative_path(&file) {
+                    to.resolve_relative_path(path)
+                } else {
+                    continue;
+                };
+                debug!("Song moved from '{}' to '{}'", file.uri(), new_file.uri());
+                queue.relocate_song(&song, &new_file);
+            }
+        }
+
+        let removed: Vec<Song> = changes
+            .removed
+            .iter()
+            .flat_map(|f| queue.songs_in(f))
+            .collect();
+        if !removed.is_empty() {
+            self.remove_songs(&removed);
+            let msg = ni18n_f(
+                // Translators: the `{}` must be left unmodified; it will
+                // be expanded to the number of songs removed from the
+                // playlist
+                "Removed {} song whose file was deleted",
+                "Removed {} songs whose files were deleted",
+                removed.len() as u32,
+                &[&removed.len().to_string()],
+            );
+            self.add_toast(msg);
+        }
+
+        if !changes.moved.is_empty() || !removed.is_empty() {
+            utils::store_playlist(queue);
+        }
+
+        if !changes.added.is_empty() && self.imp().settings.boolean("watch-add-to-queue") {
+            self.queue_songs(changes.added, LoadMode::Import);
+        }
+    }
+
+    pub fn remove_songs(&self, songs: &[Song]) {
         if let Some(p) = self.player() {
-            p.remove_song(song);
+            p.remove_songs(songs);
         }
         self.update_selected_count();
         self.update_playlist_time();
     }
 
+    // Adds the selected songs, or their artists or albums, to the list of
+    // things we never play in shuffled order
+    fn skip_selected(&self, kind: SkipKind) {
+        if let Some(player) = self.player() {
+            let values: Vec<String> = player
+                .queue()
+                .songs()
+                .iter()
+                .filter(|s| s.selected())
+                .flat_map(|s| kind.values_for(s))
+                .filter(|v| !v.trim().is_empty())
+                .collect();
+
+            if !values.is_empty() {
+                SkipList::add(kind, &values);
+                self.add_toast(i18n("Selected songs will be skipped in shuffle"));
+            }
+        }
+    }
+
     pub fn add_toast(&self, msg: String) {
         let toast = adw::Toast::new(&msg);
         self.imp().toast_overlay.add_toast(toast);
@@ -1503,6 +2427,205 @@ impl Window {
         self.imp().toast_overlay.add_toast(toast);
     }
 
+    // The selected songs, or the current one
+    fn selected_or_current_songs(&self) -> Vec<Song> {
+        let player = match self.player() {
+            Some(player) => player,
+            None => return vec![],
+        };
+
+        let mut songs: Vec<Song> = vec![];
+        if self.playlist_selection() {
+            songs = player
+                .queue()
+                .songs()
+                .into_iter()
+                .filter(|s| s.selected())
+                .collect();
+        }
+        if songs.is_empty() {
+            songs.extend(player.state().current_song());
+        }
+        songs
+    }
+
+    // Edits the selected songs, or the current one
+    fn edit_tags(&self) {
+        let songs = self.selected_or_current_songs();
+        if songs.is_empty() {
+            return;
+        }
+
+        let dialog = TagEditorDialog::new(&songs);
+        dialog.connect_local(
+            "save",
+            false,
+            clone!(
+                #[weak(rename_to = this)]
+                self,
+                #[upgrade_or]
+                None,
+                move |args| {
+                    let dialog = args[0].get::<TagEditorDialog>().unwrap();
+                    let changes = dialog.changes();
+                    let edits = dialog
+                        .songs()
+                        .into_iter()
+                        .map(|s| (s, changes.clone()))
+                        .collect();
+                    glib::spawn_future_local(async move {
+                        this.write_tags(edits, true).await;
+                    });
+                    None
+                }
+            ),
+        );
+        dialog.present(Some(self));
+    }
+
+    // Shows the first selected song, or the current one
+    fn show_song_properties(&self) {
+        let player = match self.player() {
+            Some(player) => player,
+            None => return,
+        };
+
+        let mut song = None;
+        if self.playlist_selection() {
+            song = player.queue().songs().into_iter().find(|s| s.selected());
+        }
+        if let Some(song) = song.or_else(|| player.state().current_song()) {
+            SongPropertiesDialog::new(&song).present(Some(self));
+        }
+    }
+
+    // Writing tags can take a while for large files, so we do it in a
+    // worker thread, and then read the tags of each song again
+    async fn write_tags(&self, edits: Vec<(Song, TagChanges)>, undoable: bool) {
+        if edits.is_empty() {
+            return;
+        }
+
+        // Reading the tags again is as slow as writing them, so it happens
+        // in the same worker thread
+        let jobs: Vec<(String, Option<String>, TagChanges)> = edits
+            .iter()
+            .map(|(song, changes)| (song.uri(), song.cover_uuid(), changes.clone()))
+            .collect();
+        let results = gio::spawn_blocking(move || {
+            jobs.into_iter()
+                .map(|(uri, cover_uuid, changes)| {
+                    let path = gio::File::for_uri(&uri)
+                        .path()
+                        .ok_or_else(|| "not a local file".to_string())?;
+                    let undo_changes = write_tags(&path, &changes)?;
+                    let cover_changed = changes.cover.is_some();
+                    let data = SongData::reload(&uri, cover_uuid.as_deref(), cover_changed);
+                    Ok((undo_changes, data))
+                })
+                .collect::<Vec<Result<_, String>>>()
+        })
+        .await
+        .unwrap_or_default();
+
+        let player = self.player();
+        let mut undo = vec![];
+        let mut n_failed = edits.len() - results.len();
+        for ((song, _), res) in edits.into_iter().zip(results) {
+            match res {
+                Ok((undo_changes, data)) => {
+                    if let Some(ref player) = player {
+                        player.queue().reload_song(&song, data);
+                        player.refresh_song(&song);
+                    } else {
+                        song.set_reloaded_data(data);
+                    }
+                    undo.push((song, undo_changes));
+                }
+                Err(e) => {
+                    warn!("Unable to write the tags of {}: {}", song.uri(), e);
+                    n_failed += 1;
+                }
+            }
+        }
+        self.update_title(player.and_then(|p| p.state().current_song()).as_ref());
+
+        // The library keeps songs of its own, which need the new tags too
+        if let Some(library) = self.library() {
+            let uris: Vec<String> = undo.iter().map(|(song, _)| song.uri()).collect();
+            library.reload_uris(&uris);
+        }
+
+        // The songs we could write can be restored, even if others failed
+        let can_undo = undoable && !undo.is_empty();
+        if can_undo {
+            self.imp().tags_undo.replace(undo);
+        }
+
+        let toast = if n_failed > 0 {
+            adw::Toast::new(&ni18n_f(
+                // Translators: the `{}` must be left unmodified;
+                // it will be expanded to the number of songs
+                "Unable to save the tags of {} song",
+                "Unable to save the tags of {} songs",
+                n_failed as u32,
+                &[&n_failed.to_string()],
+            ))
+        } else if undoable {
+            adw::Toast::new(&i18n("Tags saved"))
+        } else {
+            adw::Toast::new(&i18n("Tags restored"))
+        };
+        if can_undo {
+            toast.set_button_label(Some(&i18n("_Undo")));
+            toast.set_action_name(Some("win.undo-tags"));
+        }
+        self.imp().toast_overlay.add_toast(toast);
+    }
+
+    // Ratings go in the tags when we can write them; otherwise, we keep
+    // them ourselves, so that they are not lost
+    async fn rate_songs(&self, songs: Vec<Song>, rating: u32) {
+        if songs.is_empty() {
+            return;
+        }
+
+        let format = RatingTag::from(self.imp().settings.enum_("rating-tag"));
+        let paths: Vec<Option<PathBuf>> = songs.iter().map(|s| s.file().path()).collect();
+        let results = gio::spawn_blocking(move || {
+            paths
+                .into_iter()
+                .map(|path| match path {
+                    Some(path) => write_rating(&path, rating, format),
+                    None => Err("not a local file".to_string()),
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap_or_default();
+
+        let player = self.player();
+        for (i, song) in songs.iter().enumerate() {
+            match results.get(i) {
+                Some(Ok(())) => song.set_rating(rating, true),
+                Some(Err(e)) if format != RatingTag::None => {
+                    warn!("Unable to write the rating of {}: {}", song.uri(), e);
+                    song.set_rating(rating, false);
+                }
+                _ => song.set_rating(rating, false),
+            }
+            if let Some(ref player) = player {
+                player.refresh_song(song);
+            }
+        }
+
+        // Smart playlists and the library views pick songs by rating
+        if let Some(library) = self.library() {
+            let uris: Vec<String> = songs.iter().map(|s| s.uri()).collect();
+            library.reload_uris(&uris);
+        }
+    }
+
     fn copy_song(&self) {
         if let Some(player) = self.player() {
             let state = player.state();
@@ -1553,6 +2676,74 @@ impl Window {
         self.imp().replaygain_mode.get()
     }
 
+    pub fn set_shuffle_mode(&self, shuffle_mode: ShuffleMode) {
+        let imp = self.imp();
+
+        if shuffle_mode != imp.shuffle_mode.replace(shuffle_mode) {
+            if let Some(p) = self.player() {
+                p.queue().set_shuffle_mode(shuffle_mode);
+            }
+            imp.playback_control.set_shuffle_mode(shuffle_mode);
+            imp.settings
+                .set_enum("shuffle-mode", shuffle_mode.into())
+                .expect("Unable to store setting");
+
+            self.queue_save_session();
+            self.notify("shuffle-mode");
+        }
+    }
+
+    pub fn shuffle_mode(&self) -> ShuffleMode {
+        self.imp().shuffle_mode.get()
+    }
+
+    pub fn set_sort_key(&self, sort_key: SortKey) {
+        let imp = self.imp();
+
+        if sort_key != imp.sort_key.replace(sort_key) {
+            imp.settings
+                .set_enum("sort-key", sort_key.into())
+                .expect("Unable to store setting");
+
+            self.sort_queue();
+            self.notify("sort-key");
+        }
+    }
+
+    pub fn sort_key(&self) -> SortKey {
+        self.imp().sort_key.get()
+    }
+
+    pub fn set_sort_descending(&self, descending: bool) {
+        let imp = self.imp();
+
+        if descending != imp.sort_descending.replace(descending) {
+            imp.settings
+                .set_boolean("sort-descending", descending)
+                .expect("Unable to store setting");
+
+            self.sort_queue();
+            self.notify("sort-descending");
+        }
+    }
+
+    pub fn sort_descending(&self) -> bool {
+        self.imp().sort_descending.get()
+    }
+
+    fn sort_queue(&self) {
+        if let Some(player) = self.player() {
+            let queue = player.queue();
+            if queue.is_empty() {
+                return;
+            }
+
+            queue.sort(self.sort_key(), self.sort_descending());
+            utils::store_playlist(queue);
+            self.queue_save_session();
+        }
+    }
+
     pub fn set_song_time(&self, elapsed: Option<u64>, remaining: Option<u64>) {
         if let Some(elapsed) = elapsed {
             self.imp()